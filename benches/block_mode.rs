@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use aes_rustlang::{aes128, block_cipher_mode};
+use aes_rustlang::block_cipher::{AesCipher, Backend, ReferenceAes};
+use aes_rustlang::ttable::TTableAes;
+use aes_rustlang::bitsliced::BitslicedAes;
+use aes_rustlang::block_mode::{BlockMode, Cbc, Ctr, Ecb};
+
+// bytes of text in each iteration
+const TEXT_LENGTH : usize = 4096;
+
+const KEY : [u8; 16] = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+const IV : [u8; 16] = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+
+// The hex String functions of block_cipher_mode against the in-place block modes.
+// Both run on the backend of Backend::default(), which the hex String functions use, so that only the allocations differ
+fn bench_block_mode( c : &mut Criterion ) {
+    let aes = AesCipher::new( &KEY, Backend::default() ).expect( "Failed to expand key" );
+    let key = hex::encode( KEY );
+    let iv = hex::encode( IV );
+    let text = hex::encode( vec![0x5a; TEXT_LENGTH] );
+    let mut data = vec![0x5a; TEXT_LENGTH];
+
+    let mut group = c.benchmark_group( "ecb_encrypt" );
+    group.throughput( Throughput::Bytes( TEXT_LENGTH as u64 ) );
+    group.bench_function( "string", | b | b.iter( || block_cipher_mode::encrypt_ecb_blocks( text.clone(), &key, aes128::encrypt ) ) );
+    group.bench_function( "in_place", | b | b.iter( || Ecb::new( &aes ).encrypt_blocks( &mut data ) ) );
+    group.finish();
+
+    let mut group = c.benchmark_group( "cbc_encrypt" );
+    group.throughput( Throughput::Bytes( TEXT_LENGTH as u64 ) );
+    group.bench_function( "string", | b | b.iter( || block_cipher_mode::encrypt_cbc_blocks( text.clone(), &key, iv.clone(), aes128::encrypt ) ) );
+    group.bench_function( "in_place", | b | b.iter( || Cbc::new( &aes, IV ).encrypt_blocks( &mut data ) ) );
+    group.finish();
+
+    let mut group = c.benchmark_group( "ctr_encrypt" );
+    group.throughput( Throughput::Bytes( TEXT_LENGTH as u64 ) );
+    group.bench_function( "string", | b | b.iter( || block_cipher_mode::encrypt_ctr_mode( text.clone(), key.clone(), iv.clone(), aes128::encrypt ) ) );
+    group.bench_function( "in_place", | b | b.iter( || Ctr::new( &aes, IV ).encrypt_blocks( &mut data ) ) );
+    group.finish();
+}
+
+// The reference round functions against the other backends in CTR mode
+fn bench_backend( c : &mut Criterion ) {
+    let reference = ReferenceAes::new( &KEY ).expect( "Failed to expand key" );
+    let ttable = TTableAes::new( &KEY ).expect( "Failed to expand key" );
+    let bitsliced = BitslicedAes::new( &KEY ).expect( "Failed to expand key" );
+    let mut data = vec![0x5a; TEXT_LENGTH];
+
+    let mut group = c.benchmark_group( "backend_ctr_encrypt" );
+    group.throughput( Throughput::Bytes( TEXT_LENGTH as u64 ) );
+    group.bench_function( "reference", | b | b.iter( || Ctr::new( &reference, IV ).encrypt_blocks( &mut data ) ) );
+    group.bench_function( "ttable", | b | b.iter( || Ctr::new( &ttable, IV ).encrypt_blocks( &mut data ) ) );
+    group.bench_function( "bitsliced", | b | b.iter( || Ctr::new( &bitsliced, IV ).encrypt_blocks( &mut data ) ) );
+
+    // the hardware instructions, or the fallback without them
+    let hardware = AesCipher::new( &KEY, Backend::AesNi ).expect( "Failed to expand key" );
+    group.bench_function( format!( "{:?}", hardware.backend() ).to_lowercase(), | b | b.iter( || Ctr::new( &hardware, IV ).encrypt_blocks( &mut data ) ) );
+    group.finish();
+}
+
+criterion_group!( benches, bench_block_mode, bench_backend );
+criterion_main!( benches );
\ No newline at end of file
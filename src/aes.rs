@@ -0,0 +1,112 @@
+use core::error;
+use core::fmt;
+#[cfg(feature = "std")]
+use hex;
+use crate::aes_common::KeySize;
+#[cfg(feature = "std")]
+use crate::zeroize::Zeroizing;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyError {
+    InvalidHex,
+    // key length in bytes
+    InvalidLength( usize ),
+    KeySizeMismatch { key_size : KeySize, key_length : usize }
+}
+
+impl fmt::Display for KeyError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            KeyError::InvalidHex => write!( f, "key is not hex" ),
+            KeyError::InvalidLength( key_length ) => write!( f, "key must be 16, 24 or 32 bytes, but it is {} bytes", key_length ),
+            KeyError::KeySizeMismatch { key_size, key_length } => {
+                write!( f, "{} needs a {}-byte key, but the key is {} bytes", key_size, key_size.key_length(), key_length )
+            }
+        }
+    }
+}
+
+impl error::Error for KeyError {}
+
+// AES with the key size taken from the key
+#[cfg(feature = "std")]
+pub struct Aes {
+    key : Zeroizing<String>,
+    key_size : KeySize
+}
+
+#[cfg(feature = "std")]
+impl Aes {
+    pub fn new( key : String ) -> Result<Self, KeyError> {
+        let key_length = get_key_length( &key )?;
+        let key_size = KeySize::from_key_length( key_length ).ok_or( KeyError::InvalidLength( key_length ) )?;
+
+        Ok( Aes { key : Zeroizing::new( key ), key_size } )
+    }
+
+    // Fails when the key does not have the length of key_size
+    pub fn with_key_size( key : String, key_size : KeySize ) -> Result<Self, KeyError> {
+        let key_length = get_key_length( &key )?;
+
+        if key_length != key_size.key_length() {
+            return Err( KeyError::KeySizeMismatch { key_size, key_length } );
+        }
+
+        Ok( Aes { key : Zeroizing::new( key ), key_size } )
+    }
+
+    pub fn key_size( &self ) -> KeySize {
+        self.key_size
+    }
+
+    pub fn encrypt( &self, text : String ) -> String {
+        self.key_size.cipher_func()( text, &self.key )
+    }
+
+    pub fn decrypt( &self, text : String ) -> String {
+        self.key_size.inv_cipher_func()( text, &self.key )
+    }
+}
+
+#[cfg(feature = "std")]
+fn get_key_length( key : &str ) -> Result<usize, KeyError> {
+    hex::decode( key ).map( | key_u8 | key_u8.len() ).map_err( | _ | KeyError::InvalidHex )
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_aes_new() {
+
+    //FIPS 197 Appendix C Example Vectors
+    let text = "00112233445566778899aabbccddeeff";
+
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let key_size = [KeySize::Aes128, KeySize::Aes192, KeySize::Aes256];
+
+    let expect = ["69c4e0d86a7b0430d8cdb78070b4c55a",
+                  "dda97ca4864cdfe06eaf70a0ec0d7191",
+                  "8ea2b7ca516745bfeafc49904b496089"];
+
+    for i in 0..key.len() {
+        let aes = Aes::new( key[i].to_string() ).unwrap();
+
+        assert_eq!( aes.key_size(), key_size[i] );
+        assert_eq!( aes.encrypt( text.to_string() ), expect[i] );
+        assert_eq!( aes.decrypt( expect[i].to_string() ), text );
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_aes_key_error() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+
+    assert_eq!( Aes::new( "00010203".to_string() ).err(), Some( KeyError::InvalidLength( 4 ) ) );
+    assert_eq!( Aes::new( "0g".to_string() ).err(), Some( KeyError::InvalidHex ) );
+    assert_eq!( Aes::with_key_size( key.to_string(), KeySize::Aes256 ).err(),
+                Some( KeyError::KeySizeMismatch { key_size : KeySize::Aes256, key_length : 16 } ) );
+    assert_eq!( Aes::with_key_size( key.to_string(), KeySize::Aes128 ).unwrap().key_size(), KeySize::Aes128 );
+}
\ No newline at end of file
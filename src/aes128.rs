@@ -1,30 +1,34 @@
+use crate::aes_common::KeySize;
+use crate::block_cipher;
+#[cfg(test)]
 use hex;
+#[cfg(test)]
 use crate::aes_common;
+#[cfg(test)]
+use crate::zeroize::Zeroizing;
 
-const ROUND_NUM : usize = 10;
+pub const ROUND_NUM : usize = 10;
+#[cfg(test)]
 const KEY_LENGTH : usize = 4;
 
+#[cfg(test)]
 const R_CON : [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1B, 0x36];
 
-pub fn encrypt( text : String, key : String ) -> String
+// One block through the backend of block_cipher::Backend::default()
+pub fn encrypt( text : String, key : &str ) -> String
 {
-    let round_key = key_expansion( key );
-    let result = cipher( text, round_key );
-
-    result
+    block_cipher::encrypt_text( text, key, KeySize::Aes128 )
 }
 
-pub fn decrypt( text : String, key : String ) -> String
+pub fn decrypt( text : String, key : &str ) -> String
 {
-    let round_key = key_expansion( key );
-    let result = inv_cipher( text, round_key );
-
-    result
+    block_cipher::decrypt_text( text, key, KeySize::Aes128 )
 }
 
-fn cipher( plain_text : String, round_key : [u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )] ) -> String {
+// The round functions of aes_common on hex text, which the tests check against the intermediate values of FIPS 197
+#[cfg(test)]
+fn cipher( plain_text : String, round_key : &[u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )] ) -> String {
     let mut state : [[u8; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE] = aes_common::text_to_state( plain_text );
-    let encrypted_text : String;
 
     state = add_round_key( state, round_key, 0 );
 
@@ -39,35 +43,34 @@ fn cipher( plain_text : String, round_key : [u32; aes_common::BLOCK_SIZE * ( ROU
     state = aes_common::shift_rows( state );
     state = add_round_key( state, round_key, ROUND_NUM );
 
-    encrypted_text = aes_common::state_to_text( state );
-
-    return encrypted_text;
+    aes_common::state_to_text( state )
 }
 
-fn inv_cipher( plain_text : String, round_key : [u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )] ) -> String {
+// Equivalent inverse cipher(FIPS 197 5.3.5) with the round keys of inv_key_expansion.
+// The rounds are in the same order as the cipher, so that they can be merged into tables like the encryption
+#[cfg(test)]
+fn inv_cipher( plain_text : String, round_key : &[u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )] ) -> String {
     let mut state : [[u8; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE] = aes_common::text_to_state( plain_text );
-    let decrypted_text : String;
 
     state = add_round_key( state, round_key, ROUND_NUM );
 
     for round in ( 1..ROUND_NUM ).rev() {
-        state = aes_common::inv_shift_rows( state );
         state = aes_common::inv_sub_bytes( state );
-        state = add_round_key( state, round_key, round );
+        state = aes_common::inv_shift_rows( state );
         state = aes_common::inv_mix_columns( state );
+        state = add_round_key( state, round_key, round );
     }
 
-    state = aes_common::inv_shift_rows( state );
     state = aes_common::inv_sub_bytes( state );
+    state = aes_common::inv_shift_rows( state );
     state = add_round_key( state, round_key, 0 );
 
-    decrypted_text = aes_common::state_to_text( state );
-
-    return decrypted_text;
+    aes_common::state_to_text( state )
 }
 
+#[cfg(test)]
 fn add_round_key( input_state : [[u8; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE],
-                    round_key : [u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )], round : usize ) -> [[u8; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE] {
+                    round_key : &[u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )], round : usize ) -> [[u8; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE] {
     let mut output_state : [[u8; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE] = [[0; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE];
 
     for col in 0..aes_common::WORD_IN_BYTES_NUM {
@@ -77,15 +80,19 @@ fn add_round_key( input_state : [[u8; aes_common::WORD_IN_BYTES_NUM]; aes_common
         output_state[3][col] = input_state[3][col] ^ ( ( round_key[round * aes_common::BLOCK_SIZE + col] & 0x000000FF ) as u8 );
     }
 
-    return output_state;
+    output_state
 }
 
-fn key_expansion( key : String ) ->  [u32; aes_common::BLOCK_SIZE*( ROUND_NUM + 1 )] {
-    let mut round_key : [u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )] = [0; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )];
+#[cfg(test)]
+fn key_expansion( key : &str ) -> Zeroizing<[u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )]> {
+    let mut round_key = Zeroizing::new( [0u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )] );
     let mut i : usize = 0;
     let mut temp : u32;
 
-    let key_u8 = hex::decode( key ).expect( "Failed to convert key in key_expansion" );
+    let key_u8 = Zeroizing::new( hex::decode( key ).expect( "Failed to convert key in key_expansion" ) );
+
+    assert!( key_u8.len() == KEY_LENGTH * aes_common::WORD_IN_BYTES_NUM,
+             "AES-128 needs a {}-byte key, but the key is {} bytes", KEY_LENGTH * aes_common::WORD_IN_BYTES_NUM, key_u8.len() );
 
     while i < KEY_LENGTH {
         round_key[i] = ( key_u8[i * 4] as u32 ) << 24 |
@@ -100,7 +107,7 @@ fn key_expansion( key : String ) ->  [u32; aes_common::BLOCK_SIZE*( ROUND_NUM +
     while i < aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 ) {
         temp = round_key[i - 1];
 
-        if i % KEY_LENGTH == 0 {
+        if i.is_multiple_of( KEY_LENGTH ) {
             temp = aes_common::sub_word( aes_common::rot_word( temp ) ) ^ ( ( R_CON[i / KEY_LENGTH - 1] as u32 ) << 24 );
         }
 
@@ -108,7 +115,20 @@ fn key_expansion( key : String ) ->  [u32; aes_common::BLOCK_SIZE*( ROUND_NUM +
         i += 1;
     }
 
-    return round_key;
+    round_key
+}
+
+// Decryption key schedule of the equivalent inverse cipher, with InvMixColumns applied to the round keys
+// of every round but the first and the last
+#[cfg(test)]
+fn inv_key_expansion( key : &str ) -> Zeroizing<[u32; aes_common::BLOCK_SIZE * ( ROUND_NUM + 1 )]> {
+    let mut round_key = key_expansion( key );
+
+    for i in aes_common::BLOCK_SIZE..( aes_common::BLOCK_SIZE * ROUND_NUM ) {
+        round_key[i] = aes_common::inv_mix_column_word( round_key[i] );
+    }
+
+    round_key
 }
 
 #[test]
@@ -168,7 +188,7 @@ fn test_add_round_key() {
 
     for i in 0..expect.len() {
         let actual_result = aes_common::state_to_text( 
-            add_round_key( aes_common::text_to_state( input_data[i].to_string() ), round_key_u32, round[i] ) );
+            add_round_key( aes_common::text_to_state( input_data[i].to_string() ), &round_key_u32, round[i] ) );
 
         assert_eq!( actual_result, expect[i] );
     }    
@@ -189,9 +209,33 @@ fn test_key_expansion() {
                               0xac77_66f3, 0x19fa_dc21, 0x28d1_2941, 0x575c_006e, 0xd014_f9a8, 0xc9ee_2589,
                               0xe13f_0cc8, 0xb663_0ca6];
 
-    let actual_result = key_expansion( key.to_string() );
+    let actual_result = key_expansion( key );
 
     for i in 0..expect.len() {
         assert_eq!( actual_result[i], expect[i] );
     }
+}
+
+#[test]
+fn test_cipher() {
+
+    //FIPS 197 p35 C.1 AES-128 (Nk=4, Nr=10)
+    let text = "00112233445566778899aabbccddeeff";
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let expect = "69c4e0d86a7b0430d8cdb78070b4c55a";
+
+    assert_eq!( cipher( text.to_string(), &key_expansion( key ) ), expect );
+    assert_eq!( encrypt( text.to_string(), key ), expect );
+}
+
+#[test]
+fn test_inv_cipher() {
+
+    //FIPS 197 p35 C.1 AES-128 (Nk=4, Nr=10), EQUIVALENT INVERSE CIPHER
+    let text = "69c4e0d86a7b0430d8cdb78070b4c55a";
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let expect = "00112233445566778899aabbccddeeff";
+
+    assert_eq!( inv_cipher( text.to_string(), &inv_key_expansion( key ) ), expect );
+    assert_eq!( decrypt( text.to_string(), key ), expect );
 }
\ No newline at end of file
@@ -1,13 +1,93 @@
+use core::fmt;
+#[cfg(feature = "std")]
 use hex;
+#[cfg(feature = "std")]
+use crate::{aes128, aes192, aes256};
 
 pub const WORD_IN_BYTES_NUM : usize = 4;
 pub const BLOCK_SIZE : usize = 4;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeySize {
+    Aes128,
+    Aes192,
+    Aes256
+}
+
+impl fmt::Display for KeySize {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        let name = match self {
+            KeySize::Aes128 => "AES-128",
+            KeySize::Aes192 => "AES-192",
+            KeySize::Aes256 => "AES-256"
+        };
+
+        write!( f, "{}", name )
+    }
+}
+
+impl KeySize {
+    // key length in bytes
+    pub fn key_length( &self ) -> usize {
+        match self {
+            KeySize::Aes128 => 16,
+            KeySize::Aes192 => 24,
+            KeySize::Aes256 => 32
+        }
+    }
+
+    // number of rounds(Nr)
+    pub fn round_num( &self ) -> usize {
+        match self {
+            KeySize::Aes128 => 10,
+            KeySize::Aes192 => 12,
+            KeySize::Aes256 => 14
+        }
+    }
+
+    pub fn from_key_length( key_length : usize ) -> Option<KeySize> {
+        match key_length {
+            16 => Some( KeySize::Aes128 ),
+            24 => Some( KeySize::Aes192 ),
+            32 => Some( KeySize::Aes256 ),
+            _ => None
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn cipher_func( &self ) -> fn( String, &str ) -> String {
+        match self {
+            KeySize::Aes128 => aes128::encrypt,
+            KeySize::Aes192 => aes192::encrypt,
+            KeySize::Aes256 => aes256::encrypt
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn inv_cipher_func( &self ) -> fn( String, &str ) -> String {
+        match self {
+            KeySize::Aes128 => aes128::decrypt,
+            KeySize::Aes192 => aes192::decrypt,
+            KeySize::Aes256 => aes256::decrypt
+        }
+    }
+}
+
+// Random bytes in hex from the operating system random source, for keys and initialization vectors
+#[cfg(feature = "std")]
+pub fn generate_random_text( byte_num : usize ) -> String {
+    let mut random_bytes = vec![0; byte_num];
+
+    getrandom::getrandom( &mut random_bytes ).expect( "Failed to read the OS random source" );
+
+    hex::encode( random_bytes )
+}
+
 // Irreducible polynomial(0x11B) to 8bit
 // When XOR calculation, MSB translate to 0. So 0x11B can translate to 0x1B in 8bit valiable calculation
 const IRR_POLYNOMIAL : u8 = 0x1B;
 
-const S_BOX : [[u8; 16]; 16] = [
+pub(crate) const S_BOX : [[u8; 16]; 16] = [
     [0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76],
     [0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0],
     [0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15],
@@ -26,7 +106,7 @@ const S_BOX : [[u8; 16]; 16] = [
     [0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16]
 ];
 
-const INV_S_BOX : [[u8; 16]; 16] = [
+pub(crate) const INV_S_BOX : [[u8; 16]; 16] = [
     [0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb], 
     [0x7c, 0xe3, 0x39, 0x82, 0x9b, 0x2f, 0xff, 0x87, 0x34, 0x8e, 0x43, 0x44, 0xc4, 0xde, 0xe9, 0xcb], 
     [0x54, 0x7b, 0x94, 0x32, 0xa6, 0xc2, 0x23, 0x3d, 0xee, 0x4c, 0x95, 0x0b, 0x42, 0xfa, 0xc3, 0x4e], 
@@ -45,6 +125,7 @@ const INV_S_BOX : [[u8; 16]; 16] = [
     [0x17, 0x2b, 0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d]
 ];
 
+#[cfg(feature = "std")]
 pub fn text_to_state( text : String ) -> [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] {
     let mut state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] = [[0; WORD_IN_BYTES_NUM]; BLOCK_SIZE];
 
@@ -59,9 +140,10 @@ pub fn text_to_state( text : String ) -> [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] {
         }
     }
 
-    return state;
+    state
 }
 
+#[cfg(feature = "std")]
 pub fn state_to_text( state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> String {
     let mut hex_data : Vec<u8> = vec![0; BLOCK_SIZE * WORD_IN_BYTES_NUM];
 
@@ -71,9 +153,29 @@ pub fn state_to_text( state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> String
         }
     }
 
-    let text = hex::encode( hex_data );
+    hex::encode( hex_data )
+}
+
+// The operations of a round of the cipher(FIPS 197 5.1), for the instrumented ciphers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    SubBytes,
+    ShiftRows,
+    MixColumns,
+    AddRoundKey
+}
 
-    return text;
+// Round 0 has only AddRoundKey, and the last round has no MixColumns
+pub(crate) fn operations( round : usize, round_num : usize ) -> &'static [Operation] {
+    if round == 0 {
+        &[Operation::AddRoundKey]
+    }
+    else if round == round_num {
+        &[Operation::SubBytes, Operation::ShiftRows, Operation::AddRoundKey]
+    }
+    else {
+        &[Operation::SubBytes, Operation::ShiftRows, Operation::MixColumns, Operation::AddRoundKey]
+    }
 }
 
 pub fn sub_bytes( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] {
@@ -88,9 +190,10 @@ pub fn sub_bytes( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u8;
         }
     }    
 
-    return output_state;
+    output_state
 }
 
+#[allow(clippy::needless_range_loop)]
 pub fn shift_rows( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] {
     let mut output_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] = [[0; WORD_IN_BYTES_NUM]; BLOCK_SIZE];
 
@@ -101,7 +204,7 @@ pub fn shift_rows( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u8
         }
     }
 
-    return output_state;
+    output_state
 }
 
 pub fn mix_columns( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] {
@@ -121,7 +224,7 @@ pub fn mix_columns( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u
                                 input_state[2][col] ^ multiplication( input_state[3][col], 2 );
     }
 
-    return output_state;
+    output_state
 }
 
 pub fn inv_sub_bytes( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] {
@@ -136,9 +239,10 @@ pub fn inv_sub_bytes( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [
         }
     }    
 
-    return output_state;
+    output_state
 }
 
+#[allow(clippy::needless_range_loop)]
 pub fn inv_shift_rows( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] {
     let mut output_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] = [[0; WORD_IN_BYTES_NUM]; BLOCK_SIZE];
 
@@ -149,7 +253,7 @@ pub fn inv_shift_rows( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) ->
         }
     }
 
-    return output_state;
+    output_state
 }
 
 pub fn inv_mix_columns( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) -> [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] {
@@ -169,32 +273,40 @@ pub fn inv_mix_columns( input_state : [[u8; WORD_IN_BYTES_NUM]; BLOCK_SIZE] ) ->
                                 multiplication( input_state[2][col], 0x09 ) ^ multiplication( input_state[3][col], 0x0e );
     }
 
-    return output_state;
+    output_state
+}
+
+// InvMixColumns of a single column word. The equivalent inverse cipher(FIPS 197 5.3.5) applies it to the round keys
+pub fn inv_mix_column_word( input : u32 ) -> u32 {
+    let column = input.to_be_bytes();
+    let mut output : [u8; WORD_IN_BYTES_NUM] = [0; WORD_IN_BYTES_NUM];
+
+    output[0] = multiplication( column[0], 0x0e ) ^ multiplication( column[1], 0x0b ) ^ multiplication( column[2], 0x0d ) ^ multiplication( column[3], 0x09 );
+    output[1] = multiplication( column[0], 0x09 ) ^ multiplication( column[1], 0x0e ) ^ multiplication( column[2], 0x0b ) ^ multiplication( column[3], 0x0d );
+    output[2] = multiplication( column[0], 0x0d ) ^ multiplication( column[1], 0x09 ) ^ multiplication( column[2], 0x0e ) ^ multiplication( column[3], 0x0b );
+    output[3] = multiplication( column[0], 0x0b ) ^ multiplication( column[1], 0x0d ) ^ multiplication( column[2], 0x09 ) ^ multiplication( column[3], 0x0e );
+
+    u32::from_be_bytes( output )
 }
 
-fn multiplication( multiplicand : u8, multiplier : u8 ) -> u8 {
+// Multiplication in GF(2^8) without a branch or a table lookup on either operand,
+// since the key schedule of the constant-time backends runs InvMixColumns on secret round keys
+pub(crate) fn multiplication( multiplicand : u8, multiplier : u8 ) -> u8 {
     let mut xtime_val : u8 = multiplicand;
     let mut result : u8 = 0;
 
-    //check LSB
-    if multiplier & 0x01 != 0 {
-        result = multiplicand;
+    for bit_pos in 0..8 {
+        // all ones when the bit of the multiplier is set
+        result ^= xtime_val & 0u8.wrapping_sub( ( multiplier >> bit_pos ) & 1 );
+        xtime_val = xtime( xtime_val );
     }
 
-    for bit_pos in 1..7 {
-        if xtime_val & 0x80 != 0 {
-            xtime_val = ( ( xtime_val & 0x7F ) << 1 ) ^ IRR_POLYNOMIAL;
-        }
-        else {
-            xtime_val <<= 1;
-        }
-        
-        if multiplier & ( 1 << bit_pos ) != 0 {
-            result ^= xtime_val;
-        }
-    }
-    
-    return result;
+    result
+}
+
+// xtime(FIPS 197 4.2.1), reducing by the polynomial when the MSB is set
+fn xtime( input : u8 ) -> u8 {
+    ( input << 1 ) ^ ( IRR_POLYNOMIAL & 0u8.wrapping_sub( input >> 7 ) )
 }
 
 pub fn sub_word( input : u32 ) -> u32 {
@@ -208,15 +320,16 @@ pub fn sub_word( input : u32 ) -> u32 {
         output ^= ( S_BOX[x][y] as u32 ) << ( ( 3 - i ) * 8 );
     }
 
-    return output;
+    output
 }
 
 pub fn rot_word( input : u32 ) -> u32 {
-    let output : u32 = ( input << 8 ) | ( input >> 24 );
+    let output : u32 = input.rotate_left( 8 );
 
-    return output;
+    output
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_sub_bytes() {
 
@@ -249,6 +362,7 @@ fn test_sub_bytes() {
     }
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_shift_rows() {
 
@@ -281,6 +395,7 @@ fn test_shift_rows() {
     }
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_mix_columns() {
 
@@ -311,6 +426,7 @@ fn test_mix_columns() {
     }
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_inv_sub_bytes() {
 
@@ -343,6 +459,7 @@ fn test_inv_sub_bytes() {
     }
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_inv_shift_rows() {
 
@@ -375,6 +492,7 @@ fn test_inv_shift_rows() {
     }
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_inv_mix_columns() {
 
@@ -403,4 +521,30 @@ fn test_inv_mix_columns() {
         let actual_result = state_to_text( inv_mix_columns( text_to_state( input_data[i].to_string() ) ) );
         assert_eq!( actual_result, expect[i] );
     }
+}
+
+#[test]
+fn test_inv_mix_column_word() {
+
+    //FIPS 197 p37 C.1 EQUIVALENT INVERSE CIPHER, round[ 1].ik_sch from the round key of round 9
+    let input_data = [0x5499_32d1, 0xf085_5768, 0x1093_ed9c, 0xbe2c_974e];
+    let expect = [0x13aa_29be, 0x9c8f_aff6, 0xf770_f580, 0x00f7_bf03];
+
+    for i in 0..expect.len() {
+        assert_eq!( inv_mix_column_word( input_data[i] ), expect[i] );
+    }
+}
+
+#[test]
+fn test_multiplication() {
+
+    //FIPS 197 4.2 {57} * {83} = {c1}, 4.2.1 {57} * {13} = {fe}
+    assert_eq!( multiplication( 0x57, 0x83 ), 0xc1 );
+    assert_eq!( multiplication( 0x57, 0x13 ), 0xfe );
+
+    // the inverses of FIPS 197 4.2, and 1 and 0
+    assert_eq!( multiplication( 0x53, 0xca ), 0x01 );
+    assert_eq!( multiplication( 0xca, 0x53 ), 0x01 );
+    assert_eq!( multiplication( 0xff, 0x01 ), 0xff );
+    assert_eq!( multiplication( 0xff, 0x00 ), 0x00 );
 }
\ No newline at end of file
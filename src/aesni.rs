@@ -0,0 +1,233 @@
+use std::arch::x86_64::*;
+use std::ptr;
+use std::sync::atomic::{self, Ordering};
+use crate::aes::KeyError;
+use crate::block_cipher::{BlockCipher, RoundKeys, BLOCK_LENGTH};
+use crate::zeroize::Zeroize;
+
+#[cfg(test)]
+use hex;
+#[cfg(test)]
+use crate::block_cipher::ReferenceAes;
+
+// blocks in flight at once, to hide the latency of aesenc and aesdec
+pub const PARALLEL_BLOCK_NUM : usize = 8;
+
+// round keys of AES-256, the most of all key sizes
+const MAX_ROUND_NUM : usize = 14;
+
+// AES with the AES-NI instructions of x86_64.
+// Only constructed after is_available, so every unsafe call below has the instructions it needs
+#[derive(Clone)]
+pub struct AesNiAes {
+    encrypt_keys : [__m128i; MAX_ROUND_NUM + 1],
+    // round keys of the equivalent inverse cipher(FIPS 197 5.3.5) for aesdec
+    decrypt_keys : [__m128i; MAX_ROUND_NUM + 1],
+    round_num : usize
+}
+
+pub fn is_available() -> bool {
+    is_x86_feature_detected!( "aes" ) && is_x86_feature_detected!( "sse2" )
+}
+
+impl AesNiAes {
+    // Panics if the CPU has no AES-NI. Check is_available first, or use AesCipher which falls back to software
+    pub fn new( key : &[u8] ) -> Result<Self, KeyError> {
+        assert!( is_available(), "AES-NI is not available" );
+
+        let expanded_keys = RoundKeys::with_sub_word( key, sub_word )?;
+        let round_num = expanded_keys.round_num();
+
+        // safe, since AES-NI is available
+        let ( encrypt_keys, decrypt_keys ) = unsafe { load_round_keys( &expanded_keys ) };
+
+        Ok( AesNiAes { encrypt_keys, decrypt_keys, round_num } )
+    }
+}
+
+impl Drop for AesNiAes {
+    fn drop( &mut self ) {
+        for round_key in self.encrypt_keys.iter_mut().chain( self.decrypt_keys.iter_mut() ) {
+            // safe, since SSE2 is part of x86_64
+            unsafe { ptr::write_volatile( round_key, _mm_setzero_si128() ) };
+        }
+
+        atomic::compiler_fence( Ordering::SeqCst );
+    }
+}
+
+impl BlockCipher for AesNiAes {
+    fn encrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        self.encrypt_parallel( std::slice::from_mut( block ) );
+    }
+
+    fn decrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        self.decrypt_parallel( std::slice::from_mut( block ) );
+    }
+
+    fn encrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        for chunk in blocks.chunks_mut( PARALLEL_BLOCK_NUM ) {
+            // safe, since AES-NI is available
+            unsafe { encrypt_chunk( &self.encrypt_keys[..=self.round_num], chunk ) };
+        }
+    }
+
+    fn decrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        for chunk in blocks.chunks_mut( PARALLEL_BLOCK_NUM ) {
+            // safe, since AES-NI is available
+            unsafe { decrypt_chunk( &self.decrypt_keys[..=self.round_num], chunk ) };
+        }
+    }
+}
+
+// SubWord of the key expansion by aeskeygenassist, which substitutes the second word of its input into the first
+fn sub_word( input : u32 ) -> u32 {
+    // safe, since only AesNiAes::new calls it after is_available
+    unsafe { sub_word_aesni( input ) }
+}
+
+#[target_feature(enable = "aes,sse2")]
+unsafe fn sub_word_aesni( input : u32 ) -> u32 {
+    let assisted = _mm_aeskeygenassist_si128( _mm_set_epi32( 0, 0, input as i32, 0 ), 0x00 );
+
+    _mm_cvtsi128_si32( assisted ) as u32
+}
+
+#[target_feature(enable = "aes,sse2")]
+unsafe fn load_round_keys( expanded_keys : &RoundKeys ) -> ( [__m128i; MAX_ROUND_NUM + 1], [__m128i; MAX_ROUND_NUM + 1] ) {
+    let round_num = expanded_keys.round_num();
+    let mut encrypt_keys = [_mm_setzero_si128(); MAX_ROUND_NUM + 1];
+    let mut decrypt_keys = [_mm_setzero_si128(); MAX_ROUND_NUM + 1];
+
+    for ( round, encrypt_key ) in encrypt_keys.iter_mut().enumerate().take( round_num + 1 ) {
+        let mut bytes = [0; BLOCK_LENGTH];
+
+        for ( col, word ) in expanded_keys.round_key( round ).iter().enumerate() {
+            bytes[( col * 4 )..( col * 4 + 4 )].copy_from_slice( &word.to_be_bytes() );
+        }
+
+        *encrypt_key = _mm_loadu_si128( bytes.as_ptr() as *const __m128i );
+        bytes.zeroize();
+    }
+
+    // InvMixColumns applied to the round keys except the first and the last
+    decrypt_keys[0] = encrypt_keys[round_num];
+
+    for round in 1..round_num {
+        decrypt_keys[round] = _mm_aesimc_si128( encrypt_keys[round_num - round] );
+    }
+
+    decrypt_keys[round_num] = encrypt_keys[0];
+
+    ( encrypt_keys, decrypt_keys )
+}
+
+// Every round of all blocks in a chunk is issued before the next round, so the blocks are pipelined
+#[target_feature(enable = "aes,sse2")]
+unsafe fn encrypt_chunk( round_keys : &[__m128i], chunk : &mut [[u8; BLOCK_LENGTH]] ) {
+    let round_num = round_keys.len() - 1;
+    let mut state = [_mm_setzero_si128(); PARALLEL_BLOCK_NUM];
+
+    for ( x, block ) in state.iter_mut().zip( chunk.iter() ) {
+        *x = _mm_xor_si128( _mm_loadu_si128( block.as_ptr() as *const __m128i ), round_keys[0] );
+    }
+
+    for round_key in &round_keys[1..round_num] {
+        for x in state.iter_mut().take( chunk.len() ) {
+            *x = _mm_aesenc_si128( *x, *round_key );
+        }
+    }
+
+    for ( x, block ) in state.iter().zip( chunk.iter_mut() ) {
+        _mm_storeu_si128( block.as_mut_ptr() as *mut __m128i, _mm_aesenclast_si128( *x, round_keys[round_num] ) );
+    }
+}
+
+#[target_feature(enable = "aes,sse2")]
+unsafe fn decrypt_chunk( round_keys : &[__m128i], chunk : &mut [[u8; BLOCK_LENGTH]] ) {
+    let round_num = round_keys.len() - 1;
+    let mut state = [_mm_setzero_si128(); PARALLEL_BLOCK_NUM];
+
+    for ( x, block ) in state.iter_mut().zip( chunk.iter() ) {
+        *x = _mm_xor_si128( _mm_loadu_si128( block.as_ptr() as *const __m128i ), round_keys[0] );
+    }
+
+    for round_key in &round_keys[1..round_num] {
+        for x in state.iter_mut().take( chunk.len() ) {
+            *x = _mm_aesdec_si128( *x, *round_key );
+        }
+    }
+
+    for ( x, block ) in state.iter().zip( chunk.iter_mut() ) {
+        _mm_storeu_si128( block.as_mut_ptr() as *mut __m128i, _mm_aesdeclast_si128( *x, round_keys[round_num] ) );
+    }
+}
+
+#[test]
+fn test_aesni_aes() {
+    if !is_available() {
+        return;
+    }
+
+    //FIPS 197 Appendix C Example Vectors
+    let text = "00112233445566778899aabbccddeeff";
+
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let expect = ["69c4e0d86a7b0430d8cdb78070b4c55a",
+                  "dda97ca4864cdfe06eaf70a0ec0d7191",
+                  "8ea2b7ca516745bfeafc49904b496089"];
+
+    for i in 0..key.len() {
+        let aes = AesNiAes::new( &hex::decode( key[i] ).unwrap() ).unwrap();
+        let mut block = [0; BLOCK_LENGTH];
+
+        block.copy_from_slice( &hex::decode( text ).unwrap() );
+        aes.encrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), expect[i] );
+
+        aes.decrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), text );
+    }
+
+    assert_eq!( sub_word( 0xcf4f_3c09 ), crate::aes_common::sub_word( 0xcf4f_3c09 ) );
+}
+
+#[test]
+fn test_aesni_aes_against_reference() {
+    if !is_available() {
+        return;
+    }
+
+    // xorshift64 for reproducible keys and blocks
+    let mut seed : u64 = 0xd1b5_4a32_d192_ed03;
+    let mut next_byte = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as u8
+    };
+
+    for key_length in [16, 24, 32] {
+        // fewer blocks than a chunk, a whole chunk and a partial second chunk
+        for block_num in [1, 7, 8, 19] {
+            let key : Vec<u8> = ( 0..key_length ).map( | _ | next_byte() ).collect();
+            let mut blocks = vec![[0; BLOCK_LENGTH]; block_num];
+            blocks.iter_mut().flatten().for_each( | byte | *byte = next_byte() );
+
+            let reference = ReferenceAes::new( &key ).unwrap();
+            let aesni = AesNiAes::new( &key ).unwrap();
+            let text = blocks.clone();
+            let mut expect = blocks.clone();
+
+            expect.iter_mut().for_each( | block | reference.encrypt_block( block ) );
+            aesni.encrypt_parallel( &mut blocks );
+            assert_eq!( blocks, expect );
+
+            aesni.decrypt_parallel( &mut blocks );
+            assert_eq!( blocks, text );
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,552 @@
+use crate::aes::KeyError;
+use crate::block_cipher::{BlockCipher, RoundKeys, BLOCK_LENGTH};
+use crate::zeroize::Zeroize;
+
+#[cfg(test)]
+use hex;
+#[cfg(test)]
+use crate::aes_common;
+#[cfg(all(test, feature = "std"))]
+use crate::block_cipher::ReferenceAes;
+
+// blocks processed at once, one per bit of a lane
+pub const PARALLEL_BLOCK_NUM : usize = 8;
+
+// round keys of AES-256, the most of all key sizes
+const MAX_ROUND_NUM : usize = 14;
+
+// ShiftRows as a permutation of the lanes. Lane row + 4 * col takes lane row + 4 * ( ( col + row ) % 4 )
+const SHIFT_ROWS : [usize; 16] = [0, 5, 10, 15, 4, 9, 14, 3, 8, 13, 2, 7, 12, 1, 6, 11];
+const INV_SHIFT_ROWS : [usize; 16] = [0, 13, 10, 7, 4, 1, 14, 11, 8, 5, 2, 15, 12, 9, 6, 3];
+
+// Plane i holds bit i of every byte of 8 blocks.
+// Byte j of the plane(a lane) is byte j of the blocks, and bit b of the lane is block b.
+// Every round function is a fixed sequence of logic operations, so no table is indexed by secret data
+type Planes = [u128; 8];
+
+// Constant-time AES that processes 8 blocks in parallel
+#[derive(Clone)]
+pub struct BitslicedAes {
+    round_keys : [Planes; MAX_ROUND_NUM + 1],
+    round_num : usize
+}
+
+impl BitslicedAes {
+    pub fn new( key : &[u8] ) -> Result<Self, KeyError> {
+        let expanded_keys = RoundKeys::with_sub_word( key, sub_word )?;
+        let round_num = expanded_keys.round_num();
+        let mut round_keys = [[0; 8]; MAX_ROUND_NUM + 1];
+
+        for ( round, planes ) in round_keys.iter_mut().enumerate().take( round_num + 1 ) {
+            let mut bytes = [0; BLOCK_LENGTH];
+
+            for ( col, word ) in expanded_keys.round_key( round ).iter().enumerate() {
+                bytes[( col * 4 )..( col * 4 + 4 )].copy_from_slice( &word.to_be_bytes() );
+            }
+
+            // every bit of a lane is the same, since all blocks share the key
+            for ( i, plane ) in planes.iter_mut().enumerate() {
+                let mut lanes = [0; BLOCK_LENGTH];
+
+                for j in 0..BLOCK_LENGTH {
+                    lanes[j] = 0u8.wrapping_sub( ( bytes[j] >> i ) & 1 );
+                }
+
+                *plane = u128::from_le_bytes( lanes );
+                lanes.zeroize();
+            }
+
+            bytes.zeroize();
+        }
+
+        Ok( BitslicedAes { round_keys, round_num } )
+    }
+
+    fn encrypt_batch( &self, blocks : &mut [[u8; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM] ) {
+        let mut state = pack( blocks );
+
+        add_round_key( &mut state, &self.round_keys[0] );
+
+        for round in 1..self.round_num {
+            sub_bytes( &mut state );
+            permute_lanes( &mut state, &SHIFT_ROWS );
+            mix_columns( &mut state );
+            add_round_key( &mut state, &self.round_keys[round] );
+        }
+
+        sub_bytes( &mut state );
+        permute_lanes( &mut state, &SHIFT_ROWS );
+        add_round_key( &mut state, &self.round_keys[self.round_num] );
+
+        unpack( &state, blocks );
+    }
+
+    fn decrypt_batch( &self, blocks : &mut [[u8; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM] ) {
+        let mut state = pack( blocks );
+
+        add_round_key( &mut state, &self.round_keys[self.round_num] );
+
+        for round in ( 1..self.round_num ).rev() {
+            permute_lanes( &mut state, &INV_SHIFT_ROWS );
+            inv_sub_bytes( &mut state );
+            add_round_key( &mut state, &self.round_keys[round] );
+            inv_mix_columns( &mut state );
+        }
+
+        permute_lanes( &mut state, &INV_SHIFT_ROWS );
+        inv_sub_bytes( &mut state );
+        add_round_key( &mut state, &self.round_keys[0] );
+
+        unpack( &state, blocks );
+    }
+}
+
+impl Drop for BitslicedAes {
+    fn drop( &mut self ) {
+        for planes in self.round_keys.iter_mut() {
+            planes.zeroize();
+        }
+    }
+}
+
+impl BlockCipher for BitslicedAes {
+    fn encrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        self.encrypt_parallel( core::slice::from_mut( block ) );
+    }
+
+    fn decrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        self.decrypt_parallel( core::slice::from_mut( block ) );
+    }
+
+    fn encrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        for chunk in blocks.chunks_mut( PARALLEL_BLOCK_NUM ) {
+            let mut batch = [[0; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM];
+
+            batch[..chunk.len()].copy_from_slice( chunk );
+            self.encrypt_batch( &mut batch );
+            chunk.copy_from_slice( &batch[..chunk.len()] );
+        }
+    }
+
+    fn decrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        for chunk in blocks.chunks_mut( PARALLEL_BLOCK_NUM ) {
+            let mut batch = [[0; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM];
+
+            batch[..chunk.len()].copy_from_slice( chunk );
+            self.decrypt_batch( &mut batch );
+            chunk.copy_from_slice( &batch[..chunk.len()] );
+        }
+    }
+}
+
+// SubWord of the key expansion without the S-box table
+pub(crate) fn sub_word( input : u32 ) -> u32 {
+    let bytes = input.to_be_bytes();
+    let mut state = [0; 8];
+
+    // the 4 bytes in the first 4 lanes of block 0
+    for ( i, plane ) in state.iter_mut().enumerate() {
+        for ( j, byte ) in bytes.iter().enumerate() {
+            *plane |= ( ( ( byte >> i ) & 1 ) as u128 ) << ( 8 * j );
+        }
+    }
+
+    sub_bytes( &mut state );
+
+    let mut output = [0u8; 4];
+
+    for ( i, plane ) in state.iter().enumerate() {
+        for ( j, byte ) in output.iter_mut().enumerate() {
+            *byte |= ( ( ( plane >> ( 8 * j ) ) & 1 ) as u8 ) << i;
+        }
+    }
+
+    u32::from_be_bytes( output )
+}
+
+fn pack( blocks : &[[u8; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM] ) -> Planes {
+    let mut lanes = [[0; BLOCK_LENGTH]; 8];
+
+    for j in 0..BLOCK_LENGTH {
+        let mut column = [0; PARALLEL_BLOCK_NUM];
+
+        for b in 0..PARALLEL_BLOCK_NUM {
+            column[b] = blocks[b][j];
+        }
+
+        // byte b bit i to byte i bit b
+        let bits = transpose( u64::from_le_bytes( column ) ).to_le_bytes();
+
+        for i in 0..8 {
+            lanes[i][j] = bits[i];
+        }
+    }
+
+    let mut state = [0; 8];
+
+    for i in 0..8 {
+        state[i] = u128::from_le_bytes( lanes[i] );
+    }
+
+    state
+}
+
+fn unpack( state : &Planes, blocks : &mut [[u8; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM] ) {
+    let mut lanes = [[0; BLOCK_LENGTH]; 8];
+
+    for i in 0..8 {
+        lanes[i] = state[i].to_le_bytes();
+    }
+
+    for j in 0..BLOCK_LENGTH {
+        let mut column = [0; 8];
+
+        for i in 0..8 {
+            column[i] = lanes[i][j];
+        }
+
+        let bytes = transpose( u64::from_le_bytes( column ) ).to_le_bytes();
+
+        for b in 0..PARALLEL_BLOCK_NUM {
+            blocks[b][j] = bytes[b];
+        }
+    }
+}
+
+// transpose of an 8x8 bit matrix, bit i of byte b to bit b of byte i
+fn transpose( mut x : u64 ) -> u64 {
+    let mut t = ( x ^ ( x >> 7 ) ) & 0x00aa_00aa_00aa_00aa;
+    x ^= t ^ ( t << 7 );
+    t = ( x ^ ( x >> 14 ) ) & 0x0000_cccc_0000_cccc;
+    x ^= t ^ ( t << 14 );
+    t = ( x ^ ( x >> 28 ) ) & 0x0000_0000_f0f0_f0f0;
+    x ^= t ^ ( t << 28 );
+
+    x
+}
+
+fn add_round_key( state : &mut Planes, round_key : &Planes ) {
+    for i in 0..8 {
+        state[i] ^= round_key[i];
+    }
+}
+
+// The same lane permutation on every plane. The indexes are constants, not data
+fn permute_lanes( state : &mut Planes, permutation : &[usize; 16] ) {
+    for plane in state.iter_mut() {
+        let lanes = plane.to_le_bytes();
+        let mut permuted = [0; 16];
+
+        for j in 0..16 {
+            permuted[j] = lanes[permutation[j]];
+        }
+
+        *plane = u128::from_le_bytes( permuted );
+    }
+}
+
+// lane row + 4 * col takes lane ( row + n ) % 4 + 4 * col, that is rotation of each 32-bit column
+fn rotate_rows( plane : u128, n : u32 ) -> u128 {
+    let low = ( ( 1u128 << ( 32 - 8 * n ) ) - 1 ) * 0x0000_0001_0000_0001_0000_0001_0000_0001;
+    ( ( plane >> ( 8 * n ) ) & low ) | ( ( plane << ( 32 - 8 * n ) ) & !low )
+}
+
+// multiplication by x(02) in GF(2^8) on every lane
+fn xtime( a : &Planes ) -> Planes {
+    [a[7], a[0] ^ a[7], a[1], a[2] ^ a[7], a[3] ^ a[7], a[4], a[5], a[6]]
+}
+
+// 02 * a0 + 03 * a1 + a2 + a3 = 02 * ( a0 + a1 ) + a1 + a2 + a3
+fn mix_columns( state : &mut Planes ) {
+    let mut rotated = [0; 8];
+
+    for i in 0..8 {
+        rotated[i] = state[i] ^ rotate_rows( state[i], 1 );
+    }
+
+    let doubled = xtime( &rotated );
+
+    for i in 0..8 {
+        state[i] = doubled[i] ^ rotate_rows( state[i], 1 ) ^ rotate_rows( state[i], 2 ) ^ rotate_rows( state[i], 3 );
+    }
+}
+
+// InvMixColumns is MixColumns after adding 04 * ( a0 + a2 ) to a0 and a2, and 04 * ( a1 + a3 ) to a1 and a3
+fn inv_mix_columns( state : &mut Planes ) {
+    let mut rotated = [0; 8];
+
+    for i in 0..8 {
+        rotated[i] = state[i] ^ rotate_rows( state[i], 2 );
+    }
+
+    let quadrupled = xtime( &xtime( &rotated ) );
+
+    for i in 0..8 {
+        state[i] ^= quadrupled[i];
+    }
+
+    mix_columns( state );
+}
+
+// The affine transformation of InvSubBytes with the constant 05(FIPS 197 5.3.2)
+fn inv_affine( state : &mut Planes ) {
+    let a = *state;
+
+    for i in 0..8 {
+        state[i] = a[( i + 2 ) % 8] ^ a[( i + 5 ) % 8] ^ a[( i + 7 ) % 8];
+    }
+
+    state[0] = !state[0];
+    state[2] = !state[2];
+}
+
+// SubBytes is the affine transformation of the inverse, so InvSubBytes is the inverse affine transformation
+// of SubBytes of the inverse affine transformation
+fn inv_sub_bytes( state : &mut Planes ) {
+    inv_affine( state );
+    sub_bytes( state );
+    inv_affine( state );
+}
+
+// The S-box circuit of depth 16 by Boyar and Peralta(https://eprint.iacr.org/2011/332).
+// U0 and S0 are the most significant bits
+#[allow(clippy::many_single_char_names)]
+fn sub_bytes( state : &mut Planes ) {
+    let u0 = state[7];
+    let u1 = state[6];
+    let u2 = state[5];
+    let u3 = state[4];
+    let u4 = state[3];
+    let u5 = state[2];
+    let u6 = state[1];
+    let u7 = state[0];
+
+    // top linear transformation
+    let t1 = u0 ^ u3;
+    let t2 = u0 ^ u5;
+    let t3 = u0 ^ u6;
+    let t4 = u3 ^ u5;
+    let t5 = u4 ^ u6;
+    let t6 = t1 ^ t5;
+    let t7 = u1 ^ u2;
+    let t8 = u7 ^ t6;
+    let t9 = u7 ^ t7;
+    let t10 = t6 ^ t7;
+    let t11 = u1 ^ u5;
+    let t12 = u2 ^ u5;
+    let t13 = t3 ^ t4;
+    let t14 = t6 ^ t11;
+    let t15 = t5 ^ t11;
+    let t16 = t5 ^ t12;
+    let t17 = t9 ^ t16;
+    let t18 = u3 ^ u7;
+    let t19 = t7 ^ t18;
+    let t20 = t1 ^ t19;
+    let t21 = u6 ^ u7;
+    let t22 = t7 ^ t21;
+    let t23 = t2 ^ t22;
+    let t24 = t2 ^ t10;
+    let t25 = t20 ^ t17;
+    let t26 = t3 ^ t16;
+    let t27 = t1 ^ t12;
+
+    // inversion in GF(2^8)
+    let m1 = t13 & t6;
+    let m2 = t23 & t8;
+    let m3 = t14 ^ m1;
+    let m4 = t19 & u7;
+    let m5 = m4 ^ m1;
+    let m6 = t3 & t16;
+    let m7 = t22 & t9;
+    let m8 = t26 ^ m6;
+    let m9 = t20 & t17;
+    let m10 = m9 ^ m6;
+    let m11 = t1 & t15;
+    let m12 = t4 & t27;
+    let m13 = m12 ^ m11;
+    let m14 = t2 & t10;
+    let m15 = m14 ^ m11;
+    let m16 = m3 ^ m2;
+    let m17 = m5 ^ t24;
+    let m18 = m8 ^ m7;
+    let m19 = m10 ^ m15;
+    let m20 = m16 ^ m13;
+    let m21 = m17 ^ m15;
+    let m22 = m18 ^ m13;
+    let m23 = m19 ^ t25;
+    let m24 = m22 ^ m23;
+    let m25 = m22 & m20;
+    let m26 = m21 ^ m25;
+    let m27 = m20 ^ m21;
+    let m28 = m23 ^ m25;
+    let m29 = m28 & m27;
+    let m30 = m26 & m24;
+    let m31 = m20 & m23;
+    let m32 = m27 & m31;
+    let m33 = m27 ^ m25;
+    let m34 = m21 & m22;
+    let m35 = m24 & m34;
+    let m36 = m24 ^ m25;
+    let m37 = m21 ^ m29;
+    let m38 = m32 ^ m33;
+    let m39 = m23 ^ m30;
+    let m40 = m35 ^ m36;
+    let m41 = m38 ^ m40;
+    let m42 = m37 ^ m39;
+    let m43 = m37 ^ m38;
+    let m44 = m39 ^ m40;
+    let m45 = m42 ^ m41;
+    let m46 = m44 & t6;
+    let m47 = m40 & t8;
+    let m48 = m39 & u7;
+    let m49 = m43 & t16;
+    let m50 = m38 & t9;
+    let m51 = m37 & t17;
+    let m52 = m42 & t15;
+    let m53 = m45 & t27;
+    let m54 = m41 & t10;
+    let m55 = m44 & t13;
+    let m56 = m40 & t23;
+    let m57 = m39 & t19;
+    let m58 = m43 & t3;
+    let m59 = m38 & t22;
+    let m60 = m37 & t20;
+    let m61 = m42 & t1;
+    let m62 = m45 & t4;
+    let m63 = m41 & t2;
+
+    // bottom linear transformation
+    let l0 = m61 ^ m62;
+    let l1 = m50 ^ m56;
+    let l2 = m46 ^ m48;
+    let l3 = m47 ^ m55;
+    let l4 = m54 ^ m58;
+    let l5 = m49 ^ m61;
+    let l6 = m62 ^ l5;
+    let l7 = m46 ^ l3;
+    let l8 = m51 ^ m59;
+    let l9 = m52 ^ m53;
+    let l10 = m53 ^ l4;
+    let l11 = m60 ^ l2;
+    let l12 = m48 ^ m51;
+    let l13 = m50 ^ l0;
+    let l14 = m52 ^ m61;
+    let l15 = m55 ^ l1;
+    let l16 = m56 ^ l0;
+    let l17 = m57 ^ l1;
+    let l18 = m58 ^ l8;
+    let l19 = m63 ^ l4;
+    let l20 = l0 ^ l1;
+    let l21 = l1 ^ l7;
+    let l22 = l3 ^ l12;
+    let l23 = l18 ^ l2;
+    let l24 = l15 ^ l9;
+    let l25 = l6 ^ l10;
+    let l26 = l7 ^ l9;
+    let l27 = l8 ^ l10;
+    let l28 = l11 ^ l14;
+    let l29 = l11 ^ l17;
+
+    state[7] = l6 ^ l24;
+    state[6] = !( l16 ^ l26 );
+    state[5] = !( l19 ^ l28 );
+    state[4] = l6 ^ l21;
+    state[3] = l20 ^ l22;
+    state[2] = l25 ^ l29;
+    state[1] = !( l13 ^ l27 );
+    state[0] = !( l6 ^ l23 );
+}
+
+#[test]
+fn test_bitsliced_s_box() {
+    for x in 0..256usize {
+        let mut blocks = [[0; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM];
+
+        // a different byte in every position of every block
+        for ( b, block ) in blocks.iter_mut().enumerate() {
+            for ( j, byte ) in block.iter_mut().enumerate() {
+                *byte = ( x + b * BLOCK_LENGTH + j ) as u8;
+            }
+        }
+
+        let mut state = pack( &blocks );
+        sub_bytes( &mut state );
+
+        let mut substituted = [[0; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM];
+        unpack( &state, &mut substituted );
+
+        for b in 0..PARALLEL_BLOCK_NUM {
+            for j in 0..BLOCK_LENGTH {
+                let byte = blocks[b][j] as usize;
+                assert_eq!( substituted[b][j], aes_common::S_BOX[byte >> 4][byte & 0x0f] );
+            }
+        }
+
+        inv_sub_bytes( &mut state );
+        unpack( &state, &mut substituted );
+        assert_eq!( substituted, blocks );
+    }
+
+    assert_eq!( sub_word( 0xcf4f_3c09 ), aes_common::sub_word( 0xcf4f_3c09 ) );
+}
+
+#[test]
+fn test_bitsliced_aes() {
+
+    //FIPS 197 Appendix C Example Vectors
+    let text = "00112233445566778899aabbccddeeff";
+
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let expect = ["69c4e0d86a7b0430d8cdb78070b4c55a",
+                  "dda97ca4864cdfe06eaf70a0ec0d7191",
+                  "8ea2b7ca516745bfeafc49904b496089"];
+
+    for i in 0..key.len() {
+        let aes = BitslicedAes::new( &hex::decode( key[i] ).unwrap() ).unwrap();
+        let mut block = [0; BLOCK_LENGTH];
+
+        block.copy_from_slice( &hex::decode( text ).unwrap() );
+        aes.encrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), expect[i] );
+
+        aes.decrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), text );
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_bitsliced_aes_against_reference() {
+    // xorshift64 for reproducible keys and blocks
+    let mut seed : u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut next_byte = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as u8
+    };
+
+    for key_length in [16, 24, 32] {
+        // fewer blocks than a batch, a whole batch and a partial second batch
+        for block_num in [1, 7, 8, 19] {
+            let key : Vec<u8> = ( 0..key_length ).map( | _ | next_byte() ).collect();
+            let mut blocks = vec![[0; BLOCK_LENGTH]; block_num];
+            blocks.iter_mut().flatten().for_each( | byte | *byte = next_byte() );
+
+            let reference = ReferenceAes::new( &key ).unwrap();
+            let bitsliced = BitslicedAes::new( &key ).unwrap();
+            let text = blocks.clone();
+            let mut expect = blocks.clone();
+
+            expect.iter_mut().for_each( | block | reference.encrypt_block( block ) );
+            bitsliced.encrypt_parallel( &mut blocks );
+            assert_eq!( blocks, expect );
+
+            bitsliced.decrypt_parallel( &mut blocks );
+            assert_eq!( blocks, text );
+        }
+    }
+}
\ No newline at end of file
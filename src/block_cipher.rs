@@ -0,0 +1,593 @@
+use crate::aes::KeyError;
+use crate::aes_common::{self, KeySize};
+use crate::zeroize::Zeroize;
+#[cfg(feature = "std")]
+use crate::zeroize::Zeroizing;
+use crate::ttable::TTableAes;
+use crate::bitsliced::BitslicedAes;
+#[cfg(feature = "std")]
+use crate::masked::MaskedAes;
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+use crate::aesni::{self, AesNiAes};
+
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use hex;
+
+// block length in bytes
+pub const BLOCK_LENGTH : usize = 16;
+
+// round key words of AES-256, the most of all key sizes
+pub(crate) const MAX_ROUND_KEY_NUM : usize = aes_common::BLOCK_SIZE * ( 14 + 1 );
+
+const R_CON : [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1B, 0x36];
+
+pub(crate) type State = [[u8; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE];
+
+// Encryption and decryption of a single block in place
+pub trait BlockCipher {
+    fn encrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] );
+    fn decrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] );
+
+    // Independent blocks, which a backend may process in parallel
+    fn encrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        for block in blocks {
+            self.encrypt_block( block );
+        }
+    }
+
+    fn decrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        for block in blocks {
+            self.decrypt_block( block );
+        }
+    }
+}
+
+impl<C : BlockCipher + ?Sized> BlockCipher for &C {
+    fn encrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        ( **self ).encrypt_block( block )
+    }
+
+    fn decrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        ( **self ).decrypt_block( block )
+    }
+
+    fn encrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        ( **self ).encrypt_parallel( blocks )
+    }
+
+    fn decrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        ( **self ).decrypt_parallel( blocks )
+    }
+}
+
+// Key expansion(FIPS 197 5.2) of a key in bytes, for every key size.
+// The decryption key schedule of the equivalent inverse cipher(FIPS 197 5.3.5) is computed along with it
+#[derive(Clone)]
+pub struct RoundKeys {
+    words : [u32; MAX_ROUND_KEY_NUM],
+    inv_words : [u32; MAX_ROUND_KEY_NUM],
+    key_size : KeySize
+}
+
+impl RoundKeys {
+    pub fn new( key : &[u8] ) -> Result<Self, KeyError> {
+        RoundKeys::with_sub_word( key, aes_common::sub_word )
+    }
+
+    // SubWord is given for a backend that must not use the S-box table
+    pub(crate) fn with_sub_word( key : &[u8], sub_word : fn( u32 ) -> u32 ) -> Result<Self, KeyError> {
+        let key_size = KeySize::from_key_length( key.len() ).ok_or( KeyError::InvalidLength( key.len() ) )?;
+        let key_word_num = key.len() / aes_common::WORD_IN_BYTES_NUM;
+        let round_key_num = aes_common::BLOCK_SIZE * ( key_size.round_num() + 1 );
+        let mut words = [0; MAX_ROUND_KEY_NUM];
+
+        for i in 0..key_word_num {
+            words[i] = u32::from_be_bytes( [key[i * 4], key[i * 4 + 1], key[i * 4 + 2], key[i * 4 + 3]] );
+        }
+
+        for i in key_word_num..round_key_num {
+            words[i] = words[i - key_word_num] ^ expansion_temp( words[i - 1], i, key_word_num, sub_word );
+        }
+
+        // InvMixColumns applied to the round keys of every round but the first and the last.
+        // Its multiplication has no branch on the key, so every backend may compute it
+        let mut inv_words = words;
+
+        for word in &mut inv_words[aes_common::BLOCK_SIZE..( aes_common::BLOCK_SIZE * key_size.round_num() )] {
+            *word = aes_common::inv_mix_column_word( *word );
+        }
+
+        Ok( RoundKeys { words, inv_words, key_size } )
+    }
+
+    pub fn key_size( &self ) -> KeySize {
+        self.key_size
+    }
+
+    pub fn round_num( &self ) -> usize {
+        self.key_size.round_num()
+    }
+
+    // the 4 words of a round
+    pub fn round_key( &self, round : usize ) -> &[u32] {
+        &self.words[( round * aes_common::BLOCK_SIZE )..( ( round + 1 ) * aes_common::BLOCK_SIZE )]
+    }
+
+    // the 4 words of a round in the equivalent inverse cipher, which uses them from the last round to the first
+    pub fn inv_round_key( &self, round : usize ) -> &[u32] {
+        &self.inv_words[( round * aes_common::BLOCK_SIZE )..( ( round + 1 ) * aes_common::BLOCK_SIZE )]
+    }
+}
+
+// The word that word i of the key expansion adds to word i - Nk, computed from word i - 1.
+// Word i - Nk is also the word i xor this, which runs the expansion backwards
+pub(crate) fn expansion_temp( prev_word : u32, i : usize, key_word_num : usize, sub_word : fn( u32 ) -> u32 ) -> u32 {
+    if i.is_multiple_of( key_word_num ) {
+        sub_word( aes_common::rot_word( prev_word ) ) ^ ( ( R_CON[i / key_word_num - 1] as u32 ) << 24 )
+    }
+    else if key_word_num > 6 && i % key_word_num == 4 {
+        sub_word( prev_word )
+    }
+    else {
+        prev_word
+    }
+}
+
+impl Drop for RoundKeys {
+    fn drop( &mut self ) {
+        self.words.zeroize();
+        self.inv_words.zeroize();
+    }
+}
+
+// The round functions of aes_common on bytes instead of hex text
+#[derive(Clone)]
+pub struct ReferenceAes {
+    round_keys : RoundKeys
+}
+
+impl ReferenceAes {
+    pub fn new( key : &[u8] ) -> Result<Self, KeyError> {
+        Ok( ReferenceAes { round_keys : RoundKeys::new( key )? } )
+    }
+
+    // for the leakage simulator
+    #[cfg(feature = "std")]
+    pub(crate) fn round_keys( &self ) -> &RoundKeys {
+        &self.round_keys
+    }
+}
+
+impl BlockCipher for ReferenceAes {
+    fn encrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        let round_num = self.round_keys.round_num();
+        let mut state = block_to_state( block );
+
+        state = add_round_key( state, self.round_keys.round_key( 0 ) );
+
+        for round in 1..round_num {
+            state = aes_common::sub_bytes( state );
+            state = aes_common::shift_rows( state );
+            state = aes_common::mix_columns( state );
+            state = add_round_key( state, self.round_keys.round_key( round ) );
+        }
+
+        state = aes_common::sub_bytes( state );
+        state = aes_common::shift_rows( state );
+        state = add_round_key( state, self.round_keys.round_key( round_num ) );
+
+        state_to_block( state, block );
+    }
+
+    fn decrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        inv_cipher( &self.round_keys, block );
+    }
+}
+
+// Equivalent inverse cipher(FIPS 197 5.3.5) with the round functions of aes_common
+fn inv_cipher( round_keys : &RoundKeys, block : &mut [u8; BLOCK_LENGTH] ) {
+    let round_num = round_keys.round_num();
+    let mut state = block_to_state( block );
+
+    state = add_round_key( state, round_keys.inv_round_key( round_num ) );
+
+    for round in ( 1..round_num ).rev() {
+        state = aes_common::inv_sub_bytes( state );
+        state = aes_common::inv_shift_rows( state );
+        state = aes_common::inv_mix_columns( state );
+        state = add_round_key( state, round_keys.inv_round_key( round ) );
+    }
+
+    state = aes_common::inv_sub_bytes( state );
+    state = aes_common::inv_shift_rows( state );
+    state = add_round_key( state, round_keys.inv_round_key( 0 ) );
+
+    state_to_block( state, block );
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    // the round functions of aes_common one by one
+    Reference,
+    // SubBytes, ShiftRows and MixColumns merged into 32-bit table lookups
+    TTable,
+    // constant-time logic operations on 8 blocks at once
+    Bitsliced,
+    // the AES instructions of x86_64, falling back to Bitsliced without them or without std
+    AesNi,
+    // first-order boolean masking with fresh masks for every block. Without std there is no random source for the masks,
+    // so it is left out, and MaskedAes::with_seed takes the seed from the caller
+    #[cfg(feature = "std")]
+    Masked
+}
+
+// The hardware instructions when the CPU has them. Otherwise constant time,
+// since the table lookups depend on secret data and leak through the cache
+impl Default for Backend {
+    fn default() -> Self {
+        if hardware_aes_available() {
+            Backend::AesNi
+        }
+        else {
+            Backend::Bitsliced
+        }
+    }
+}
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+fn hardware_aes_available() -> bool {
+    aesni::is_available()
+}
+
+#[cfg(not(all(feature = "std", target_arch = "x86_64")))]
+fn hardware_aes_available() -> bool {
+    false
+}
+
+// AES with the implementation chosen at run time.
+// The round keys are kept by value, so a key needs no allocation.
+// AesNi falls back to Bitsliced when the CPU has no AES instructions
+#[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum AesCipher {
+    Reference( ReferenceAes ),
+    TTable( TTableAes ),
+    Bitsliced( BitslicedAes ),
+    #[cfg(all(feature = "std", target_arch = "x86_64"))]
+    AesNi( AesNiAes ),
+    #[cfg(feature = "std")]
+    Masked( MaskedAes )
+}
+
+impl AesCipher {
+    pub fn new( key : &[u8], backend : Backend ) -> Result<Self, KeyError> {
+        match backend {
+            Backend::Reference => Ok( AesCipher::Reference( ReferenceAes::new( key )? ) ),
+            Backend::TTable => Ok( AesCipher::TTable( TTableAes::new( key )? ) ),
+            Backend::Bitsliced => Ok( AesCipher::Bitsliced( BitslicedAes::new( key )? ) ),
+            Backend::AesNi => AesCipher::new_hardware( key ),
+            #[cfg(feature = "std")]
+            Backend::Masked => Ok( AesCipher::Masked( MaskedAes::new( key )? ) )
+        }
+    }
+
+    #[cfg(all(feature = "std", target_arch = "x86_64"))]
+    fn new_hardware( key : &[u8] ) -> Result<Self, KeyError> {
+        if aesni::is_available() {
+            Ok( AesCipher::AesNi( AesNiAes::new( key )? ) )
+        }
+        else {
+            Ok( AesCipher::Bitsliced( BitslicedAes::new( key )? ) )
+        }
+    }
+
+    #[cfg(not(all(feature = "std", target_arch = "x86_64")))]
+    fn new_hardware( key : &[u8] ) -> Result<Self, KeyError> {
+        Ok( AesCipher::Bitsliced( BitslicedAes::new( key )? ) )
+    }
+
+    // The backend in use, which is Bitsliced for AesNi without the instructions
+    pub fn backend( &self ) -> Backend {
+        match self {
+            AesCipher::Reference( _ ) => Backend::Reference,
+            AesCipher::TTable( _ ) => Backend::TTable,
+            AesCipher::Bitsliced( _ ) => Backend::Bitsliced,
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            AesCipher::AesNi( _ ) => Backend::AesNi,
+            #[cfg(feature = "std")]
+            AesCipher::Masked( _ ) => Backend::Masked
+        }
+    }
+}
+
+impl BlockCipher for AesCipher {
+    fn encrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        match self {
+            AesCipher::Reference( aes ) => aes.encrypt_block( block ),
+            AesCipher::TTable( aes ) => aes.encrypt_block( block ),
+            AesCipher::Bitsliced( aes ) => aes.encrypt_block( block ),
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            AesCipher::AesNi( aes ) => aes.encrypt_block( block ),
+            #[cfg(feature = "std")]
+            AesCipher::Masked( aes ) => aes.encrypt_block( block )
+        }
+    }
+
+    fn decrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        match self {
+            AesCipher::Reference( aes ) => aes.decrypt_block( block ),
+            AesCipher::TTable( aes ) => aes.decrypt_block( block ),
+            AesCipher::Bitsliced( aes ) => aes.decrypt_block( block ),
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            AesCipher::AesNi( aes ) => aes.decrypt_block( block ),
+            #[cfg(feature = "std")]
+            AesCipher::Masked( aes ) => aes.decrypt_block( block )
+        }
+    }
+
+    fn encrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        match self {
+            AesCipher::Reference( aes ) => aes.encrypt_parallel( blocks ),
+            AesCipher::TTable( aes ) => aes.encrypt_parallel( blocks ),
+            AesCipher::Bitsliced( aes ) => aes.encrypt_parallel( blocks ),
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            AesCipher::AesNi( aes ) => aes.encrypt_parallel( blocks ),
+            #[cfg(feature = "std")]
+            AesCipher::Masked( aes ) => aes.encrypt_parallel( blocks )
+        }
+    }
+
+    fn decrypt_parallel( &self, blocks : &mut [[u8; BLOCK_LENGTH]] ) {
+        match self {
+            AesCipher::Reference( aes ) => aes.decrypt_parallel( blocks ),
+            AesCipher::TTable( aes ) => aes.decrypt_parallel( blocks ),
+            AesCipher::Bitsliced( aes ) => aes.decrypt_parallel( blocks ),
+            #[cfg(all(feature = "std", target_arch = "x86_64"))]
+            AesCipher::AesNi( aes ) => aes.decrypt_parallel( blocks ),
+            #[cfg(feature = "std")]
+            AesCipher::Masked( aes ) => aes.decrypt_parallel( blocks )
+        }
+    }
+}
+
+// The cipher of the key of a hex String mode call, which the block functions of aes128, aes192 and aes256
+// take instead of expanding the key again for every block of the call
+#[cfg(feature = "std")]
+struct ScopedCipher {
+    key : Zeroizing<Vec<u8>>,
+    aes : AesCipher
+}
+
+#[cfg(feature = "std")]
+impl ScopedCipher {
+    // compare every byte so that the time does not depend on the position of a mismatch
+    fn has_key( &self, key : &[u8] ) -> bool {
+        self.key.len() == key.len() && self.key.iter().zip( key ).fold( 0, | acc, ( byte1, byte2 ) | acc | ( byte1 ^ byte2 ) ) == 0
+    }
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static SCOPED_CIPHER : RefCell<Option<ScopedCipher>> = const { RefCell::new( None ) };
+}
+
+// Expands a key once for the hex String functions called while it is alive on this thread, and wipes it on drop.
+// A mode function keeps one for the length of the call, since its cipher_func takes the key as hex for every block
+#[cfg(feature = "std")]
+pub(crate) struct CipherScope {
+    // the cipher of the enclosing scope, put back on drop. None when this scope set no cipher
+    outer : Option<Option<ScopedCipher>>
+}
+
+#[cfg(feature = "std")]
+impl CipherScope {
+    pub(crate) fn new( key : &str ) -> Self {
+        let key_u8 = Zeroizing::new( hex::decode( key ).unwrap_or_default() );
+
+        // a mode built on another one, such as EAX on CTR and CMAC, shares the cipher of the outer call
+        if SCOPED_CIPHER.with( | scoped | scoped.borrow().as_ref().is_some_and( | scoped | scoped.has_key( &key_u8 ) ) ) {
+            return CipherScope { outer : None };
+        }
+
+        // a key that is not hex or has no AES key length is left to the block functions, which report it
+        match AesCipher::new( &key_u8, Backend::default() ) {
+            Ok( aes ) => CipherScope { outer : Some( SCOPED_CIPHER.with( | scoped | scoped.replace( Some( ScopedCipher { key : key_u8, aes } ) ) ) ) },
+            Err( _ ) => CipherScope { outer : None }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for CipherScope {
+    fn drop( &mut self ) {
+        if let Some( outer ) = self.outer.take() {
+            SCOPED_CIPHER.with( | scoped | scoped.replace( outer ) );
+        }
+    }
+}
+
+// One block of hex text with a hex key through the backend of Backend::default(),
+// which the hex String functions of aes128, aes192 and aes256 run on
+#[cfg(feature = "std")]
+pub(crate) fn encrypt_text( text : String, key : &str, key_size : KeySize ) -> String {
+    process_text( text, key, key_size, | aes, block | aes.encrypt_block( block ) )
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn decrypt_text( text : String, key : &str, key_size : KeySize ) -> String {
+    process_text( text, key, key_size, | aes, block | aes.decrypt_block( block ) )
+}
+
+#[cfg(feature = "std")]
+fn process_text( text : String, key : &str, key_size : KeySize, process : fn( &AesCipher, &mut [u8; BLOCK_LENGTH] ) ) -> String {
+    let key_u8 = Zeroizing::new( hex::decode( key ).expect( "Failed to convert key" ) );
+
+    assert!( key_u8.len() == key_size.key_length(),
+             "{} needs a {}-byte key, but the key is {} bytes", key_size, key_size.key_length(), key_u8.len() );
+
+    let text = Zeroizing::new( text );
+    let text_u8 = Zeroizing::new( hex::decode( &*text ).expect( "Failed to convert text" ) );
+
+    assert!( text_u8.len() == BLOCK_LENGTH, "Text must be one block, but it is {} bytes", text_u8.len() );
+
+    let mut block = [0; BLOCK_LENGTH];
+
+    block.copy_from_slice( &text_u8 );
+
+    SCOPED_CIPHER.with( | scoped | match &*scoped.borrow() {
+        Some( scoped ) if scoped.has_key( &key_u8 ) => process( &scoped.aes, &mut block ),
+        _ => process( &AesCipher::new( &key_u8, Backend::default() ).expect( "Failed to expand key" ), &mut block )
+    } );
+
+    let result = hex::encode( block );
+    block.zeroize();
+
+    result
+}
+
+pub(crate) fn block_to_state( block : &[u8; BLOCK_LENGTH] ) -> State {
+    let mut state : State = [[0; aes_common::WORD_IN_BYTES_NUM]; aes_common::BLOCK_SIZE];
+
+    for col in 0..aes_common::WORD_IN_BYTES_NUM {
+        for row in 0..aes_common::BLOCK_SIZE {
+            state[row][col] = block[row + aes_common::WORD_IN_BYTES_NUM * col];
+        }
+    }
+
+    state
+}
+
+pub(crate) fn state_to_block( state : State, block : &mut [u8; BLOCK_LENGTH] ) {
+    for col in 0..aes_common::WORD_IN_BYTES_NUM {
+        for row in 0..aes_common::BLOCK_SIZE {
+            block[row + aes_common::WORD_IN_BYTES_NUM * col] = state[row][col];
+        }
+    }
+}
+
+pub(crate) fn add_round_key( mut state : State, round_key : &[u32] ) -> State {
+    for col in 0..aes_common::WORD_IN_BYTES_NUM {
+        let word = round_key[col].to_be_bytes();
+
+        for row in 0..aes_common::BLOCK_SIZE {
+            state[row][col] ^= word[row];
+        }
+    }
+
+    state
+}
+
+#[cfg(test)]
+fn to_block( text : &str ) -> [u8; BLOCK_LENGTH] {
+    let mut block = [0; BLOCK_LENGTH];
+
+    block.copy_from_slice( &hex::decode( text ).unwrap() );
+    block
+}
+
+#[test]
+fn test_round_keys() {
+
+    //FIPS 197 Appendix A.1 - A.3, the last round key
+    let key = ["2b7e151628aed2a6abf7158809cf4f3c",
+               "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+               "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4"];
+
+    let expect : [[u32; 4]; 3] = [[0xd014_f9a8, 0xc9ee_2589, 0xe13f_0cc8, 0xb663_0ca6],
+                                  [0xe98b_a06f, 0x448c_773c, 0x8ecc_7204, 0x0100_2202],
+                                  [0xfe48_90d1, 0xe618_8d0b, 0x046d_f344, 0x706c_631e]];
+
+    for i in 0..key.len() {
+        let round_keys = RoundKeys::new( &hex::decode( key[i] ).unwrap() ).unwrap();
+
+        assert_eq!( round_keys.round_key( round_keys.round_num() ), expect[i] );
+        assert_eq!( round_keys.inv_round_key( round_keys.round_num() ), expect[i] );
+    }
+
+    //FIPS 197 C.1 EQUIVALENT INVERSE CIPHER, round[ 1].ik_sch
+    let round_keys = RoundKeys::new( &hex::decode( "000102030405060708090a0b0c0d0e0f" ).unwrap() ).unwrap();
+    assert_eq!( round_keys.inv_round_key( 9 ), [0x13aa_29be, 0x9c8f_aff6, 0xf770_f580, 0x00f7_bf03] );
+
+    assert!( RoundKeys::new( &[0; 20] ).is_err() );
+}
+
+#[test]
+fn test_reference_aes() {
+
+    //FIPS 197 Appendix C Example Vectors
+    let text = "00112233445566778899aabbccddeeff";
+
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let expect = ["69c4e0d86a7b0430d8cdb78070b4c55a",
+                  "dda97ca4864cdfe06eaf70a0ec0d7191",
+                  "8ea2b7ca516745bfeafc49904b496089"];
+
+    for i in 0..key.len() {
+        let aes = ReferenceAes::new( &hex::decode( key[i] ).unwrap() ).unwrap();
+        let mut block = to_block( text );
+
+        aes.encrypt_block( &mut block );
+        assert_eq!( block, to_block( expect[i] ) );
+
+        aes.decrypt_block( &mut block );
+        assert_eq!( block, to_block( text ) );
+    }
+
+    assert_eq!( Backend::default() == Backend::AesNi, hardware_aes_available() );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_aes_cipher_backend() {
+    let key = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+
+    //NIST SP 800-38A F.1.1 ECB-AES128.Encrypt, the first block
+    let text = "6bc1bee22e409f96e93d7e117393172a";
+    let expect = "3ad77bb40d7a3660a89ecaf32466ef97";
+
+    for backend in [Backend::Reference, Backend::TTable, Backend::Bitsliced, Backend::AesNi, Backend::Masked] {
+        let aes = AesCipher::new( &key, backend ).unwrap();
+        let mut block = to_block( text );
+
+        if backend != Backend::AesNi || hardware_aes_available() {
+            assert_eq!( aes.backend(), backend );
+        }
+        else {
+            assert_eq!( aes.backend(), Backend::Bitsliced );
+        }
+
+        aes.encrypt_block( &mut block );
+        assert_eq!( block, to_block( expect ) );
+
+        aes.decrypt_block( &mut block );
+        assert_eq!( block, to_block( text ) );
+    }
+
+    assert_eq!( Backend::default() == Backend::AesNi, hardware_aes_available() );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_cipher_scope() {
+
+    //FIPS 197 Appendix C.1 and C.3
+    let key128 = "000102030405060708090a0b0c0d0e0f";
+    let key256 = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    let text = "00112233445566778899aabbccddeeff";
+
+    let outer = CipherScope::new( key128 );
+    {
+        let _inner = CipherScope::new( key256 );
+        assert_eq!( crate::aes256::encrypt( text.to_string(), key256 ), "8ea2b7ca516745bfeafc49904b496089" );
+        assert_eq!( crate::aes128::encrypt( text.to_string(), key128 ), "69c4e0d86a7b0430d8cdb78070b4c55a" );
+    }
+
+    assert!( SCOPED_CIPHER.with( | scoped | scoped.borrow().as_ref().is_some_and( | scoped | scoped.key.len() == 16 ) ) );
+    drop( outer );
+    assert!( SCOPED_CIPHER.with( | scoped | scoped.borrow().is_none() ) );
+}
\ No newline at end of file
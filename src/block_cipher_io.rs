@@ -0,0 +1,413 @@
+use std::io::{self, Read, Write};
+use hex;
+use crate::aes::Aes;
+use crate::block_cipher_mode;
+use crate::zeroize::Zeroizing;
+
+#[cfg(test)]
+use crate::aes128;
+
+const BLOCK_SIZE : usize = 16;
+
+// bytes read from the inner reader at once
+const READ_CHUNK_SIZE : usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CipherMode {
+    // PKCS#7 padding is added by finish() and removed at the end of the input
+    Cbc,
+    // CTR, CFB and OFB need no padding, and the last block may be partial
+    Ctr,
+    Cfb,
+    Ofb
+}
+
+// Encrypt everything written to it into the inner writer.
+// A partial block is kept until more data arrives, and finish() must be called to write the last block
+pub struct EncryptWriter<W : Write> {
+    inner : W,
+    state : ModeState,
+    buffer : Vec<u8>
+}
+
+impl<W : Write> EncryptWriter<W> {
+    // iv is the initial counter block in CTR mode
+    pub fn new( inner : W, key : String, mode : CipherMode, iv : String ) -> io::Result<Self> {
+        Ok( EncryptWriter { inner, state : ModeState::new( key, mode, iv )?, buffer : Vec::new() } )
+    }
+
+    // Threads for CTR mode. The other modes chain every block to the previous cipher block
+    pub fn with_threads( mut self, thread_num : usize ) -> Self {
+        self.state.thread_num = thread_num;
+        self
+    }
+
+    // Write the last block with the padding in CBC mode, and return the inner writer
+    pub fn finish( mut self ) -> io::Result<W> {
+        let rest = std::mem::take( &mut self.buffer );
+
+        if self.state.mode == CipherMode::Cbc || !rest.is_empty() {
+            self.inner.write_all( &self.state.encrypt_last( &rest ) )?;
+        }
+
+        self.inner.flush()?;
+
+        Ok( self.inner )
+    }
+}
+
+impl<W : Write> Write for EncryptWriter<W> {
+    fn write( &mut self, buf : &[u8] ) -> io::Result<usize> {
+        self.buffer.extend_from_slice( buf );
+
+        let process_len = self.buffer.len() / BLOCK_SIZE * BLOCK_SIZE;
+
+        if process_len != 0 {
+            let blocks : Vec<u8> = self.buffer.drain( ..process_len ).collect();
+            self.inner.write_all( &self.state.encrypt_blocks( &blocks ) )?;
+        }
+
+        Ok( buf.len() )
+    }
+
+    fn flush( &mut self ) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Decrypt the cipher text from the inner reader.
+// In CBC mode the last block is kept until the end of the input to remove the padding
+pub struct DecryptReader<R : Read> {
+    inner : R,
+    state : ModeState,
+    is_finished : bool,
+    // cipher text not decrypted yet, and plain text not read out yet
+    buffer : Vec<u8>,
+    plain_text : Vec<u8>,
+    plain_text_pos : usize
+}
+
+impl<R : Read> DecryptReader<R> {
+    // iv is the initial counter block in CTR mode
+    pub fn new( inner : R, key : String, mode : CipherMode, iv : String ) -> io::Result<Self> {
+        Ok( DecryptReader {
+            inner,
+            state : ModeState::new( key, mode, iv )?,
+            is_finished : false,
+            buffer : Vec::new(),
+            plain_text : Vec::new(),
+            plain_text_pos : 0
+        } )
+    }
+
+    // Threads for CBC and CTR mode. CFB and OFB chain every key stream block to the previous one
+    pub fn with_threads( mut self, thread_num : usize ) -> Self {
+        self.state.thread_num = thread_num;
+        self
+    }
+
+    fn decrypt_next( &mut self ) -> io::Result<()> {
+        let mut read_buffer = vec![0; READ_CHUNK_SIZE];
+        let read_len = self.inner.read( &mut read_buffer )?;
+
+        self.plain_text_pos = 0;
+
+        if read_len == 0 {
+            let rest = std::mem::take( &mut self.buffer );
+
+            self.plain_text = self.state.decrypt_last( &rest )?;
+            self.is_finished = true;
+            return Ok( () );
+        }
+
+        self.buffer.extend_from_slice( &read_buffer[..read_len] );
+
+        let mut process_len = self.buffer.len() / BLOCK_SIZE * BLOCK_SIZE;
+
+        if self.state.mode == CipherMode::Cbc && process_len == self.buffer.len() {
+            process_len = process_len.saturating_sub( BLOCK_SIZE );
+        }
+
+        let blocks : Vec<u8> = self.buffer.drain( ..process_len ).collect();
+        self.plain_text = self.state.decrypt_blocks( &blocks );
+
+        Ok( () )
+    }
+}
+
+impl<R : Read> Read for DecryptReader<R> {
+    fn read( &mut self, buf : &mut [u8] ) -> io::Result<usize> {
+        while self.plain_text_pos == self.plain_text.len() {
+            if self.is_finished {
+                return Ok( 0 );
+            }
+
+            self.decrypt_next()?;
+        }
+
+        let read_len = buf.len().min( self.plain_text.len() - self.plain_text_pos );
+
+        buf[..read_len].copy_from_slice( &self.plain_text[self.plain_text_pos..( self.plain_text_pos + read_len )] );
+        self.plain_text_pos += read_len;
+
+        Ok( read_len )
+    }
+}
+
+// A mode and its chaining value between pieces of text
+struct ModeState {
+    mode : CipherMode,
+    key : Zeroizing<String>,
+    cipher_func : fn( String, &str ) -> String,
+    inv_cipher_func : fn( String, &str ) -> String,
+    // IV, counter block, or the last cipher text or key stream block in hex
+    chaining_value : String,
+    thread_num : usize
+}
+
+impl ModeState {
+    fn new( key : String, mode : CipherMode, iv : String ) -> io::Result<Self> {
+        let key_size = Aes::new( key.clone() ).map_err( | e | io::Error::new( io::ErrorKind::InvalidInput, e ) )?.key_size();
+
+        if hex::decode( &iv ).map( | iv | iv.len() ) != Ok( BLOCK_SIZE ) {
+            return Err( io::Error::new( io::ErrorKind::InvalidInput, "Initialization vector must be 16 bytes in hex" ) );
+        }
+
+        Ok( ModeState { mode, key : Zeroizing::new( key ), cipher_func : key_size.cipher_func(), inv_cipher_func : key_size.inv_cipher_func(), chaining_value : iv, thread_num : 1 } )
+    }
+
+    // whole blocks only, so that the chaining value can be taken from the last block
+    fn encrypt_blocks( &mut self, blocks : &[u8] ) -> Vec<u8> {
+        let text = hex::encode( blocks );
+        let result = self.encrypt_text( text.clone() );
+
+        self.update_chaining_value( &text, &result, &result );
+
+        hex::decode( result ).expect( "Failed to convert cipher text" )
+    }
+
+    fn decrypt_blocks( &mut self, blocks : &[u8] ) -> Vec<u8> {
+        let text = hex::encode( blocks );
+        let result = self.decrypt_text( text.clone() );
+
+        self.update_chaining_value( &text, &result, &text );
+
+        hex::decode( result ).expect( "Failed to convert plain text" )
+    }
+
+    fn encrypt_last( &self, rest : &[u8] ) -> Vec<u8> {
+        let result = match self.mode {
+            CipherMode::Cbc => block_cipher_mode::encrypt_cbc_mode( hex::encode( rest ), self.key.to_string(), self.chaining_value.clone(), self.cipher_func ),
+            _ => self.encrypt_text( hex::encode( rest ) )
+        };
+
+        hex::decode( result ).expect( "Failed to convert cipher text" )
+    }
+
+    fn decrypt_last( &self, rest : &[u8] ) -> io::Result<Vec<u8>> {
+        if self.mode != CipherMode::Cbc {
+            return Ok( hex::decode( self.decrypt_text( hex::encode( rest ) ) ).expect( "Failed to convert plain text" ) );
+        }
+
+        if rest.len() != BLOCK_SIZE {
+            return Err( io::Error::new( io::ErrorKind::InvalidData, "Cipher text is not a multiple of the block size" ) );
+        }
+
+        let padded_text = hex::decode( self.decrypt_text( hex::encode( rest ) ) ).expect( "Failed to convert plain text" );
+
+        remove_padding( padded_text )
+    }
+
+    fn encrypt_text( &self, text : String ) -> String {
+        let chaining_value = self.chaining_value.clone();
+
+        match self.mode {
+            CipherMode::Cbc => block_cipher_mode::encrypt_cbc_blocks( text, &self.key, chaining_value, self.cipher_func ),
+            CipherMode::Ctr => block_cipher_mode::encrypt_ctr_mode_parallel( text, self.key.to_string(), chaining_value, self.cipher_func, self.thread_num ),
+            CipherMode::Cfb => block_cipher_mode::encrypt_cfb_mode( text, self.key.to_string(), chaining_value, self.cipher_func ),
+            CipherMode::Ofb => block_cipher_mode::encrypt_ofb_mode( text, self.key.to_string(), chaining_value, self.cipher_func )
+        }
+    }
+
+    fn decrypt_text( &self, text : String ) -> String {
+        let chaining_value = self.chaining_value.clone();
+
+        match self.mode {
+            CipherMode::Cbc => block_cipher_mode::decrypt_cbc_blocks_parallel( text, &self.key, chaining_value, self.inv_cipher_func, self.thread_num ),
+            CipherMode::Ctr => block_cipher_mode::decrypt_ctr_mode_parallel( text, self.key.to_string(), chaining_value, self.cipher_func, self.thread_num ),
+            CipherMode::Cfb => block_cipher_mode::decrypt_cfb_mode( text, self.key.to_string(), chaining_value, self.cipher_func ),
+            CipherMode::Ofb => block_cipher_mode::decrypt_ofb_mode( text, self.key.to_string(), chaining_value, self.cipher_func )
+        }
+    }
+
+    fn update_chaining_value( &mut self, input : &str, output : &str, cipher_text : &str ) {
+        let hex_block_size = BLOCK_SIZE * 2;
+
+        if input.is_empty() {
+            return;
+        }
+
+        let last_block = | text : &str | text[( text.len() - hex_block_size )..].to_string();
+
+        self.chaining_value = match self.mode {
+            CipherMode::Cbc | CipherMode::Cfb => last_block( cipher_text ),
+            CipherMode::Ctr => {
+                let counter = u128::from_str_radix( &self.chaining_value, 16 ).expect( "Failed to convert counter" );
+                format!( "{:032x}", counter.wrapping_add( ( input.len() / hex_block_size ) as u128 ) )
+            },
+            // the key stream block is the input xor the output
+            CipherMode::Ofb => {
+                let input_block = hex::decode( last_block( input ) ).expect( "Failed to convert block" );
+                let output_block = hex::decode( last_block( output ) ).expect( "Failed to convert block" );
+                hex::encode( input_block.iter().zip( output_block.iter() ).map( | ( byte1, byte2 ) | byte1 ^ byte2 ).collect::<Vec<u8>>() )
+            }
+        };
+    }
+}
+
+// PKCS#7 padding, checked before it is removed
+fn remove_padding( mut text : Vec<u8> ) -> io::Result<Vec<u8>> {
+    let padding_num = *text.last().unwrap_or( &0 ) as usize;
+
+    if padding_num == 0 || padding_num > BLOCK_SIZE || text.len() < padding_num ||
+       text[( text.len() - padding_num )..].iter().any( | &byte | byte as usize != padding_num ) {
+        return Err( io::Error::new( io::ErrorKind::InvalidData, "Invalid padding" ) );
+    }
+
+    text.truncate( text.len() - padding_num );
+
+    Ok( text )
+}
+
+// Reader giving out a few bytes at a time, to split the cipher text at every position
+#[cfg(test)]
+struct PieceReader<'a> {
+    data : &'a [u8],
+    piece_len : usize
+}
+
+#[cfg(test)]
+impl Read for PieceReader<'_> {
+    fn read( &mut self, buf : &mut [u8] ) -> io::Result<usize> {
+        let read_len = buf.len().min( self.piece_len ).min( self.data.len() );
+
+        buf[..read_len].copy_from_slice( &self.data[..read_len] );
+        self.data = &self.data[read_len..];
+
+        Ok( read_len )
+    }
+}
+
+#[test]
+fn test_encrypt_writer() {
+
+    //NIST SP 800-38A F.2.1, F.3.13, F.4.1 and F.5.1 with AES-128
+    let text = hex::decode( "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710" ).unwrap();
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+
+    let mode = [CipherMode::Cbc, CipherMode::Cfb, CipherMode::Ofb, CipherMode::Ctr];
+
+    let iv = ["000102030405060708090a0b0c0d0e0f",
+              "000102030405060708090a0b0c0d0e0f",
+              "000102030405060708090a0b0c0d0e0f",
+              "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff"];
+
+    let expect = ["7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b273bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7",
+                  "3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b26751f67a3cbb140b1808cf187a4f4dfc04b05357c5d1c0eeac4c66f9ff7f2e6",
+                  "3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed8259740051e9c5fecf64344f7a82260edcc304c6528f659c77866a510d9c1d6ae5e",
+                  "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee"];
+
+    for i in 0..mode.len() {
+        // written in uneven pieces
+        for piece_len in [1, 5, 16, 17, 64] {
+            let mut writer = EncryptWriter::new( Vec::new(), key.to_string(), mode[i], iv[i].to_string() ).unwrap();
+
+            for piece in text.chunks( piece_len ) {
+                writer.write_all( piece ).unwrap();
+            }
+
+            let cipher_text = hex::encode( writer.finish().unwrap() );
+
+            // a padding block follows the 4 blocks in CBC mode
+            assert_eq!( cipher_text[..128], *expect[i] );
+            assert_eq!( cipher_text.len(), if mode[i] == CipherMode::Cbc { 160 } else { 128 } );
+        }
+    }
+}
+
+#[test]
+fn test_decrypt_reader() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let iv = "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff";
+    let text : Vec<u8> = ( 0..70 ).collect();
+
+    for mode in [CipherMode::Cbc, CipherMode::Ctr, CipherMode::Cfb, CipherMode::Ofb] {
+        for text_len in [0, 1, 15, 16, 17, 32, 70] {
+            let mut writer = EncryptWriter::new( Vec::new(), key.to_string(), mode, iv.to_string() ).unwrap();
+            writer.write_all( &text[..text_len] ).unwrap();
+            let cipher_text = writer.finish().unwrap();
+
+            // the same as the whole text at once
+            let expect = match mode {
+                CipherMode::Cbc => block_cipher_mode::encrypt_cbc_mode( hex::encode( &text[..text_len] ), key.to_string(), iv.to_string(), aes128::encrypt ),
+                CipherMode::Ctr => block_cipher_mode::encrypt_ctr_mode( hex::encode( &text[..text_len] ), key.to_string(), iv.to_string(), aes128::encrypt ),
+                CipherMode::Cfb => block_cipher_mode::encrypt_cfb_mode( hex::encode( &text[..text_len] ), key.to_string(), iv.to_string(), aes128::encrypt ),
+                CipherMode::Ofb => block_cipher_mode::encrypt_ofb_mode( hex::encode( &text[..text_len] ), key.to_string(), iv.to_string(), aes128::encrypt )
+            };
+            assert_eq!( hex::encode( &cipher_text ), expect );
+
+            for piece_len in [1, 7, 16, 100] {
+                let input = PieceReader { data : &cipher_text, piece_len };
+                let mut reader = DecryptReader::new( input, key.to_string(), mode, iv.to_string() ).unwrap();
+                let mut plain_text = Vec::new();
+
+                reader.read_to_end( &mut plain_text ).unwrap();
+                assert_eq!( plain_text, &text[..text_len] );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decrypt_reader_error() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let iv = "000102030405060708090a0b0c0d0e0f";
+
+    let mut writer = EncryptWriter::new( Vec::new(), key.to_string(), CipherMode::Cbc, iv.to_string() ).unwrap();
+    writer.write_all( b"padding check" ).unwrap();
+    let cipher_text = writer.finish().unwrap();
+
+    let mut plain_text = Vec::new();
+
+    // the wrong IV changes the padding of a single block
+    let mut reader = DecryptReader::new( cipher_text.as_slice(), key.to_string(), CipherMode::Cbc, "000102030405060708090a0b0c0d0e00".to_string() ).unwrap();
+    assert_eq!( reader.read_to_end( &mut plain_text ).unwrap_err().kind(), io::ErrorKind::InvalidData );
+
+    let mut reader = DecryptReader::new( &cipher_text[..15], key.to_string(), CipherMode::Cbc, iv.to_string() ).unwrap();
+    assert_eq!( reader.read_to_end( &mut plain_text ).unwrap_err().kind(), io::ErrorKind::InvalidData );
+
+    assert!( EncryptWriter::new( Vec::new(), key.to_string(), CipherMode::Ctr, "0001".to_string() ).is_err() );
+    assert!( DecryptReader::new( cipher_text.as_slice(), "0001".to_string(), CipherMode::Ofb, iv.to_string() ).is_err() );
+}
+
+#[test]
+fn test_threads() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let iv = "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff";
+    let text : Vec<u8> = ( 0..5000 ).map( | i | ( i * 7 ) as u8 ).collect();
+
+    for mode in [CipherMode::Cbc, CipherMode::Ctr, CipherMode::Cfb, CipherMode::Ofb] {
+        let mut writer = EncryptWriter::new( Vec::new(), key.to_string(), mode, iv.to_string() ).unwrap();
+        writer.write_all( &text ).unwrap();
+        let expect = writer.finish().unwrap();
+
+        let mut writer = EncryptWriter::new( Vec::new(), key.to_string(), mode, iv.to_string() ).unwrap().with_threads( 4 );
+        writer.write_all( &text ).unwrap();
+        assert_eq!( writer.finish().unwrap(), expect );
+
+        let mut reader = DecryptReader::new( expect.as_slice(), key.to_string(), mode, iv.to_string() ).unwrap().with_threads( 4 );
+        let mut plain_text = Vec::new();
+        reader.read_to_end( &mut plain_text ).unwrap();
+        assert_eq!( plain_text, text );
+    }
+}
\ No newline at end of file
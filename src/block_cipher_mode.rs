@@ -1,62 +1,266 @@
 use hex;
 use std::cmp;
+use std::thread;
+use crate::aes_common;
+use crate::block_cipher::CipherScope;
+use crate::zeroize::Zeroizing;
+
+#[cfg(test)]
+use crate::aes128;
 
 const CIPHER_BLOCK_SIZE : usize = 32;
 
-pub fn encrypt_ecb_mode( plain_text : String, key : String, cipher_func : fn( String, String ) -> String ) -> String {
-    let text = add_padding( plain_text );
+// fewer blocks than this for each thread are not worth spawning it
+const MIN_BLOCKS_PER_THREAD : usize = 64;
+
+pub fn encrypt_ecb_mode( plain_text : String, key : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+
+    encrypt_ecb_blocks( add_padding( plain_text ), &key, cipher_func )
+}
+
+pub fn encrypt_cbc_mode( plain_text : String, key : String, iv : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+
+    encrypt_cbc_blocks( add_padding( plain_text ), &key, iv, cipher_func )
+}
+
+pub fn decrypt_ecb_mode( cipher_text : String, key : String, inv_cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+
+    remove_padding( decrypt_ecb_blocks( cipher_text, &key, inv_cipher_func ) )
+}
+
+pub fn decrypt_cbc_mode( cipher_text : String, key : String, iv : String, inv_cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+
+    remove_padding( decrypt_cbc_blocks( cipher_text, &key, iv, inv_cipher_func ) )
+}
+
+// ECB encryption without padding, for text that is a multiple of the block size
+pub fn encrypt_ecb_blocks( text : String, key : &str, cipher_func : fn( String, &str ) -> String ) -> String {
+    let _cipher_scope = CipherScope::new( key );
     let input_blocks = divide_blocks( text );
     let mut output_blocks : Vec<String> = Vec::new();
 
-    for i in 0..input_blocks.len() {
-        output_blocks.push( cipher_func( input_blocks[ i ].clone(), key.clone() ) );
+    for input_block in input_blocks {
+        output_blocks.push( cipher_func( input_block, key ) );
     }
 
     output_blocks.join( "" )
 }
 
-pub fn encrypt_cbc_mode( plain_text : String, key : String, iv : String, cipher_func : fn( String, String ) -> String ) -> String {
-    let text = add_padding( plain_text );
+// CBC encryption without padding. The last cipher block is the IV of the following text
+pub fn encrypt_cbc_blocks( text : String, key : &str, iv : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let _cipher_scope = CipherScope::new( key );
     let input_blocks = divide_blocks( text );
     let mut output_blocks : Vec<String> = Vec::new();
     let mut next_xor_text = iv;
 
     for i in 0..input_blocks.len() {
         let input_text = xor_text( input_blocks[i].clone(), next_xor_text );
-        output_blocks.push( cipher_func( input_text, key.clone() ) );
+        output_blocks.push( cipher_func( input_text, key ) );
         next_xor_text = output_blocks[ i ].clone();
     }
 
     output_blocks.join( "" )
 }
 
-pub fn decrypt_ecb_mode( cipher_text : String, key : String, inv_cipher_func : fn( String, String ) -> String ) -> String {
+// ECB decryption without removing the padding
+pub fn decrypt_ecb_blocks( cipher_text : String, key : &str, inv_cipher_func : fn( String, &str ) -> String ) -> String {
+    let _cipher_scope = CipherScope::new( key );
     let input_blocks = divide_blocks( cipher_text );
     let mut output_blocks : Vec<String> = Vec::new();
 
-    for i in 0..input_blocks.len() {
-        output_blocks.push( inv_cipher_func( input_blocks[ i ].clone(), key.clone() ) );
+    for input_block in input_blocks {
+        output_blocks.push( inv_cipher_func( input_block, key ) );
     }
 
-    remove_padding( output_blocks.join( "" ) )
+    output_blocks.join( "" )
 }
 
-pub fn decrypt_cbc_mode( cipher_text : String, key : String, iv : String, inv_cipher_func : fn( String, String ) -> String ) -> String {
+// CBC decryption without removing the padding. The last input block is the IV of the following text
+pub fn decrypt_cbc_blocks( cipher_text : String, key : &str, iv : String, inv_cipher_func : fn( String, &str ) -> String ) -> String {
+    let _cipher_scope = CipherScope::new( key );
     let input_blocks = divide_blocks( cipher_text );
     let mut output_blocks : Vec<String> = Vec::new();
     let mut next_xor_text = iv;
 
-    for i in 0..input_blocks.len() {
-        let inv_cipher_text = inv_cipher_func( input_blocks[ i ].clone(), key.clone() );
+    for input_block in input_blocks {
+        let inv_cipher_text = inv_cipher_func( input_block.clone(), key );
         
         output_blocks.push( xor_text( inv_cipher_text, next_xor_text ) );
-        next_xor_text = input_blocks[ i ].clone();
+        next_xor_text = input_block;
+    }
+
+    output_blocks.join( "" )
+}
+
+// ECB encryption, CBC decryption and CTR have independent blocks, so these functions split the text
+// into one run of blocks for each of thread_num threads. The output is the same as the serial functions
+pub fn encrypt_ecb_mode_parallel( plain_text : String, key : String, cipher_func : fn( String, &str ) -> String, thread_num : usize ) -> String {
+    let key = Zeroizing::new( key );
+
+    encrypt_ecb_blocks_parallel( add_padding( plain_text ), &key, cipher_func, thread_num )
+}
+
+pub fn decrypt_ecb_mode_parallel( cipher_text : String, key : String, inv_cipher_func : fn( String, &str ) -> String, thread_num : usize ) -> String {
+    let key = Zeroizing::new( key );
+
+    remove_padding( decrypt_ecb_blocks_parallel( cipher_text, &key, inv_cipher_func, thread_num ) )
+}
+
+pub fn decrypt_cbc_mode_parallel( cipher_text : String, key : String, iv : String, inv_cipher_func : fn( String, &str ) -> String,
+                                  thread_num : usize ) -> String {
+    let key = Zeroizing::new( key );
+
+    remove_padding( decrypt_cbc_blocks_parallel( cipher_text, &key, iv, inv_cipher_func, thread_num ) )
+}
+
+pub fn encrypt_ecb_blocks_parallel( text : String, key : &str, cipher_func : fn( String, &str ) -> String, thread_num : usize ) -> String {
+    split_runs_parallel( &text, thread_num, | _, run | encrypt_ecb_blocks( run.to_string(), key, cipher_func ) )
+}
+
+pub fn decrypt_ecb_blocks_parallel( cipher_text : String, key : &str, inv_cipher_func : fn( String, &str ) -> String, thread_num : usize ) -> String {
+    split_runs_parallel( &cipher_text, thread_num, | _, run | decrypt_ecb_blocks( run.to_string(), key, inv_cipher_func ) )
+}
+
+pub fn decrypt_cbc_blocks_parallel( cipher_text : String, key : &str, iv : String, inv_cipher_func : fn( String, &str ) -> String,
+                                    thread_num : usize ) -> String {
+    split_runs_parallel( &cipher_text, thread_num, | start, run | {
+        // the IV of a run is the cipher block before it
+        let run_iv = if start == 0 { iv.clone() } else { cipher_text[( start - CIPHER_BLOCK_SIZE )..start].to_string() };
+
+        decrypt_cbc_blocks( run.to_string(), key, run_iv, inv_cipher_func )
+    } )
+}
+
+pub fn encrypt_ctr_mode_parallel( text : String, key : String, initial_counter : String, cipher_func : fn( String, &str ) -> String,
+                                  thread_num : usize ) -> String {
+    let key = Zeroizing::new( key );
+    let counter = u128::from_str_radix( &initial_counter, 16 ).expect( "Failed to convert initial counter" );
+
+    split_runs_parallel( &text, thread_num, | start, run | {
+        let run_counter = counter.wrapping_add( ( start / CIPHER_BLOCK_SIZE ) as u128 );
+
+        encrypt_ctr_blocks( run.to_string(), &key, run_counter, cipher_func )
+    } )
+}
+
+pub fn decrypt_ctr_mode_parallel( cipher_text : String, key : String, initial_counter : String, cipher_func : fn( String, &str ) -> String,
+                                  thread_num : usize ) -> String {
+    encrypt_ctr_mode_parallel( cipher_text, key, initial_counter, cipher_func, thread_num )
+}
+
+// Generate a random IV and prepend it to the cipher text
+pub fn encrypt_cbc_mode_with_random_iv( plain_text : String, key : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let iv = aes_common::generate_random_text( CIPHER_BLOCK_SIZE / 2 );
+    let cipher_text = encrypt_cbc_mode( plain_text, key, iv.clone(), cipher_func );
+
+    iv + &cipher_text
+}
+
+// Take the IV from the first block of the cipher text
+pub fn decrypt_cbc_mode_with_prepended_iv( cipher_text : String, key : String, inv_cipher_func : fn( String, &str ) -> String ) -> String {
+    assert!( cipher_text.len() >= CIPHER_BLOCK_SIZE * 2, "Cipher text is too short to contain an initialization vector" );
+
+    let ( iv, text ) = cipher_text.split_at( CIPHER_BLOCK_SIZE );
+
+    decrypt_cbc_mode( text.to_string(), key, iv.to_string(), inv_cipher_func )
+}
+
+// CTR mode(SP 800-38A 6.5) needs no padding, and the last block may be partial.
+// The counter block is incremented as a 128-bit big-endian integer
+pub fn encrypt_ctr_mode( text : String, key : String, initial_counter : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+    let counter = u128::from_str_radix( &initial_counter, 16 ).expect( "Failed to convert initial counter" );
+
+    encrypt_ctr_blocks( text, &key, counter, cipher_func )
+}
+
+// CTR mode from a counter that is already parsed, shared by the serial and the parallel functions
+fn encrypt_ctr_blocks( text : String, key : &str, initial_counter : u128, cipher_func : fn( String, &str ) -> String ) -> String {
+    let _cipher_scope = CipherScope::new( key );
+    let input_blocks = divide_blocks( text );
+    let mut output_blocks : Vec<String> = Vec::new();
+    let mut counter = initial_counter;
+
+    for input_block in input_blocks {
+        let mut key_stream = cipher_func( format!( "{:032x}", counter ), key );
+
+        key_stream.truncate( input_block.len() );
+        output_blocks.push( xor_text( input_block, key_stream ) );
+        counter = counter.wrapping_add( 1 );
+    }
+
+    output_blocks.join( "" )
+}
+
+// CTR decryption is the same operation as the encryption, with the cipher function
+pub fn decrypt_ctr_mode( cipher_text : String, key : String, initial_counter : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    encrypt_ctr_mode( cipher_text, key, initial_counter, cipher_func )
+}
+
+// CFB mode with 128-bit segments(SP 800-38A 6.3). The last block may be partial
+pub fn encrypt_cfb_mode( plain_text : String, key : String, iv : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+    let _cipher_scope = CipherScope::new( &key );
+    let input_blocks = divide_blocks( plain_text );
+    let mut output_blocks : Vec<String> = Vec::new();
+    let mut next_input = iv;
+
+    for i in 0..input_blocks.len() {
+        let mut key_stream = cipher_func( next_input, &key );
+
+        key_stream.truncate( input_blocks[ i ].len() );
+        output_blocks.push( xor_text( input_blocks[ i ].clone(), key_stream ) );
+        next_input = output_blocks[ i ].clone();
+    }
+
+    output_blocks.join( "" )
+}
+
+// CFB decryption also uses the cipher function
+pub fn decrypt_cfb_mode( cipher_text : String, key : String, iv : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+    let _cipher_scope = CipherScope::new( &key );
+    let input_blocks = divide_blocks( cipher_text );
+    let mut output_blocks : Vec<String> = Vec::new();
+    let mut next_input = iv;
+
+    for input_block in input_blocks {
+        let mut key_stream = cipher_func( next_input, &key );
+
+        key_stream.truncate( input_block.len() );
+        output_blocks.push( xor_text( input_block.clone(), key_stream ) );
+        next_input = input_block;
+    }
+
+    output_blocks.join( "" )
+}
+
+// OFB mode(SP 800-38A 6.4). The last block may be partial
+pub fn encrypt_ofb_mode( text : String, key : String, iv : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+    let _cipher_scope = CipherScope::new( &key );
+    let input_blocks = divide_blocks( text );
+    let mut output_blocks : Vec<String> = Vec::new();
+    let mut key_stream = iv;
+
+    for i in 0..input_blocks.len() {
+        key_stream = cipher_func( key_stream, &key );
+        output_blocks.push( xor_text( input_blocks[ i ].clone(), key_stream[..input_blocks[ i ].len()].to_string() ) );
     }
 
-    remove_padding( output_blocks.join( "" ) )
+    output_blocks.join( "" )
+}
+
+// OFB decryption is the same operation as the encryption, with the cipher function
+pub fn decrypt_ofb_mode( cipher_text : String, key : String, iv : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    encrypt_ofb_mode( cipher_text, key, iv, cipher_func )
 }
 
-fn add_padding( text : String ) -> String {
+pub fn add_padding( text : String ) -> String {
     let padding_num = ( CIPHER_BLOCK_SIZE - ( text.len() % CIPHER_BLOCK_SIZE ) ) / 2;
     let padding_text = format!( "{:02x}", padding_num );
     let padded_text = format!( "{}{}", text, padding_text.repeat( padding_num ) );
@@ -64,7 +268,7 @@ fn add_padding( text : String ) -> String {
     padded_text
 }
 
-fn remove_padding( text : String ) -> String {
+pub fn remove_padding( text : String ) -> String {
     let padding_last_str = text.chars().rev().take( 2 ).collect::<String>().chars().rev().collect::<String>();
     let padding_num = hex::decode( padding_last_str ).expect( "Failed to convert padding num" )[0];
 
@@ -91,6 +295,32 @@ fn divide_blocks( text : String ) -> Vec<String> {
     blocks
 }
 
+// Call run_func with the start of each run in text and the run, on a thread for each run, and join the outputs in order.
+// Every run but the last is whole blocks, and short text stays on the calling thread
+fn split_runs_parallel( text : &str, thread_num : usize, run_func : impl Fn( usize, &str ) -> String + Sync ) -> String {
+    let block_num = text.len().div_ceil( CIPHER_BLOCK_SIZE );
+    let worker_num = thread_num.clamp( 1, ( block_num / MIN_BLOCKS_PER_THREAD ).max( 1 ) );
+
+    if worker_num == 1 {
+        return run_func( 0, text );
+    }
+
+    let run_len = block_num.div_ceil( worker_num ) * CIPHER_BLOCK_SIZE;
+    let run_func = &run_func;
+
+    thread::scope( | scope | {
+        let handles : Vec<_> = ( 0..text.len() )
+            .step_by( run_len )
+            .map( | start | {
+                let run = &text[start..cmp::min( start + run_len, text.len() )];
+                scope.spawn( move || run_func( start, run ) )
+            } )
+            .collect();
+
+        handles.into_iter().map( | handle | handle.join().expect( "Block cipher worker thread panicked" ) ).collect()
+    } )
+}
+
 fn xor_text( text1 : String, text2 : String ) -> String {
     assert!( text1.len() == text2.len() );
 
@@ -191,4 +421,105 @@ fn test_remove_padding() {
         let actual = remove_padding( input[i].to_string() );
         assert_eq!( actual, expect[i].to_string() );
     }
+}
+
+#[test]
+fn test_cbc_mode_with_random_iv() {
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+
+    let cipher_text = encrypt_cbc_mode_with_random_iv( text.to_string(), key.to_string(), aes128::encrypt );
+    let ( iv, body ) = cipher_text.split_at( CIPHER_BLOCK_SIZE );
+
+    assert_eq!( cipher_text.len(), CIPHER_BLOCK_SIZE + text.len() + CIPHER_BLOCK_SIZE );
+    assert_eq!( body, encrypt_cbc_mode( text.to_string(), key.to_string(), iv.to_string(), aes128::encrypt ) );
+    assert_eq!( decrypt_cbc_mode_with_prepended_iv( cipher_text.clone(), key.to_string(), aes128::decrypt ), text );
+
+    // a fresh IV for every encryption
+    assert_ne!( encrypt_cbc_mode_with_random_iv( text.to_string(), key.to_string(), aes128::encrypt ), cipher_text );
+}
+
+#[test]
+fn test_cbc_blocks_chaining() {
+
+    //NIST SP 800-38A F.2.1 CBC-AES128.Encrypt
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let iv = "000102030405060708090a0b0c0d0e0f";
+    let expect = "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b273bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7";
+
+    let first_half = encrypt_cbc_blocks( text[..64].to_string(), key, iv.to_string(), aes128::encrypt );
+    let second_half = encrypt_cbc_blocks( text[64..].to_string(), key, first_half[32..].to_string(), aes128::encrypt );
+    assert_eq!( first_half + &second_half, expect );
+
+    let first_half = decrypt_cbc_blocks( expect[..64].to_string(), key, iv.to_string(), aes128::decrypt );
+    let second_half = decrypt_cbc_blocks( expect[64..].to_string(), key, expect[32..64].to_string(), aes128::decrypt );
+    assert_eq!( first_half + &second_half, text );
+}
+
+#[test]
+fn test_ctr_mode() {
+
+    //NIST SP 800-38A F.5.1 CTR-AES128.Encrypt
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let initial_counter = "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff";
+    let expect = "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee";
+
+    assert_eq!( encrypt_ctr_mode( text.to_string(), key.to_string(), initial_counter.to_string(), aes128::encrypt ), expect );
+    assert_eq!( decrypt_ctr_mode( expect.to_string(), key.to_string(), initial_counter.to_string(), aes128::encrypt ), text );
+
+    // a partial last block uses the head of the key stream
+    assert_eq!( encrypt_ctr_mode( text[..40].to_string(), key.to_string(), initial_counter.to_string(), aes128::encrypt ), expect[..40] );
+
+    // the counter wraps around at 2^128
+    let wrapped = encrypt_ctr_mode( text[32..64].to_string(), key.to_string(), "00000000000000000000000000000000".to_string(), aes128::encrypt );
+    let expect_wrapped = encrypt_ctr_mode( text[..64].to_string(), key.to_string(), "ffffffffffffffffffffffffffffffff".to_string(), aes128::encrypt );
+    assert_eq!( wrapped, expect_wrapped[32..] );
+}
+
+#[test]
+fn test_cfb_ofb_mode() {
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let iv = "000102030405060708090a0b0c0d0e0f";
+
+    //NIST SP 800-38A F.3.13 CFB128-AES128.Encrypt
+    let expect_cfb = "3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b26751f67a3cbb140b1808cf187a4f4dfc04b05357c5d1c0eeac4c66f9ff7f2e6";
+
+    //NIST SP 800-38A F.4.1 OFB-AES128.Encrypt
+    let expect_ofb = "3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed8259740051e9c5fecf64344f7a82260edcc304c6528f659c77866a510d9c1d6ae5e";
+
+    assert_eq!( encrypt_cfb_mode( text.to_string(), key.to_string(), iv.to_string(), aes128::encrypt ), expect_cfb );
+    assert_eq!( decrypt_cfb_mode( expect_cfb.to_string(), key.to_string(), iv.to_string(), aes128::encrypt ), text );
+    assert_eq!( encrypt_ofb_mode( text.to_string(), key.to_string(), iv.to_string(), aes128::encrypt ), expect_ofb );
+    assert_eq!( decrypt_ofb_mode( expect_ofb.to_string(), key.to_string(), iv.to_string(), aes128::encrypt ), text );
+
+    // a partial last block uses the head of the key stream
+    assert_eq!( encrypt_cfb_mode( text[..40].to_string(), key.to_string(), iv.to_string(), aes128::encrypt ), expect_cfb[..40] );
+    assert_eq!( decrypt_ofb_mode( expect_ofb[..40].to_string(), key.to_string(), iv.to_string(), aes128::encrypt ), text[..40] );
+}
+
+#[test]
+fn test_parallel_mode() {
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let iv = "000102030405060708090a0b0c0d0e0f";
+    let text : String = ( 0..5000 ).map( | i | format!( "{:02x}", ( i * 7 ) % 256 ) ).collect();
+
+    let ecb_expect = encrypt_ecb_mode( text.clone(), key.to_string(), aes128::encrypt );
+    let cbc_expect = encrypt_cbc_mode( text.clone(), key.to_string(), iv.to_string(), aes128::encrypt );
+    let ctr_expect = encrypt_ctr_mode( text.clone(), key.to_string(), iv.to_string(), aes128::encrypt );
+
+    // the counter also wraps around in the middle of a run
+    let wrapping_counter = "fffffffffffffffffffffffffffffff0";
+    let ctr_wrapped_expect = encrypt_ctr_mode( text.clone(), key.to_string(), wrapping_counter.to_string(), aes128::encrypt );
+
+    for thread_num in [1, 2, 3, 8, 100] {
+        assert_eq!( encrypt_ecb_mode_parallel( text.clone(), key.to_string(), aes128::encrypt, thread_num ), ecb_expect );
+        assert_eq!( decrypt_ecb_mode_parallel( ecb_expect.clone(), key.to_string(), aes128::decrypt, thread_num ), text );
+        assert_eq!( decrypt_cbc_mode_parallel( cbc_expect.clone(), key.to_string(), iv.to_string(), aes128::decrypt, thread_num ), text );
+        assert_eq!( encrypt_ctr_mode_parallel( text.clone(), key.to_string(), iv.to_string(), aes128::encrypt, thread_num ), ctr_expect );
+        assert_eq!( decrypt_ctr_mode_parallel( ctr_expect.clone(), key.to_string(), iv.to_string(), aes128::encrypt, thread_num ), text );
+        assert_eq!( encrypt_ctr_mode_parallel( text.clone(), key.to_string(), wrapping_counter.to_string(), aes128::encrypt, thread_num ), ctr_wrapped_expect );
+    }
 }
\ No newline at end of file
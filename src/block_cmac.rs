@@ -0,0 +1,139 @@
+use crate::block_cipher::{BlockCipher, BLOCK_LENGTH};
+use crate::zeroize::Zeroize;
+
+#[cfg(test)]
+use hex;
+#[cfg(test)]
+use crate::block_cipher::ReferenceAes;
+
+const SUBKEY_GEN_R128 : [u8; BLOCK_LENGTH] = [0x00, 0x00, 0x00, 0x00,
+                                              0x00, 0x00, 0x00, 0x00,
+                                              0x00, 0x00, 0x00, 0x00,
+                                              0x00, 0x00, 0x00, 0x87];
+
+// CMAC(SP 800-38B) on bytes with any BlockCipher. It allocates nothing, so it is available without std.
+// The text can be given in pieces of any length
+pub struct Cmac<C : BlockCipher> {
+    cipher : C,
+    subkey : ( [u8; BLOCK_LENGTH], [u8; BLOCK_LENGTH] ),
+    state : [u8; BLOCK_LENGTH],
+    // the last block is kept until finalize because it depends on the subkey
+    buffer : [u8; BLOCK_LENGTH],
+    buffer_len : usize
+}
+
+impl<C : BlockCipher> Cmac<C> {
+    pub fn new( cipher : C ) -> Self {
+        let mut l = [0; BLOCK_LENGTH];
+        cipher.encrypt_block( &mut l );
+
+        let k1 = double_block( l );
+        let k2 = double_block( k1 );
+        l.zeroize();
+
+        Cmac { cipher, subkey : ( k1, k2 ), state : [0; BLOCK_LENGTH], buffer : [0; BLOCK_LENGTH], buffer_len : 0 }
+    }
+
+    pub fn update( &mut self, mut data : &[u8] ) {
+        while !data.is_empty() {
+            if self.buffer_len == BLOCK_LENGTH {
+                xor_block( &mut self.state, &self.buffer );
+                self.cipher.encrypt_block( &mut self.state );
+                self.buffer_len = 0;
+            }
+
+            let length = ( BLOCK_LENGTH - self.buffer_len ).min( data.len() );
+
+            self.buffer[self.buffer_len..( self.buffer_len + length )].copy_from_slice( &data[..length] );
+            self.buffer_len += length;
+            data = &data[length..];
+        }
+    }
+
+    pub fn finalize( mut self ) -> [u8; BLOCK_LENGTH] {
+        let mut last_block = [0; BLOCK_LENGTH];
+        last_block[..self.buffer_len].copy_from_slice( &self.buffer[..self.buffer_len] );
+
+        if self.buffer_len == BLOCK_LENGTH {
+            xor_block( &mut last_block, &self.subkey.0 );
+        }
+        else {
+            last_block[self.buffer_len] = 0x80;
+            xor_block( &mut last_block, &self.subkey.1 );
+        }
+
+        xor_block( &mut self.state, &last_block );
+        self.cipher.encrypt_block( &mut self.state );
+
+        self.state
+    }
+}
+
+// The subkeys are derived from the key, so they are wiped like the round keys
+impl<C : BlockCipher> Drop for Cmac<C> {
+    fn drop( &mut self ) {
+        self.subkey.0.zeroize();
+        self.subkey.1.zeroize();
+        self.buffer.zeroize();
+    }
+}
+
+// multiplication by x in GF(2^128)
+pub fn double_block( input : [u8; BLOCK_LENGTH] ) -> [u8; BLOCK_LENGTH] {
+    let mut output = ( u128::from_be_bytes( input ) << 1 ).to_be_bytes();
+
+    if input[0] & 0x80 != 0x00 {
+        xor_block( &mut output, &SUBKEY_GEN_R128 );
+    }
+
+    output
+}
+
+fn xor_block( block : &mut [u8; BLOCK_LENGTH], input : &[u8; BLOCK_LENGTH] ) {
+    for ( x, y ) in block.iter_mut().zip( input.iter() ) {
+        *x ^= *y;
+    }
+}
+
+#[test]
+fn test_block_cmac() {
+    //NIST Special Publication 800-38B Appendix D: Examples
+    //CMAC-AES128 Example #1 to #4
+    let key = hex::decode( "2b7e151628aed2a6abf7158809cf4f3c" ).unwrap();
+    let text = hex::decode( "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710" ).unwrap();
+
+    let text_length = [0, 16, 40, 64];
+
+    let expect = ["bb1d6929e95937287fa37d129b756746",
+                  "070a16b46b4d4144f79bdd9dd04a287c",
+                  "dfa66747de9ae63030ca32611497c827",
+                  "51f0bebf7e3b9d92fc49741779363cfe"];
+
+    let aes = ReferenceAes::new( &key ).unwrap();
+
+    for i in 0..expect.len() {
+        for piece_len in [1, 15, 16, 17, 64] {
+            let mut cmac = Cmac::new( &aes );
+
+            for piece in text[..text_length[i]].chunks( piece_len ) {
+                cmac.update( piece );
+            }
+
+            assert_eq!( hex::encode( cmac.finalize() ), expect[i] );
+        }
+    }
+}
+
+#[test]
+fn test_double_block() {
+    //NIST Special Publication 800-38B Appendix D: Examples
+    //K1 and K2 of AES-128
+    let l = hex::decode( "7df76b0c1ab899b33e42f047b91b546f" ).unwrap();
+    let mut block = [0; BLOCK_LENGTH];
+    block.copy_from_slice( &l );
+
+    let k1 = double_block( block );
+
+    assert_eq!( hex::encode( k1 ), "fbeed618357133667c85e08f7236a8de" );
+    assert_eq!( hex::encode( double_block( k1 ) ), "f7ddac306ae266ccf90bc11ee46d513b" );
+}
\ No newline at end of file
@@ -0,0 +1,284 @@
+use core::convert::TryInto;
+use crate::block_cipher::{BlockCipher, BLOCK_LENGTH};
+
+#[cfg(all(test, feature = "std"))]
+use hex;
+#[cfg(test)]
+use crate::block_cipher::ReferenceAes;
+#[cfg(all(test, feature = "std"))]
+use crate::block_cipher::{AesCipher, Backend};
+
+// counter blocks given to the cipher at once in CTR mode
+const PARALLEL_BLOCK_NUM : usize = 8;
+
+// In-place encryption and decryption that allocate nothing.
+// The chaining value is kept between calls, so a long text can be given in pieces of whole blocks.
+pub trait BlockMode {
+    fn encrypt_blocks( &mut self, data : &mut [u8] );
+    fn decrypt_blocks( &mut self, data : &mut [u8] );
+}
+
+pub struct Ecb<C : BlockCipher> {
+    cipher : C
+}
+
+pub struct Cbc<C : BlockCipher> {
+    cipher : C,
+    iv : [u8; BLOCK_LENGTH]
+}
+
+// The counter block is incremented as a 128-bit big-endian integer
+pub struct Ctr<C : BlockCipher> {
+    cipher : C,
+    counter : [u8; BLOCK_LENGTH]
+}
+
+pub struct Cfb<C : BlockCipher> {
+    cipher : C,
+    iv : [u8; BLOCK_LENGTH]
+}
+
+pub struct Ofb<C : BlockCipher> {
+    cipher : C,
+    key_stream : [u8; BLOCK_LENGTH]
+}
+
+impl<C : BlockCipher> Ecb<C> {
+    pub fn new( cipher : C ) -> Self {
+        Ecb { cipher }
+    }
+}
+
+impl<C : BlockCipher> Cbc<C> {
+    pub fn new( cipher : C, iv : [u8; BLOCK_LENGTH] ) -> Self {
+        Cbc { cipher, iv }
+    }
+}
+
+impl<C : BlockCipher> Ctr<C> {
+    pub fn new( cipher : C, initial_counter : [u8; BLOCK_LENGTH] ) -> Self {
+        Ctr { cipher, counter : initial_counter }
+    }
+}
+
+impl<C : BlockCipher> Cfb<C> {
+    pub fn new( cipher : C, iv : [u8; BLOCK_LENGTH] ) -> Self {
+        Cfb { cipher, iv }
+    }
+}
+
+impl<C : BlockCipher> Ofb<C> {
+    pub fn new( cipher : C, iv : [u8; BLOCK_LENGTH] ) -> Self {
+        Ofb { cipher, key_stream : iv }
+    }
+}
+
+// ECB and CBC take whole blocks only. Padding is left to the caller
+impl<C : BlockCipher> BlockMode for Ecb<C> {
+    fn encrypt_blocks( &mut self, data : &mut [u8] ) {
+        self.cipher.encrypt_parallel( block_array( data ) );
+    }
+
+    fn decrypt_blocks( &mut self, data : &mut [u8] ) {
+        self.cipher.decrypt_parallel( block_array( data ) );
+    }
+}
+
+impl<C : BlockCipher> BlockMode for Cbc<C> {
+    fn encrypt_blocks( &mut self, data : &mut [u8] ) {
+        for block in whole_blocks( data ) {
+            xor_block( block, &self.iv );
+            self.cipher.encrypt_block( block );
+            self.iv = *block;
+        }
+    }
+
+    fn decrypt_blocks( &mut self, data : &mut [u8] ) {
+        for block in whole_blocks( data ) {
+            let cipher_block = *block;
+
+            self.cipher.decrypt_block( block );
+            xor_block( block, &self.iv );
+            self.iv = cipher_block;
+        }
+    }
+}
+
+// CTR, CFB and OFB also take a partial block, which has to be the last one
+impl<C : BlockCipher> BlockMode for Ctr<C> {
+    fn encrypt_blocks( &mut self, data : &mut [u8] ) {
+        for chunk in data.chunks_mut( BLOCK_LENGTH * PARALLEL_BLOCK_NUM ) {
+            let block_num = chunk.len().div_ceil( BLOCK_LENGTH );
+            let mut key_stream = [[0; BLOCK_LENGTH]; PARALLEL_BLOCK_NUM];
+
+            for counter_block in key_stream.iter_mut().take( block_num ) {
+                *counter_block = self.counter;
+                self.counter = ( u128::from_be_bytes( self.counter ).wrapping_add( 1 ) ).to_be_bytes();
+            }
+
+            self.cipher.encrypt_parallel( &mut key_stream[..block_num] );
+
+            for ( block, key_stream_block ) in chunk.chunks_mut( BLOCK_LENGTH ).zip( key_stream.iter() ) {
+                xor_block( block, key_stream_block );
+            }
+        }
+    }
+
+    fn decrypt_blocks( &mut self, data : &mut [u8] ) {
+        self.encrypt_blocks( data )
+    }
+}
+
+impl<C : BlockCipher> BlockMode for Cfb<C> {
+    fn encrypt_blocks( &mut self, data : &mut [u8] ) {
+        for block in data.chunks_mut( BLOCK_LENGTH ) {
+            self.cipher.encrypt_block( &mut self.iv );
+            xor_block( block, &self.iv );
+            self.iv[..block.len()].copy_from_slice( block );
+        }
+    }
+
+    fn decrypt_blocks( &mut self, data : &mut [u8] ) {
+        for block in data.chunks_mut( BLOCK_LENGTH ) {
+            let mut key_stream = self.iv;
+
+            self.cipher.encrypt_block( &mut key_stream );
+            self.iv[..block.len()].copy_from_slice( block );
+            xor_block( block, &key_stream );
+        }
+    }
+}
+
+impl<C : BlockCipher> BlockMode for Ofb<C> {
+    fn encrypt_blocks( &mut self, data : &mut [u8] ) {
+        for block in data.chunks_mut( BLOCK_LENGTH ) {
+            self.cipher.encrypt_block( &mut self.key_stream );
+            xor_block( block, &self.key_stream );
+        }
+    }
+
+    fn decrypt_blocks( &mut self, data : &mut [u8] ) {
+        self.encrypt_blocks( data )
+    }
+}
+
+fn whole_blocks( data : &mut [u8] ) -> impl Iterator<Item = &mut [u8; BLOCK_LENGTH]> {
+    assert!( data.len().is_multiple_of( BLOCK_LENGTH ), "Text is not a multiple of the block size" );
+
+    data.chunks_exact_mut( BLOCK_LENGTH ).map( | block | block.try_into().expect( "Failed to get block" ) )
+}
+
+// whole blocks as an array for the parallel functions of BlockCipher
+fn block_array( data : &mut [u8] ) -> &mut [[u8; BLOCK_LENGTH]] {
+    assert!( data.len().is_multiple_of( BLOCK_LENGTH ), "Text is not a multiple of the block size" );
+
+    data.as_chunks_mut().0
+}
+
+// xor the head of key_stream into a block that may be partial
+fn xor_block( block : &mut [u8], key_stream : &[u8; BLOCK_LENGTH] ) {
+    for ( byte, key_byte ) in block.iter_mut().zip( key_stream.iter() ) {
+        *byte ^= key_byte;
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+fn to_block( text : &str ) -> [u8; BLOCK_LENGTH] {
+    let mut block = [0; BLOCK_LENGTH];
+
+    block.copy_from_slice( &hex::decode( text ).unwrap() );
+    block
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_block_mode() {
+
+    //NIST SP 800-38A F.1.1, F.2.1, F.3.13, F.4.1 and F.5.1 with AES-128
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+    let aes = ReferenceAes::new( &hex::decode( "2b7e151628aed2a6abf7158809cf4f3c" ).unwrap() ).unwrap();
+    let iv = to_block( "000102030405060708090a0b0c0d0e0f" );
+    let initial_counter = to_block( "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff" );
+
+    // a new mode with the initial chaining value
+    let mode = | i : usize | -> Box<dyn BlockMode + '_> {
+        match i {
+            0 => Box::new( Ecb::new( &aes ) ),
+            1 => Box::new( Cbc::new( &aes, iv ) ),
+            2 => Box::new( Cfb::new( &aes, iv ) ),
+            3 => Box::new( Ofb::new( &aes, iv ) ),
+            _ => Box::new( Ctr::new( &aes, initial_counter ) )
+        }
+    };
+
+    let expect = ["3ad77bb40d7a3660a89ecaf32466ef97f5d3d58503b9699de785895a96fdbaaf43b1cd7f598ece23881b00e3ed0306887b0c785e27e8ad3f8223207104725dd4",
+                  "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b273bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7",
+                  "3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b26751f67a3cbb140b1808cf187a4f4dfc04b05357c5d1c0eeac4c66f9ff7f2e6",
+                  "3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed8259740051e9c5fecf64344f7a82260edcc304c6528f659c77866a510d9c1d6ae5e",
+                  "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee"];
+
+    for ( i, expect ) in expect.iter().enumerate() {
+        let mut data = hex::decode( text ).unwrap();
+
+        // the chaining value carries over between calls
+        let mut encryptor = mode( i );
+        let ( first_half, second_half ) = data.split_at_mut( 32 );
+        encryptor.encrypt_blocks( first_half );
+        encryptor.encrypt_blocks( second_half );
+        assert_eq!( hex::encode( &data ), *expect );
+
+        mode( i ).decrypt_blocks( &mut data );
+        assert_eq!( hex::encode( &data ), text );
+    }
+
+    // a partial last block in the stream modes
+    for ( i, expect ) in expect.iter().enumerate().skip( 2 ) {
+        let mut data = hex::decode( &text[..40] ).unwrap();
+
+        mode( i ).encrypt_blocks( &mut data );
+        assert_eq!( hex::encode( &data ), expect[..40] );
+
+        mode( i ).decrypt_blocks( &mut data );
+        assert_eq!( hex::encode( &data ), text[..40] );
+    }
+}
+
+#[test]
+#[should_panic( expected = "Text is not a multiple of the block size" )]
+fn test_block_mode_partial_block() {
+    let aes = ReferenceAes::new( &[0; 16] ).unwrap();
+
+    Cbc::new( &aes, [0; BLOCK_LENGTH] ).encrypt_blocks( &mut [0; 20] );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_block_mode_backend() {
+    let key = hex::decode( "2b7e151628aed2a6abf7158809cf4f3c" ).unwrap();
+    let reference = ReferenceAes::new( &key ).unwrap();
+    let initial_counter = to_block( "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff" );
+    let text : Vec<u8> = ( 0..200 ).map( | i | i as u8 ).collect();
+
+    let mut ecb_expect = text[..192].to_vec();
+    let mut ctr_expect = text.clone();
+    Ecb::new( &reference ).encrypt_blocks( &mut ecb_expect );
+    Ctr::new( &reference, initial_counter ).encrypt_blocks( &mut ctr_expect );
+
+    for backend in [Backend::TTable, Backend::Bitsliced, Backend::AesNi, Backend::Masked] {
+        let aes = AesCipher::new( &key, backend ).unwrap();
+
+        // more than one batch of the parallel functions
+        let mut data = text[..192].to_vec();
+        Ecb::new( &aes ).encrypt_blocks( &mut data );
+        assert_eq!( data, ecb_expect );
+        Ecb::new( &aes ).decrypt_blocks( &mut data );
+        assert_eq!( data, text[..192] );
+
+        // a partial last block
+        let mut data = text.clone();
+        Ctr::new( &aes, initial_counter ).encrypt_blocks( &mut data );
+        assert_eq!( data, ctr_expect );
+        Ctr::new( &aes, initial_counter ).decrypt_blocks( &mut data );
+        assert_eq!( data, text );
+    }
+}
\ No newline at end of file
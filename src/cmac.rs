@@ -1,80 +1,119 @@
 use std::convert::TryInto;
 use hex;
+use crate::zeroize::Zeroizing;
+use crate::block_cmac::double_block;
+use crate::block_cipher::CipherScope;
+
+use crate::aes128;
 
 #[cfg(test)]
-use crate::{aes128, aes192, aes256};
+use crate::{aes192, aes256};
 
 const BLOCK_SIZE : usize = 16;
-const SUBKEY_GEN_R128 : [u8; BLOCK_SIZE] = [0x00, 0x00, 0x00, 0x00, 
-                                            0x00, 0x00, 0x00, 0x00, 
-                                            0x00, 0x00, 0x00, 0x00, 
-                                            0x00, 0x00, 0x00, 0x87];
 
-pub fn generate_aes_cmac( plain_text : String,  key : String, cipher_func : fn( String, String ) -> String ) -> String {
-    let subkey = generate_subkey( key.clone(), cipher_func );
+pub fn generate_aes_cmac( plain_text : String,  key : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+    let _cipher_scope = CipherScope::new( &key );
+    let subkey = generate_subkey( &key, cipher_func );
     let mut blocks = text_to_blocks( plain_text.clone() );
     let block_length = blocks.len();
     let mut result = "00000000000000000000000000000000".to_string();
 
     // judge text is complte block
-    if plain_text.len() != 0 && plain_text.len() % BLOCK_SIZE == 0 {
+    if !plain_text.is_empty() && plain_text.len().is_multiple_of( BLOCK_SIZE * 2 ) {
         blocks[block_length- 1] = xor_block( subkey.0, blocks[block_length - 1] );
     }
     else {
         blocks[block_length- 1] = xor_block( subkey.1, blocks[block_length - 1] ) ; 
     }
 
-    for i in 0..block_length {
-        result = cipher_func( hex::encode( xor_block( string_to_block( result ), blocks[i] ) ), key.clone() );
+    for block in &blocks {
+        result = cipher_func( hex::encode( xor_block( string_to_block( result ), *block ) ), &key );
     }
 
     result
 }
 
-fn generate_subkey( key : String, cipher_func : fn( String, String ) -> String ) -> ( [u8; BLOCK_SIZE], [u8; BLOCK_SIZE] ) {
-    let k1 : [u8; BLOCK_SIZE];
-    let k2 : [u8; BLOCK_SIZE];
+// Incremental CMAC for text given in pieces
+pub struct Cmac {
+    key : Zeroizing<String>,
+    cipher_func : fn( String, &str ) -> String,
+    subkey : ( [u8; BLOCK_SIZE], [u8; BLOCK_SIZE] ),
+    state : [u8; BLOCK_SIZE],
+    // the last block is kept until finalize because it depends on the subkey
+    buffer : Vec<u8>
+}
 
-    let l : [u8; BLOCK_SIZE] = string_to_block( cipher_func( "00000000000000000000000000000000".to_string(), key ) );
-                
-    if l[0] & 0x80 == 0x00 {
-        k1 = left_shift_block_1bit( l );
+impl Cmac {
+    pub fn new( key : String, cipher_func : fn( String, &str ) -> String ) -> Self {
+        Cmac {
+            subkey : generate_subkey( &key, cipher_func ),
+            key : Zeroizing::new( key ),
+            cipher_func,
+            state : [0; BLOCK_SIZE],
+            buffer : Vec::new()
+        }
     }
-    else {
-        k1 = xor_block( left_shift_block_1bit( l ), SUBKEY_GEN_R128 );
+
+    pub fn update( &mut self, text : String ) {
+        self.buffer.extend( hex::decode( text ).expect( "Failed to convert text in Cmac::update" ) );
+
+        let _cipher_scope = CipherScope::new( &self.key );
+
+        while self.buffer.len() > BLOCK_SIZE {
+            let block : Vec<u8> = self.buffer.drain( ..BLOCK_SIZE ).collect();
+            self.state = self.encrypt( xor_block( self.state, block.try_into().expect( "Failed to convert block in Cmac::update" ) ) );
+        }
+    }
+
+    pub fn finalize( self ) -> String {
+        let mut last_block : [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+        last_block[..self.buffer.len()].copy_from_slice( &self.buffer );
+
+        if self.buffer.len() == BLOCK_SIZE {
+            last_block = xor_block( self.subkey.0, last_block );
+        }
+        else {
+            last_block[self.buffer.len()] = 0x80;
+            last_block = xor_block( self.subkey.1, last_block );
+        }
+
+        hex::encode( self.encrypt( xor_block( self.state, last_block ) ) )
     }
 
-    if k1[0] & 0x80 == 0x00 {
-        k2 = left_shift_block_1bit( k1 );
+    fn encrypt( &self, block : [u8; BLOCK_SIZE] ) -> [u8; BLOCK_SIZE] {
+        string_to_block( ( self.cipher_func )( hex::encode( block ), &self.key ) )
     }
-    else {
-        k2 = xor_block( left_shift_block_1bit( k1 ), SUBKEY_GEN_R128 );
+}
+
+pub fn generate_aes_cmac_prf_128( plain_text : String, key : String ) -> String {
+    // RFC 4615 Section 3: a key that is not 128 bits is compressed by AES-CMAC under the zero key
+    let prf_key = if key.len() == BLOCK_SIZE * 2 {
+        key
     }
+    else {
+        generate_aes_cmac( key, "00".repeat( BLOCK_SIZE ), aes128::encrypt )
+    };
+
+    generate_aes_cmac( plain_text, prf_key, aes128::encrypt )
+}
+
+fn generate_subkey( key : &str, cipher_func : fn( String, &str ) -> String ) -> ( [u8; BLOCK_SIZE], [u8; BLOCK_SIZE] ) {
+    let l : [u8; BLOCK_SIZE] = string_to_block( cipher_func( "00000000000000000000000000000000".to_string(), key ) );
+
+    let k1 : [u8; BLOCK_SIZE] = double_block( l );
+    let k2 : [u8; BLOCK_SIZE] = double_block( k1 );
 
     ( k1, k2 )
 }
 
-fn string_to_block( input : String ) -> [u8; BLOCK_SIZE] {
+pub(crate) fn string_to_block( input : String ) -> [u8; BLOCK_SIZE] {
   hex::decode( input ).expect( "Failed to convert key in string_to_block" )
                       .try_into()
                       .expect( "Failed to convert key in string_to_block" )
 }
 
-fn left_shift_block_1bit( input : [u8; BLOCK_SIZE] ) -> [u8; BLOCK_SIZE] {
-    let mut output: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
-
-    for i in 0..BLOCK_SIZE {
-        if ( i != 0 ) && ( input[i] & 0x80 != 0x00 ) {
-            output[i - 1] += 0x01;
-        }
-
-        output[i] = input[i] << 1;
-    }
-    
-    output
-}
-
-fn xor_block( input1 : [u8; BLOCK_SIZE], input2 : [u8; BLOCK_SIZE] ) -> [u8; BLOCK_SIZE] {
+pub(crate) fn xor_block( input1 : [u8; BLOCK_SIZE], input2 : [u8; BLOCK_SIZE] ) -> [u8; BLOCK_SIZE] {
     let mut output: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
 
     for i in 0..BLOCK_SIZE {
@@ -85,14 +124,12 @@ fn xor_block( input1 : [u8; BLOCK_SIZE], input2 : [u8; BLOCK_SIZE] ) -> [u8; BLO
 }
 
 fn text_to_blocks( text : String ) -> Vec<[u8; BLOCK_SIZE]> {
-    let block_num : usize;
-
-    if text.len() == 0 {
-        block_num = 1;
+    let block_num : usize = if text.is_empty() {
+        1
     }
     else {
-        block_num = ( ( ( text.len() as f32 ) / 2.0 ) / BLOCK_SIZE as f32 ).ceil() as usize;
-    }
+        ( ( ( text.len() as f32 ) / 2.0 ) / BLOCK_SIZE as f32 ).ceil() as usize
+    };
 
     let mut blocks : Vec<[u8; BLOCK_SIZE]> = Vec::with_capacity( block_num );
     let hex_data = hex::decode( add_padding( text, block_num * BLOCK_SIZE ) ).expect( "Failed to convert in text_to_blocks" );
@@ -111,17 +148,14 @@ fn text_to_blocks( text : String ) -> Vec<[u8; BLOCK_SIZE]> {
 }
 
 fn add_padding( input_text : String, byte_num : usize ) -> String {
-    let output_text : String;
     let text_length = input_text.len();
 
     if text_length == byte_num * 2 {
-        output_text = input_text;
+        input_text
     }
     else {
-        output_text = input_text + &"80" + &"00".repeat( byte_num - text_length / 2 - 1 ).to_string();
+        input_text + "80" + &"00".repeat( byte_num - text_length / 2 - 1 )
     }
-
-    output_text
 }
 
 #[test]
@@ -146,9 +180,63 @@ fn test_generate_subkey()
                      "95a3da06533ddb585d3533010c42a0d9"];
     
     for i in 0..key.len() {
-        let actual_result = generate_subkey( key[i].to_string(), cipher_func[i] );
+        let actual_result = generate_subkey( key[i], cipher_func[i] );
 
         assert_eq!( hex::encode( actual_result.0 ), expect_k1[i] );
         assert_eq!( hex::encode( actual_result.1 ), expect_k2[i] );
     }
+}
+
+#[test]
+fn test_generate_aes_cmac_prf_128()
+{
+    //RFC 4615 Section 4: Test Vectors
+    let text = "000102030405060708090a0b0c0d0e0f10111213";
+
+    let key = ["000102030405060708090a0b0c0d0e0fedcb",
+               "000102030405060708090a0b0c0d0e0f",
+               "00010203040506070809"];
+
+    let expect = ["84a348a4a45d235babfffc0d2b4da09a",
+                  "980ae87b5f4c9c5214f5b6a8455e4c2d",
+                  "290d9e112edb09ee141fcf64c0b72f3d"];
+
+    for i in 0..key.len() {
+        let actual_result = generate_aes_cmac_prf_128( text.to_string(), key[i].to_string() );
+        assert_eq!( actual_result, expect[i] );
+    }
+}
+
+#[test]
+fn test_generate_aes_cmac_partial_block()
+{
+    //NIST Special Publication 800-38B Appendix D: Examples
+    //CMAC-AES128 Example #3 (40 bytes)
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let expect = "dfa66747de9ae63030ca32611497c827";
+
+    assert_eq!( generate_aes_cmac( text.to_string(), key.to_string(), aes128::encrypt ), expect );
+}
+
+#[test]
+fn test_cmac_update()
+{
+    //NIST Special Publication 800-38B Appendix D: Examples
+    //CMAC-AES128 Example #4 (64 bytes)
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let expect = "51f0bebf7e3b9d92fc49741779363cfe";
+
+    for piece_len in [2, 32, 34, 64, 128] {
+        let mut cmac = Cmac::new( key.to_string(), aes128::encrypt );
+
+        for piece in text.as_bytes().chunks( piece_len ) {
+            cmac.update( String::from_utf8( piece.to_vec() ).unwrap() );
+        }
+
+        assert_eq!( cmac.finalize(), expect );
+    }
+
+    assert_eq!( Cmac::new( key.to_string(), aes128::encrypt ).finalize(), "bb1d6929e95937287fa37d129b756746" );
 }
\ No newline at end of file
@@ -0,0 +1,519 @@
+use std::error;
+use std::fmt;
+use hex;
+use crate::aes::Aes;
+use crate::aes_common::{self, KeySize};
+use crate::{block_cipher_mode, cmac, kdf};
+use crate::zeroize::Zeroizing;
+
+// Container format, all integers big-endian
+//
+// header : magic "AESC" || version(1) || algorithm id(1) || key size in bytes(1) || nonce length(1) ||
+//          nonce || chunk size(4) || AAD length(4) || AAD
+// header tag(16) : CMAC of the header
+// chunk : last chunk flag(1) || cipher text length(4) || cipher text || tag(16)
+//
+// Every chunk but the last has chunk size bytes, and nothing may follow the last chunk.
+// The chunk tag is the CMAC of header tag || chunk index(8) || last chunk flag || cipher text length || cipher text,
+// so that chunks cannot be reordered, dropped or moved to another container.
+
+pub const MAGIC : [u8; 4] = *b"AESC";
+pub const VERSION : u8 = 1;
+pub const DEFAULT_CHUNK_SIZE : usize = 64 * 1024;
+
+// limits that keep the memory for a chunk or the header bounded while parsing
+pub const MAX_CHUNK_SIZE : usize = 1 << 24;
+pub const MAX_AAD_LENGTH : usize = 1 << 16;
+
+const NONCE_LENGTH : usize = 16;
+const TAG_LENGTH : usize = 16;
+const FIXED_HEADER_LENGTH : usize = 32;
+const CHUNK_HEADER_LENGTH : usize = 5;
+
+const LAST_CHUNK : u8 = 1;
+
+// SP 800-108 label of the key derivation, "aes_rustlang container"
+const KDF_LABEL : &str = "6165735f727573746c616e6720636f6e7461696e6572";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    // AES-CTR for the payload and AES-CMAC for the tags,
+    // with both keys derived from the key and the nonce by the SP 800-108 counter mode KDF
+    CtrCmac = 1
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContainerError {
+    InvalidKey,
+    InvalidChunkSize( usize ),
+    AadTooLong( usize ),
+    InvalidMagic,
+    UnsupportedVersion( u8 ),
+    UnsupportedAlgorithm( u8 ),
+    InvalidHeader,
+    KeySizeMismatch,
+    InvalidChunk,
+    AuthenticationFailed,
+    Truncated,
+    TrailingData
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            ContainerError::InvalidKey => write!( f, "key must be 16, 24 or 32 bytes in hex" ),
+            ContainerError::InvalidChunkSize( chunk_size ) => write!( f, "chunk size must be 1 to {} bytes, but it is {} bytes", MAX_CHUNK_SIZE, chunk_size ),
+            ContainerError::AadTooLong( aad_len ) => write!( f, "AAD must be at most {} bytes, but it is {} bytes", MAX_AAD_LENGTH, aad_len ),
+            ContainerError::InvalidMagic => write!( f, "not an aes_rustlang container" ),
+            ContainerError::UnsupportedVersion( version ) => write!( f, "unsupported container version {}", version ),
+            ContainerError::UnsupportedAlgorithm( algorithm ) => write!( f, "unsupported container algorithm {}", algorithm ),
+            ContainerError::InvalidHeader => write!( f, "invalid container header" ),
+            ContainerError::KeySizeMismatch => write!( f, "key size does not match the container" ),
+            ContainerError::InvalidChunk => write!( f, "invalid container chunk" ),
+            ContainerError::AuthenticationFailed => write!( f, "container authentication failed" ),
+            ContainerError::Truncated => write!( f, "container is truncated" ),
+            ContainerError::TrailingData => write!( f, "data after the end of the container" )
+        }
+    }
+}
+
+impl error::Error for ContainerError {}
+
+pub fn seal( plain_data : &[u8], key : String, aad : &[u8] ) -> Result<Vec<u8>, ContainerError> {
+    let mut sealer = Sealer::new( key, aad, DEFAULT_CHUNK_SIZE )?;
+    let mut output = sealer.update( plain_data );
+
+    output.extend( sealer.finalize() );
+
+    Ok( output )
+}
+
+// Return the plain data and the AAD
+pub fn open( container : &[u8], key : String ) -> Result<( Vec<u8>, Vec<u8> ), ContainerError> {
+    let mut opener = Opener::new( key )?;
+    let plain_data = opener.update( container )?;
+    let aad = opener.aad().unwrap_or_default().to_vec();
+
+    opener.finalize()?;
+
+    Ok( ( plain_data, aad ) )
+}
+
+// Incremental sealing. The header comes out with the first update
+pub struct Sealer {
+    keys : ContainerKeys,
+    chunk_size : usize,
+    chunk_index : u64,
+    header_tag : Vec<u8>,
+    // output not returned yet, and plain data not sealed yet
+    output : Vec<u8>,
+    buffer : Vec<u8>
+}
+
+impl Sealer {
+    pub fn new( key : String, aad : &[u8], chunk_size : usize ) -> Result<Self, ContainerError> {
+        let nonce = hex::decode( aes_common::generate_random_text( NONCE_LENGTH ) ).expect( "Failed to convert nonce" );
+
+        Sealer::with_nonce( key, aad, chunk_size, nonce )
+    }
+
+    fn with_nonce( key : String, aad : &[u8], chunk_size : usize, nonce : Vec<u8> ) -> Result<Self, ContainerError> {
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err( ContainerError::InvalidChunkSize( chunk_size ) );
+        }
+
+        if aad.len() > MAX_AAD_LENGTH {
+            return Err( ContainerError::AadTooLong( aad.len() ) );
+        }
+
+        let key_size = Aes::new( key.clone() ).map_err( | _ | ContainerError::InvalidKey )?.key_size();
+        let keys = ContainerKeys::derive( key, key_size, &nonce );
+
+        let mut header = MAGIC.to_vec();
+        header.extend( [VERSION, Algorithm::CtrCmac as u8, key_size.key_length() as u8, NONCE_LENGTH as u8] );
+        header.extend( &nonce );
+        header.extend( ( chunk_size as u32 ).to_be_bytes() );
+        header.extend( ( aad.len() as u32 ).to_be_bytes() );
+        header.extend( aad );
+
+        let header_tag = keys.generate_tag( &header );
+
+        let mut output = header;
+        output.extend( &header_tag );
+
+        Ok( Sealer { keys, chunk_size, chunk_index : 0, header_tag, output, buffer : Vec::new() } )
+    }
+
+    pub fn update( &mut self, plain_data : &[u8] ) -> Vec<u8> {
+        self.buffer.extend_from_slice( plain_data );
+
+        // a full chunk is kept back until more data arrives, since the last chunk is marked
+        while self.buffer.len() > self.chunk_size {
+            let chunk : Vec<u8> = self.buffer.drain( ..self.chunk_size ).collect();
+            self.seal_chunk( &chunk, false );
+        }
+
+        self.output.drain( .. ).collect()
+    }
+
+    pub fn finalize( mut self ) -> Vec<u8> {
+        let chunk = std::mem::take( &mut self.buffer );
+
+        self.seal_chunk( &chunk, true );
+
+        self.output
+    }
+
+    fn seal_chunk( &mut self, chunk : &[u8], is_last : bool ) {
+        let cipher_text = self.keys.apply_key_stream( chunk, self.chunk_index );
+        let chunk_header = build_chunk_header( is_last, cipher_text.len() );
+        let tag = self.keys.generate_chunk_tag( &self.header_tag, self.chunk_index, &chunk_header, &cipher_text );
+
+        self.output.extend( chunk_header );
+        self.output.extend( cipher_text );
+        self.output.extend( tag );
+        self.chunk_index += 1;
+    }
+}
+
+// Incremental opening. Only chunks whose tag has been verified are returned
+pub struct Opener {
+    key : Zeroizing<String>,
+    key_size : KeySize,
+    // set once the header has been verified
+    header : Option<OpenedHeader>,
+    chunk_index : u64,
+    is_finished : bool,
+    buffer : Vec<u8>
+}
+
+struct OpenedHeader {
+    keys : ContainerKeys,
+    chunk_size : usize,
+    aad : Vec<u8>,
+    header_tag : Vec<u8>
+}
+
+impl Opener {
+    pub fn new( key : String ) -> Result<Self, ContainerError> {
+        let key_size = Aes::new( key.clone() ).map_err( | _ | ContainerError::InvalidKey )?.key_size();
+
+        Ok( Opener { key : Zeroizing::new( key ), key_size, header : None, chunk_index : 0, is_finished : false, buffer : Vec::new() } )
+    }
+
+    // AAD of the container, once the header has been verified
+    pub fn aad( &self ) -> Option<&[u8]> {
+        self.header.as_ref().map( | header | header.aad.as_slice() )
+    }
+
+    pub fn update( &mut self, input : &[u8] ) -> Result<Vec<u8>, ContainerError> {
+        self.buffer.extend_from_slice( input );
+
+        let mut plain_data = Vec::new();
+
+        if self.header.is_none() && !self.open_header()? {
+            return Ok( plain_data );
+        }
+
+        while let Some( chunk ) = self.open_chunk()? {
+            plain_data.extend( chunk );
+        }
+
+        Ok( plain_data )
+    }
+
+    pub fn finalize( self ) -> Result<(), ContainerError> {
+        if !self.is_finished {
+            return Err( ContainerError::Truncated );
+        }
+
+        Ok( () )
+    }
+
+    // false until the whole header has arrived
+    fn open_header( &mut self ) -> Result<bool, ContainerError> {
+        let prefix_len = self.buffer.len().min( MAGIC.len() );
+
+        if self.buffer[..prefix_len] != MAGIC[..prefix_len] {
+            return Err( ContainerError::InvalidMagic );
+        }
+
+        if self.buffer.len() < FIXED_HEADER_LENGTH {
+            return Ok( false );
+        }
+
+        if self.buffer[4] != VERSION {
+            return Err( ContainerError::UnsupportedVersion( self.buffer[4] ) );
+        }
+
+        if self.buffer[5] != Algorithm::CtrCmac as u8 {
+            return Err( ContainerError::UnsupportedAlgorithm( self.buffer[5] ) );
+        }
+
+        if self.buffer[7] as usize != NONCE_LENGTH {
+            return Err( ContainerError::InvalidHeader );
+        }
+
+        let chunk_size = read_u32( &self.buffer[24..28] );
+        let aad_len = read_u32( &self.buffer[28..32] );
+
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE || aad_len > MAX_AAD_LENGTH {
+            return Err( ContainerError::InvalidHeader );
+        }
+
+        if self.buffer[6] as usize != self.key_size.key_length() {
+            return Err( ContainerError::KeySizeMismatch );
+        }
+
+        let header_len = FIXED_HEADER_LENGTH + aad_len;
+
+        if self.buffer.len() < header_len + TAG_LENGTH {
+            return Ok( false );
+        }
+
+        let keys = ContainerKeys::derive( self.key.to_string(), self.key_size, &self.buffer[8..24] );
+        let header_tag = keys.generate_tag( &self.buffer[..header_len] );
+
+        if !is_equal_tag( &header_tag, &self.buffer[header_len..( header_len + TAG_LENGTH )] ) {
+            return Err( ContainerError::AuthenticationFailed );
+        }
+
+        let aad = self.buffer[FIXED_HEADER_LENGTH..header_len].to_vec();
+
+        self.buffer.drain( ..( header_len + TAG_LENGTH ) );
+        self.header = Some( OpenedHeader { keys, chunk_size, aad, header_tag } );
+
+        Ok( true )
+    }
+
+    // None until the whole chunk has arrived
+    fn open_chunk( &mut self ) -> Result<Option<Vec<u8>>, ContainerError> {
+        let header = self.header.as_ref().expect( "Failed to get container header" );
+
+        if self.is_finished {
+            if !self.buffer.is_empty() {
+                return Err( ContainerError::TrailingData );
+            }
+
+            return Ok( None );
+        }
+
+        if self.buffer.len() < CHUNK_HEADER_LENGTH {
+            return Ok( None );
+        }
+
+        let is_last = match self.buffer[0] {
+            0 => false,
+            LAST_CHUNK => true,
+            _ => return Err( ContainerError::InvalidChunk )
+        };
+
+        let cipher_text_len = read_u32( &self.buffer[1..CHUNK_HEADER_LENGTH] );
+
+        if cipher_text_len > header.chunk_size || ( !is_last && cipher_text_len != header.chunk_size ) {
+            return Err( ContainerError::InvalidChunk );
+        }
+
+        let chunk_len = CHUNK_HEADER_LENGTH + cipher_text_len + TAG_LENGTH;
+
+        if self.buffer.len() < chunk_len {
+            return Ok( None );
+        }
+
+        let chunk : Vec<u8> = self.buffer.drain( ..chunk_len ).collect();
+        let ( chunk_header, rest ) = chunk.split_at( CHUNK_HEADER_LENGTH );
+        let ( cipher_text, tag ) = rest.split_at( cipher_text_len );
+        let expect_tag = header.keys.generate_chunk_tag( &header.header_tag, self.chunk_index, chunk_header, cipher_text );
+
+        if !is_equal_tag( &expect_tag, tag ) {
+            return Err( ContainerError::AuthenticationFailed );
+        }
+
+        let plain_data = header.keys.apply_key_stream( cipher_text, self.chunk_index );
+
+        self.chunk_index += 1;
+        self.is_finished = is_last;
+
+        Ok( Some( plain_data ) )
+    }
+}
+
+// Keys for the payload and the tags derived from one key
+struct ContainerKeys {
+    encryption_key : Zeroizing<String>,
+    mac_key : Zeroizing<String>,
+    cipher_func : fn( String, &str ) -> String
+}
+
+impl ContainerKeys {
+    fn derive( key : String, key_size : KeySize, nonce : &[u8] ) -> Self {
+        let output_bits = key_size.key_length() * 8 * 2;
+        let fixed_input = kdf::build_fixed_input( KDF_LABEL.to_string(), hex::encode( nonce ), output_bits, kdf::FixedInputFormat::default() )
+            .expect( "Failed to build the container KDF input" );
+        let mut encryption_key = kdf::kdf_counter_mode( key, fixed_input, output_bits, kdf::CounterFormat::default(), key_size.cipher_func() )
+            .expect( "Failed to derive the container keys" );
+        let half = encryption_key.len() / 2;
+        let mac_key = Zeroizing::new( encryption_key.split_off( half ) );
+
+        ContainerKeys { encryption_key, mac_key, cipher_func : key_size.cipher_func() }
+    }
+
+    // the counter block of a chunk is chunk index(8) || block index(8)
+    fn apply_key_stream( &self, data : &[u8], chunk_index : u64 ) -> Vec<u8> {
+        let initial_counter = format!( "{:016x}{:016x}", chunk_index, 0 );
+        let result = block_cipher_mode::encrypt_ctr_mode( hex::encode( data ), self.encryption_key.to_string(), initial_counter, self.cipher_func );
+
+        hex::decode( result ).expect( "Failed to convert CTR output" )
+    }
+
+    fn generate_tag( &self, data : &[u8] ) -> Vec<u8> {
+        let tag = cmac::generate_aes_cmac( hex::encode( data ), self.mac_key.to_string(), self.cipher_func );
+
+        hex::decode( tag ).expect( "Failed to convert tag" )
+    }
+
+    fn generate_chunk_tag( &self, header_tag : &[u8], chunk_index : u64, chunk_header : &[u8], cipher_text : &[u8] ) -> Vec<u8> {
+        let mut cmac = cmac::Cmac::new( self.mac_key.to_string(), self.cipher_func );
+
+        cmac.update( hex::encode( header_tag ) );
+        cmac.update( hex::encode( chunk_index.to_be_bytes() ) );
+        cmac.update( hex::encode( chunk_header ) );
+        cmac.update( hex::encode( cipher_text ) );
+
+        hex::decode( cmac.finalize() ).expect( "Failed to convert tag" )
+    }
+}
+
+fn build_chunk_header( is_last : bool, cipher_text_len : usize ) -> Vec<u8> {
+    let mut chunk_header = vec![if is_last { LAST_CHUNK } else { 0 }];
+
+    chunk_header.extend( ( cipher_text_len as u32 ).to_be_bytes() );
+
+    chunk_header
+}
+
+fn read_u32( input : &[u8] ) -> usize {
+    u32::from_be_bytes( [input[0], input[1], input[2], input[3]] ) as usize
+}
+
+// compare every byte so that the time does not depend on the position of a mismatch
+fn is_equal_tag( tag1 : &[u8], tag2 : &[u8] ) -> bool {
+    let difference = tag1.iter().zip( tag2.iter() ).fold( 0, | acc, ( byte1, byte2 ) | acc | ( byte1 ^ byte2 ) );
+
+    tag1.len() == tag2.len() && difference == 0
+}
+
+#[cfg(test)]
+fn seal_with_nonce( plain_data : &[u8], key : &str, aad : &[u8], chunk_size : usize ) -> Vec<u8> {
+    let mut sealer = Sealer::with_nonce( key.to_string(), aad, chunk_size, vec![0x5a; NONCE_LENGTH] ).unwrap();
+    let mut output = sealer.update( plain_data );
+
+    output.extend( sealer.finalize() );
+    output
+}
+
+#[test]
+fn test_seal_open() {
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let plain_data : Vec<u8> = ( 0..100 ).collect();
+    let data_len = [0, 1, 16, 47, 48, 100];
+
+    for key in key {
+        for data_len in data_len {
+            let container = seal_with_nonce( &plain_data[..data_len], key, b"header", 16 );
+            let chunk_num = data_len.div_ceil( 16 ).max( 1 );
+
+            assert_eq!( container.len(), FIXED_HEADER_LENGTH + 6 + TAG_LENGTH + chunk_num * ( CHUNK_HEADER_LENGTH + TAG_LENGTH ) + data_len );
+            assert_eq!( container[6] as usize, key.len() / 2 );
+            assert_eq!( open( &container, key.to_string() ), Ok( ( plain_data[..data_len].to_vec(), b"header".to_vec() ) ) );
+        }
+    }
+
+    // the payload is encrypted and a new nonce is taken every time
+    let container = seal( &plain_data, key[0].to_string(), &[] ).unwrap();
+    assert!( !container.windows( 16 ).any( | window | window == &plain_data[..16] ) );
+    assert_ne!( container, seal( &plain_data, key[0].to_string(), &[] ).unwrap() );
+}
+
+#[test]
+fn test_open_split() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let plain_data : Vec<u8> = ( 0..40 ).collect();
+    let container = seal_with_nonce( &plain_data, key, b"aad", 16 );
+
+    // every split point, including inside the header and a tag
+    for split in 0..container.len() {
+        let mut opener = Opener::new( key.to_string() ).unwrap();
+        let mut output = opener.update( &container[..split] ).unwrap();
+        output.extend( opener.update( &container[split..] ).unwrap() );
+
+        assert_eq!( opener.aad(), Some( &b"aad"[..] ) );
+        opener.finalize().unwrap();
+        assert_eq!( output, plain_data );
+    }
+}
+
+#[test]
+fn test_open_error() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let plain_data : Vec<u8> = ( 0..40 ).collect();
+    let container = seal_with_nonce( &plain_data, key, b"aad", 16 );
+
+    // every bit flip is detected
+    for i in 0..container.len() {
+        for bit in 0..8 {
+            let mut tampered = container.clone();
+            tampered[i] ^= 1 << bit;
+
+            assert!( open( &tampered, key.to_string() ).is_err() );
+        }
+    }
+
+    // every truncation is detected
+    for len in 0..container.len() {
+        assert!( open( &container[..len], key.to_string() ).is_err() );
+    }
+
+    // the last chunk is marked, so truncation at a chunk boundary is detected
+    let header_len = FIXED_HEADER_LENGTH + 3 + TAG_LENGTH;
+    let chunk_len = CHUNK_HEADER_LENGTH + 16 + TAG_LENGTH;
+    assert_eq!( open( &container[..( header_len + chunk_len * 2 )], key.to_string() ), Err( ContainerError::Truncated ) );
+    assert_eq!( open( &container[..header_len], key.to_string() ), Err( ContainerError::Truncated ) );
+
+    let mut extended = container.clone();
+    extended.push( 0 );
+    assert_eq!( open( &extended, key.to_string() ), Err( ContainerError::TrailingData ) );
+
+    // swapping the first two chunks
+    let mut swapped = container[..header_len].to_vec();
+    swapped.extend( &container[( header_len + chunk_len )..( header_len + chunk_len * 2 )] );
+    swapped.extend( &container[header_len..( header_len + chunk_len )] );
+    swapped.extend( &container[( header_len + chunk_len * 2 )..] );
+    assert_eq!( open( &swapped, key.to_string() ), Err( ContainerError::AuthenticationFailed ) );
+
+    assert_eq!( open( &container, "000102030405060708090a0b0c0d0e0e".to_string() ), Err( ContainerError::AuthenticationFailed ) );
+    assert_eq!( open( &container, "000102030405060708090a0b0c0d0e0f1011121314151617".to_string() ), Err( ContainerError::KeySizeMismatch ) );
+    assert_eq!( open( b"AESD", key.to_string() ), Err( ContainerError::InvalidMagic ) );
+    assert_eq!( open( &container, "0001".to_string() ), Err( ContainerError::InvalidKey ) );
+
+    let mut new_version = container.clone();
+    new_version[4] = 2;
+    assert_eq!( open( &new_version, key.to_string() ), Err( ContainerError::UnsupportedVersion( 2 ) ) );
+}
+
+#[test]
+fn test_seal_error() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+
+    assert_eq!( Sealer::new( key.to_string(), b"aad", 0 ).err(), Some( ContainerError::InvalidChunkSize( 0 ) ) );
+    assert_eq!( Sealer::new( key.to_string(), b"aad", MAX_CHUNK_SIZE + 1 ).err(), Some( ContainerError::InvalidChunkSize( MAX_CHUNK_SIZE + 1 ) ) );
+    assert!( Sealer::new( key.to_string(), b"aad", MAX_CHUNK_SIZE ).is_ok() );
+
+    let aad = vec![0; MAX_AAD_LENGTH + 1];
+    assert_eq!( seal( b"data", key.to_string(), &aad ), Err( ContainerError::AadTooLong( MAX_AAD_LENGTH + 1 ) ) );
+    assert!( seal( b"data", key.to_string(), &aad[..MAX_AAD_LENGTH] ).is_ok() );
+    assert_eq!( seal( b"data", "0001".to_string(), b"aad" ), Err( ContainerError::InvalidKey ) );
+}
\ No newline at end of file
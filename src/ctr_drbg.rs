@@ -0,0 +1,483 @@
+use std::error;
+use std::fmt;
+use hex;
+use crate::aes_common::KeySize;
+use crate::zeroize::Zeroizing;
+use crate::block_cipher::CipherScope;
+
+const BLOCK_SIZE : usize = 16;
+
+// NIST SP 800-90A Table 3: max_number_of_bits_per_request(2^19 bits) and reseed_interval(2^48)
+const MAX_REQUEST_BYTES : usize = 1 << 16;
+const MAX_RESEED_INTERVAL : u64 = 1 << 48;
+
+// max_length of personalization string and additional input with the derivation function(2^35 bits)
+const MAX_INPUT_BYTES : u64 = 1 << 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrbgError {
+    EntropySourceFailed,
+    InvalidInputLength,
+    RequestTooLarge,
+    PredictionResistanceUnsupported,
+    InvalidReseedInterval
+}
+
+impl fmt::Display for DrbgError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        let message = match self {
+            DrbgError::EntropySourceFailed => "entropy source failed",
+            DrbgError::InvalidInputLength => "invalid nonce, personalization string or additional input length",
+            DrbgError::RequestTooLarge => "requested too many bytes at once",
+            DrbgError::PredictionResistanceUnsupported => "prediction resistance was not enabled at instantiation",
+            DrbgError::InvalidReseedInterval => "reseed interval out of range"
+        };
+
+        write!( f, "{}", message )
+    }
+}
+
+impl error::Error for DrbgError {}
+
+pub trait EntropySource {
+    fn fill_entropy( &mut self, output : &mut [u8] ) -> Result<(), DrbgError>;
+}
+
+// Entropy from the operating system random source
+pub struct OsEntropy;
+
+impl EntropySource for OsEntropy {
+    fn fill_entropy( &mut self, output : &mut [u8] ) -> Result<(), DrbgError> {
+        getrandom::getrandom( output ).map_err( | _ | DrbgError::EntropySourceFailed )
+    }
+}
+
+// NIST SP 800-90A 10.2 CTR_DRBG
+pub struct CtrDrbg<E : EntropySource> {
+    key : Zeroizing<String>,
+    v : Zeroizing<[u8; BLOCK_SIZE]>,
+    reseed_counter : u64,
+    reseed_interval : u64,
+    key_size : KeySize,
+    use_df : bool,
+    prediction_resistance : bool,
+    entropy_source : E
+}
+
+impl CtrDrbg<OsEntropy> {
+    // Instantiate with the derivation function, taking both entropy input and nonce from the OS
+    pub fn from_os_entropy( key_size : KeySize, prediction_resistance : bool, personalization : &[u8] ) -> Result<Self, DrbgError> {
+        let mut nonce = vec![0; key_size.key_length() / 2];
+
+        OsEntropy.fill_entropy( &mut nonce )?;
+
+        CtrDrbg::new( key_size, true, prediction_resistance, OsEntropy, &nonce, personalization )
+    }
+}
+
+impl<E : EntropySource> CtrDrbg<E> {
+    pub fn new( key_size : KeySize, use_df : bool, prediction_resistance : bool, entropy_source : E,
+                nonce : &[u8], personalization : &[u8] ) -> Result<Self, DrbgError> {
+        let mut drbg = CtrDrbg {
+            key : Zeroizing::new( "00".repeat( key_size.key_length() ) ),
+            v : Zeroizing::new( [0; BLOCK_SIZE] ),
+            reseed_counter : 1,
+            reseed_interval : MAX_RESEED_INTERVAL,
+            key_size,
+            use_df,
+            prediction_resistance,
+            entropy_source
+        };
+
+        // the nonce is only used by the derivation function
+        if !use_df && !nonce.is_empty() {
+            return Err( DrbgError::InvalidInputLength );
+        }
+
+        drbg.check_input_length( personalization )?;
+
+        let entropy_input = drbg.get_entropy_input()?;
+        let seed_material = drbg.build_seed_material( &[entropy_input.as_slice(), nonce, personalization].concat(),
+                                                      &entropy_input, personalization );
+
+        drbg.update( &seed_material );
+
+        Ok( drbg )
+    }
+
+    pub fn reseed( &mut self, additional_input : &[u8] ) -> Result<(), DrbgError> {
+        self.check_input_length( additional_input )?;
+
+        let entropy_input = self.get_entropy_input()?;
+        let seed_material = self.build_seed_material( &[entropy_input.as_slice(), additional_input].concat(),
+                                                      &entropy_input, additional_input );
+
+        self.update( &seed_material );
+        self.reseed_counter = 1;
+
+        Ok( () )
+    }
+
+    pub fn generate( &mut self, output_len : usize, additional_input : &[u8], prediction_resistance_request : bool ) -> Result<Vec<u8>, DrbgError> {
+        let mut additional_input = additional_input;
+
+        if output_len > MAX_REQUEST_BYTES {
+            return Err( DrbgError::RequestTooLarge );
+        }
+
+        if prediction_resistance_request && !self.prediction_resistance {
+            return Err( DrbgError::PredictionResistanceUnsupported );
+        }
+
+        self.check_input_length( additional_input )?;
+
+        // NIST SP 800-90A 9.3.1: the additional input is consumed by the reseed
+        if prediction_resistance_request || self.reseed_counter > self.reseed_interval {
+            self.reseed( additional_input )?;
+            additional_input = &[];
+        }
+
+        let additional_data : Vec<u8>;
+
+        if additional_input.is_empty() {
+            additional_data = vec![0; self.get_seed_length()];
+        }
+        else {
+            additional_data = self.build_seed_material( additional_input, &vec![0; self.get_seed_length()], additional_input );
+            self.update( &additional_data );
+        }
+
+        let mut output : Vec<u8> = Vec::with_capacity( output_len + BLOCK_SIZE );
+        let cipher_scope = CipherScope::new( &self.key );
+
+        while output.len() < output_len {
+            *self.v = increment_block( *self.v );
+            output.extend_from_slice( &self.encrypt_block( *self.v ) );
+        }
+
+        output.truncate( output_len );
+        drop( cipher_scope );
+
+        self.update( &additional_data );
+        self.reseed_counter += 1;
+
+        Ok( output )
+    }
+
+    pub fn set_reseed_interval( &mut self, reseed_interval : u64 ) -> Result<(), DrbgError> {
+        if reseed_interval == 0 || reseed_interval > MAX_RESEED_INTERVAL {
+            return Err( DrbgError::InvalidReseedInterval );
+        }
+
+        self.reseed_interval = reseed_interval;
+
+        Ok( () )
+    }
+
+    pub fn reseed_counter( &self ) -> u64 {
+        self.reseed_counter
+    }
+
+    fn get_seed_length( &self ) -> usize {
+        self.key_size.key_length() + BLOCK_SIZE
+    }
+
+    fn get_entropy_input( &mut self ) -> Result<Vec<u8>, DrbgError> {
+        // security_strength bits with the derivation function, seedlen bits without it
+        let entropy_len = if self.use_df { self.key_size.key_length() } else { self.get_seed_length() };
+        let mut entropy_input = vec![0; entropy_len];
+
+        self.entropy_source.fill_entropy( &mut entropy_input )?;
+
+        Ok( entropy_input )
+    }
+
+    fn check_input_length( &self, input : &[u8] ) -> Result<(), DrbgError> {
+        let max_len = if self.use_df { MAX_INPUT_BYTES } else { self.get_seed_length() as u64 };
+
+        if input.len() as u64 > max_len {
+            return Err( DrbgError::InvalidInputLength );
+        }
+
+        Ok( () )
+    }
+
+    // df( df_input ) with the derivation function, otherwise input xor the zero padded optional input
+    fn build_seed_material( &self, df_input : &[u8], input : &[u8], optional_input : &[u8] ) -> Vec<u8> {
+        if self.use_df {
+            block_cipher_df( self.key_size, df_input, self.get_seed_length() )
+        }
+        else {
+            let mut seed_material = input.to_vec();
+
+            for i in 0..optional_input.len() {
+                seed_material[i] ^= optional_input[i];
+            }
+
+            seed_material
+        }
+    }
+
+    // NIST SP 800-90A 10.2.1.2 CTR_DRBG_Update
+    fn update( &mut self, provided_data : &[u8] ) {
+        let key_length = self.key_size.key_length();
+        // the next key and V
+        let mut temp : Zeroizing<Vec<u8>> = Zeroizing::new( Vec::with_capacity( self.get_seed_length() + BLOCK_SIZE ) );
+        let cipher_scope = CipherScope::new( &self.key );
+
+        while temp.len() < self.get_seed_length() {
+            *self.v = increment_block( *self.v );
+            temp.extend_from_slice( &self.encrypt_block( *self.v ) );
+        }
+
+        drop( cipher_scope );
+
+        for i in 0..provided_data.len() {
+            temp[i] ^= provided_data[i];
+        }
+
+        self.key = Zeroizing::new( hex::encode( &temp[..key_length] ) );
+        self.v.copy_from_slice( &temp[key_length..( key_length + BLOCK_SIZE )] );
+    }
+
+    fn encrypt_block( &self, block : [u8; BLOCK_SIZE] ) -> Vec<u8> {
+        let cipher_func = self.key_size.cipher_func();
+
+        hex::decode( cipher_func( hex::encode( block ), &self.key ) ).expect( "Failed to convert block in encrypt_block" )
+    }
+}
+
+// NIST SP 800-90A 10.3.2 Block_Cipher_df
+fn block_cipher_df( key_size : KeySize, input : &[u8], output_len : usize ) -> Vec<u8> {
+    let cipher_func = key_size.cipher_func();
+    let key_length = key_size.key_length();
+
+    // S = L || N || input_string || 0x80, padded with zeros to a multiple of outlen
+    let mut s : Vec<u8> = Vec::new();
+    s.extend_from_slice( &( input.len() as u32 ).to_be_bytes() );
+    s.extend_from_slice( &( output_len as u32 ).to_be_bytes() );
+    s.extend_from_slice( input );
+    s.push( 0x80 );
+    s.resize( s.len().div_ceil( BLOCK_SIZE ) * BLOCK_SIZE, 0 );
+
+    let df_key : Vec<u8> = ( 0..key_length as u8 ).collect();
+    let mut temp : Vec<u8> = Vec::with_capacity( key_length + BLOCK_SIZE * 2 );
+    let mut i : u32 = 0;
+
+    while temp.len() < key_length + BLOCK_SIZE {
+        let mut iv = [0; BLOCK_SIZE];
+        iv[..4].copy_from_slice( &i.to_be_bytes() );
+
+        temp.extend_from_slice( &bcc( &df_key, &[&iv[..], &s].concat(), cipher_func ) );
+        i += 1;
+    }
+
+    let key = Zeroizing::new( hex::encode( &temp[..key_length] ) );
+    let mut x = hex::encode( &temp[key_length..( key_length + BLOCK_SIZE )] );
+    let mut output : Vec<u8> = Vec::with_capacity( output_len + BLOCK_SIZE );
+    let _cipher_scope = CipherScope::new( &key );
+
+    while output.len() < output_len {
+        x = cipher_func( x, &key );
+        output.extend_from_slice( &hex::decode( &x ).expect( "Failed to convert block in block_cipher_df" ) );
+    }
+
+    output.truncate( output_len );
+
+    output
+}
+
+// NIST SP 800-90A 10.3.3 BCC
+fn bcc( key : &[u8], data : &[u8], cipher_func : fn( String, &str ) -> String ) -> [u8; BLOCK_SIZE] {
+    let key_text = hex::encode( key );
+    let _cipher_scope = CipherScope::new( &key_text );
+    let mut chaining_value = [0; BLOCK_SIZE];
+
+    for block in data.chunks( BLOCK_SIZE ) {
+        for i in 0..BLOCK_SIZE {
+            chaining_value[i] ^= block[i];
+        }
+
+        let output = hex::decode( cipher_func( hex::encode( chaining_value ), &key_text ) ).expect( "Failed to convert block in bcc" );
+        chaining_value.copy_from_slice( &output );
+    }
+
+    chaining_value
+}
+
+fn increment_block( block : [u8; BLOCK_SIZE] ) -> [u8; BLOCK_SIZE] {
+    u128::from_be_bytes( block ).wrapping_add( 1 ).to_be_bytes()
+}
+
+// Entropy source replaying the entropy inputs of a test vector in order
+#[cfg(test)]
+struct TestEntropy {
+    data : Vec<u8>,
+    pos : usize
+}
+
+#[cfg(test)]
+impl EntropySource for TestEntropy {
+    fn fill_entropy( &mut self, output : &mut [u8] ) -> Result<(), DrbgError> {
+        if self.pos + output.len() > self.data.len() {
+            return Err( DrbgError::EntropySourceFailed );
+        }
+
+        output.copy_from_slice( &self.data[self.pos..( self.pos + output.len() )] );
+        self.pos += output.len();
+
+        Ok( () )
+    }
+}
+
+#[cfg(test)]
+fn test_entropy( entropy : &[&str] ) -> TestEntropy {
+    TestEntropy { data : hex::decode( entropy.concat() ).expect( "Failed to convert entropy" ), pos : 0 }
+}
+
+#[test]
+fn test_ctr_drbg_no_df() {
+
+    //NIST CAVP drbgvectors_pr_false CTR_DRBG.rsp [AES-256 no df]
+    let entropy = [["e4bc23c5089a19d86f4119cb3fa08c0a4991e0a1def17e101e4c14d9c323460a7c2fb58e0b086c6c57b55f56cae25bad",
+                    "fd85a836bba85019881e8c6bad23c9061adc75477659acaea8e4a01dfe07a1832dad1c136f59d70f8653a5dc118663d6"],
+                   ["ae7ebe062971f5eb32e5b21444750785de816595ad2cbe80a209c8f8ab04b5468166de8c6ae522d8f10b56386a3b424f",
+                    "a42407931bfeca70e6ee5dd197021a129525051c07468e8b25587c5ad50abe9204e882fe847b8fd47cf7b4360e5aa034"]];
+
+    let personalization = ["",
+                           "55860dae57fcac297087c137efb796878a75868f6e7681114e9b73ed0c67e3c62bfc9f5d77e8caa59bcdb223f4ffd247"];
+
+    let additional_input_reseed = ["",
+                                   "ee4c88d1eb05f4853663eada501d2fc4b4984b283a88db579af2113031e03d9bc570de943dd168918f3ba8065581fea7"];
+
+    let additional_input = [["", ""],
+                            ["4b4b03ef19b0f259dca2b3ee3ae4cd86c3895a784b3d8eee043a2003c08289f8fffdad141e6b1ab2174d8d5d79c1e581",
+                             "3062b33f116b46e20fe3c354726ae9b2a3a4c51922c8107863cb86f1f0bdad7554075659d91c371e2b11b1e8106a1ed5"]];
+
+    let expect = ["b2cb8905c05e5950ca31895096be29ea3d5a3b82b269495554eb80fe07de43e193b9e7c3ece73b80e062b1c1f68202fbb1c52a040ea2478864295282234aaada",
+                  "0d270518baeafac160ff1cb28c11ef68712c764c0c01674e6c9ca2cc9c7e0e8accfd3c753635ee070081eee7628af6187fbc2854b3c204461a796cf3f3fcb092"];
+
+    for i in 0..expect.len() {
+        let personalization = hex::decode( personalization[i] ).expect( "Failed to convert personalization" );
+        let mut drbg = CtrDrbg::new( KeySize::Aes256, false, false, test_entropy( &entropy[i] ), &[], &personalization ).unwrap();
+
+        drbg.reseed( &hex::decode( additional_input_reseed[i] ).unwrap() ).unwrap();
+        drbg.generate( 64, &hex::decode( additional_input[i][0] ).unwrap(), false ).unwrap();
+        let actual_result = drbg.generate( 64, &hex::decode( additional_input[i][1] ).unwrap(), false ).unwrap();
+
+        assert_eq!( hex::encode( actual_result ), expect[i] );
+    }
+}
+
+#[test]
+fn test_ctr_drbg_df() {
+
+    //NIST CAVP drbgvectors_no_reseed CTR_DRBG.rsp [AES-128 use df]
+    //NIST CAVP drbgvectors_pr_false CTR_DRBG.rsp [AES-128 use df], [AES-256 use df]
+    let key_size = [KeySize::Aes128, KeySize::Aes128, KeySize::Aes256];
+
+    let entropy = [["b408cefb5bc7157d3f26cb95a8b1d7ac", ""],
+                   ["0f65da13dca407999d4773c2b4a11d85", "1dea0a12c52bf64339dd291c80d8ca89"],
+                   ["a53e371017439193591e475087aaddd5c1c386cdca0ddb68e002d80fdc401a47",
+                    "dd40e5987b2716731568d276bf0c6715757903d3dede914642ddd467c879c81e"]];
+
+    // nonce || personalization string
+    let nonce = ["026c768fd577b92a",
+                 "5209e5b4ed82a234",
+                 "a94da55afdc50ce51c9a3b8a4c4484408b52a24a93c34ea71e1ca705eb829ba65de4d4e07fa3d86b37845ff1c7d5f6d2"];
+
+    let reseed_before = [false, true, false];
+    let reseed_between = [false, false, true];
+
+    let additional_input = [["5737ef81dee365b6dadb3feebf5d1084", "3368a516b3431a3daaa60dc8743c8297"],
+                            ["", ""],
+                            ["20f422edf85ca16a01cfbe5f8d6c947fae12a857db2aa9bfc7b36581808d0d46",
+                             "cd2cff14693e4c9efdfe260de986004930bab1c65057772a62392c3b74ebc90d"]];
+
+    let additional_input_reseed = ["",
+                                   "",
+                                   "7fd81fbd2ab51c115d834e99f65ca54020ed388ed59ee07593fe125e5d73fb75"];
+
+    let expect = ["4e909ebb24147a0004063a5e47ee044fead610d62324bd0f963f756fb91361e8b87e3a76a398143fe88130fe1b547b661a6480c711b739f18a9df3ae51d41bc9",
+                  "2859cc468a76b08661ffd23b28547ffd0997ad526a0f51261b99ed3a37bd407bf418dbe6c6c3e26ed0ddefcb7474d899bd99f3655427519fc5b4057bcaf306d4",
+                  "4f78beb94d978ce9d097feadfafd355e"];
+
+    for i in 0..expect.len() {
+        let output_len = expect[i].len() / 2;
+        let nonce = hex::decode( nonce[i] ).expect( "Failed to convert nonce" );
+        let additional_input_reseed = hex::decode( additional_input_reseed[i] ).unwrap();
+        let mut drbg = CtrDrbg::new( key_size[i], true, false, test_entropy( &entropy[i] ), &nonce, &[] ).unwrap();
+
+        if reseed_before[i] {
+            drbg.reseed( &additional_input_reseed ).unwrap();
+        }
+
+        drbg.generate( output_len, &hex::decode( additional_input[i][0] ).unwrap(), false ).unwrap();
+
+        if reseed_between[i] {
+            drbg.reseed( &additional_input_reseed ).unwrap();
+        }
+
+        let actual_result = drbg.generate( output_len, &hex::decode( additional_input[i][1] ).unwrap(), false ).unwrap();
+
+        assert_eq!( hex::encode( actual_result ), expect[i] );
+    }
+}
+
+#[test]
+fn test_ctr_drbg_prediction_resistance() {
+
+    //NIST CAVP drbgvectors_pr_true CTR_DRBG.rsp [AES-128 use df], [AES-256 use df]
+    let key_size = [KeySize::Aes128, KeySize::Aes256, KeySize::Aes256];
+
+    let entropy = ["5d4041942bcf68864a4997d8171f1f9fef55a769b7eaf03fe082029bb32a2b9d8239e865c0a42e14b964b9c09de85a20",
+                   "16a1f035388cd8d956026e3b0117cb524dd3eb563f9a7720bb7dcb0fc6fbe743cf140bcd4d7130e7e3ea14046c56442b57c43b34ad219553e7105c18f6e561afe27c9f0be60d82d6cc474efb7fc737b16a6895d9a3a45b971d19b743c1a4ac8f",
+                   "6168fc1af0b5956b85099b743f1378493b85ec93133ba94f96ab2ce4c88fdd6a0b23afdff162d7d34397f87704a84220bdf60fc1172f9f54bb561786680ebaa9bf6c592a0d440fae9a5e0373d8a6e1cf25613824869e53e8a4df56f406079c0f"];
+
+    // nonce || personalization string
+    let nonce = ["d4f1f4ae08bcb3e1",
+                 "a2d015f22d854e29de278d910c573de5",
+                 "add2bbbab76589c3216c55332b36ffa46ecae72072d3845a32d34b2472c4632b9d12240c23268e8316370bd1064f686d"];
+
+    let additional_input = [["", ""],
+                            ["", ""],
+                            ["7e084abbe3217cc923d2f8b07398ba847423ab068ae222d37bce9bd24a76b8de",
+                             "946bc99fab8dc5ec71881d008c8968e4c8077736176d7978c7064e99042829c3"]];
+
+    let expect = ["4155320287eedcf7d484c2c2a1e2eb64b9c9ce77c87202a1ae1616c7a5cfd1c687c7a0bfcc85bda48fdd4629fd330c22d0a76076f88fc7cd04037ee06b7af602",
+                  "b4e8395bcb7503410a94633f70e9904a5b30e62c35bc6dd2a03496c4a49932e184fbffdbcf1de1c72c50d36dc2ae8f04f40f96aae159c3fb816ca16df99b6c3e",
+                  "224ab4b8b6ee7db19ec9f9a0d9e29700"];
+
+    for i in 0..expect.len() {
+        let output_len = expect[i].len() / 2;
+        let nonce = hex::decode( nonce[i] ).expect( "Failed to convert nonce" );
+        let mut drbg = CtrDrbg::new( key_size[i], true, true, test_entropy( &[entropy[i]] ), &nonce, &[] ).unwrap();
+
+        drbg.generate( output_len, &hex::decode( additional_input[i][0] ).unwrap(), true ).unwrap();
+        let actual_result = drbg.generate( output_len, &hex::decode( additional_input[i][1] ).unwrap(), true ).unwrap();
+
+        assert_eq!( hex::encode( actual_result ), expect[i] );
+    }
+}
+
+#[test]
+fn test_ctr_drbg_reseed_counter() {
+    let entropy = "000102030405060708090a0b0c0d0e0f1011121314151617".repeat( 3 );
+    let mut drbg = CtrDrbg::new( KeySize::Aes192, true, false, test_entropy( &[&entropy] ), &[0; 12], &[] ).unwrap();
+
+    assert_eq!( drbg.generate( 16, &[], true ), Err( DrbgError::PredictionResistanceUnsupported ) );
+    assert_eq!( drbg.generate( MAX_REQUEST_BYTES + 1, &[], false ), Err( DrbgError::RequestTooLarge ) );
+    assert_eq!( drbg.set_reseed_interval( 0 ), Err( DrbgError::InvalidReseedInterval ) );
+
+    drbg.set_reseed_interval( 2 ).unwrap();
+
+    for reseed_counter in [1, 2, 3, 2] {
+        assert_eq!( drbg.reseed_counter(), reseed_counter );
+        assert_eq!( drbg.generate( 16, &[], false ).unwrap().len(), 16 );
+    }
+
+    // entropy for the instantiation and two reseeds is used up
+    assert_eq!( drbg.generate( 16, &[], false ).unwrap().len(), 16 );
+    assert_eq!( drbg.generate( 16, &[], false ).unwrap().len(), 16 );
+    assert_eq!( drbg.generate( 16, &[], false ), Err( DrbgError::EntropySourceFailed ) );
+}
\ No newline at end of file
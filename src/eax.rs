@@ -0,0 +1,144 @@
+use std::error;
+use std::fmt;
+use hex;
+use crate::{block_cipher_mode, cmac};
+use crate::block_cipher::CipherScope;
+use crate::zeroize::Zeroizing;
+
+#[cfg(test)]
+use crate::aes128;
+
+// tag length in hex characters
+const TAG_SIZE : usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AeadError {
+    InvalidLength,
+    AuthenticationFailed
+}
+
+impl fmt::Display for AeadError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        let message = match self {
+            AeadError::InvalidLength => "cipher text is shorter than the tag",
+            AeadError::AuthenticationFailed => "authentication of the cipher text failed"
+        };
+
+        write!( f, "{}", message )
+    }
+}
+
+impl error::Error for AeadError {}
+
+// EAX mode(Bellare, Rogaway and Wagner) from CTR mode and CMAC. Return the cipher text followed by the tag
+pub fn encrypt_eax_mode( plain_text : String, key : String, nonce : String, header : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    let key = Zeroizing::new( key );
+    let _cipher_scope = CipherScope::new( &key );
+    let nonce_mac = omac( 0, nonce, &key, cipher_func );
+    let header_mac = omac( 1, header, &key, cipher_func );
+    let cipher_text = block_cipher_mode::encrypt_ctr_mode( plain_text, key.to_string(), nonce_mac.clone(), cipher_func );
+    let cipher_text_mac = omac( 2, cipher_text.clone(), &key, cipher_func );
+
+    let tag = xor_tag( &xor_tag( &nonce_mac, &header_mac ), &cipher_text_mac );
+
+    cipher_text + &tag
+}
+
+// The plain text is returned only when the tag is valid
+pub fn decrypt_eax_mode( cipher_text : String, key : String, nonce : String, header : String,
+                         cipher_func : fn( String, &str ) -> String ) -> Result<String, AeadError> {
+    let key = Zeroizing::new( key );
+
+    if cipher_text.len() < TAG_SIZE {
+        return Err( AeadError::InvalidLength );
+    }
+
+    let ( cipher_text, tag ) = cipher_text.split_at( cipher_text.len() - TAG_SIZE );
+    let _cipher_scope = CipherScope::new( &key );
+
+    let nonce_mac = omac( 0, nonce, &key, cipher_func );
+    let header_mac = omac( 1, header, &key, cipher_func );
+    let cipher_text_mac = omac( 2, cipher_text.to_string(), &key, cipher_func );
+
+    let expect_tag = xor_tag( &xor_tag( &nonce_mac, &header_mac ), &cipher_text_mac );
+    let tag = hex::decode( tag ).map_err( | _ | AeadError::AuthenticationFailed )?;
+    let expect_tag = hex::decode( expect_tag ).expect( "Failed to convert tag" );
+
+    // compare every byte so that the time does not depend on the position of a mismatch
+    let difference = expect_tag.iter().zip( tag.iter() ).fold( 0, | acc, ( byte1, byte2 ) | acc | ( byte1 ^ byte2 ) );
+
+    if difference != 0 {
+        return Err( AeadError::AuthenticationFailed );
+    }
+
+    Ok( block_cipher_mode::decrypt_ctr_mode( cipher_text.to_string(), key.to_string(), nonce_mac, cipher_func ) )
+}
+
+// OMAC^t: CMAC of the block [t] followed by the text
+fn omac( t : u8, text : String, key : &str, cipher_func : fn( String, &str ) -> String ) -> String {
+    cmac::generate_aes_cmac( format!( "{:032x}", t ) + &text, key.to_string(), cipher_func )
+}
+
+fn xor_tag( tag1 : &str, tag2 : &str ) -> String {
+    let tag1 = hex::decode( tag1 ).expect( "Failed to convert tag" );
+    let tag2 = hex::decode( tag2 ).expect( "Failed to convert tag" );
+
+    hex::encode( tag1.iter().zip( tag2.iter() ).map( | ( byte1, byte2 ) | byte1 ^ byte2 ).collect::<Vec<u8>>() )
+}
+
+#[test]
+fn test_eax_mode() {
+
+    //EAX paper Appendix: Test Vectors
+    let plain_text = ["",
+                      "f7fb",
+                      "1a47cb4933"];
+
+    let key = ["233952dee4d5ed5f9b9c6d6ff80ff478",
+               "91945d3f4dcbee0bf45ef52255f095a4",
+               "01f74ad64077f2e704c0f60ada3dd523"];
+
+    let nonce = ["62ec67f9c3a4a407fcb2a8c49031a8b3",
+                 "becaf043b0a23d843194ba972c66debd",
+                 "70c3db4f0d26368400a10ed05d2bff5e"];
+
+    let header = ["6bfb914fd07eae6b",
+                  "fa3bfd4806eb53fa",
+                  "234a3463c1264ac6"];
+
+    let expect = ["e037830e8389f27b025a2d6527e79d01",
+                  "19dd5c4c9331049d0bdab0277408f67967e5",
+                  "d851d5bae03a59f238a23e39199dc9266626c40f80"];
+
+    for i in 0..plain_text.len() {
+        let actual_result = encrypt_eax_mode( plain_text[i].to_string(), key[i].to_string(), nonce[i].to_string(), header[i].to_string(), aes128::encrypt );
+        assert_eq!( actual_result, expect[i] );
+
+        let actual_result = decrypt_eax_mode( expect[i].to_string(), key[i].to_string(), nonce[i].to_string(), header[i].to_string(), aes128::encrypt );
+        assert_eq!( actual_result, Ok( plain_text[i].to_string() ) );
+    }
+}
+
+#[test]
+fn test_eax_mode_error() {
+    let key = "91945d3f4dcbee0bf45ef52255f095a4";
+    let nonce = "becaf043b0a23d843194ba972c66debd";
+    let header = "fa3bfd4806eb53fa";
+    let cipher_text = "19dd5c4c9331049d0bdab0277408f67967e5";
+
+    // a flipped bit in the cipher text, the tag, the header or the nonce
+    let actual_result = decrypt_eax_mode( "18dd5c4c9331049d0bdab0277408f67967e5".to_string(), key.to_string(), nonce.to_string(), header.to_string(), aes128::encrypt );
+    assert_eq!( actual_result, Err( AeadError::AuthenticationFailed ) );
+
+    let actual_result = decrypt_eax_mode( "19dd5c4c9331049d0bdab0277408f67967e4".to_string(), key.to_string(), nonce.to_string(), header.to_string(), aes128::encrypt );
+    assert_eq!( actual_result, Err( AeadError::AuthenticationFailed ) );
+
+    let actual_result = decrypt_eax_mode( cipher_text.to_string(), key.to_string(), nonce.to_string(), "fa3bfd4806eb53fb".to_string(), aes128::encrypt );
+    assert_eq!( actual_result, Err( AeadError::AuthenticationFailed ) );
+
+    let actual_result = decrypt_eax_mode( cipher_text.to_string(), key.to_string(), "becaf043b0a23d843194ba972c66debc".to_string(), header.to_string(), aes128::encrypt );
+    assert_eq!( actual_result, Err( AeadError::AuthenticationFailed ) );
+
+    let actual_result = decrypt_eax_mode( cipher_text[..30].to_string(), key.to_string(), nonce.to_string(), header.to_string(), aes128::encrypt );
+    assert_eq!( actual_result, Err( AeadError::InvalidLength ) );
+}
\ No newline at end of file
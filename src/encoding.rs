@@ -0,0 +1,264 @@
+use std::error;
+use std::fmt;
+use std::str;
+use std::str::FromStr;
+use hex;
+use base64::Engine;
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+
+// Base64 with padding on output, accepting input with or without it
+const BASE64 : GeneralPurpose = GeneralPurpose::new( &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_encode_padding( true ).with_decode_padding_mode( DecodePaddingMode::Indifferent ) );
+
+// RFC 4648 Section 5 base64url without padding on output
+const BASE64_URL : GeneralPurpose = GeneralPurpose::new( &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_encode_padding( false ).with_decode_padding_mode( DecodePaddingMode::Indifferent ) );
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Hex,
+    Base64,
+    Base64Url,
+    Raw,
+    Utf8
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EncodingError {
+    UnknownEncoding,
+    InvalidHex,
+    InvalidBase64,
+    InvalidUtf8
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        let message = match self {
+            EncodingError::UnknownEncoding => "unknown encoding",
+            EncodingError::InvalidHex => "text is not hex",
+            EncodingError::InvalidBase64 => "text is not base64",
+            EncodingError::InvalidUtf8 => "text is not UTF-8"
+        };
+
+        write!( f, "{}", message )
+    }
+}
+
+impl error::Error for EncodingError {}
+
+impl FromStr for Encoding {
+    type Err = EncodingError;
+
+    fn from_str( name : &str ) -> Result<Self, Self::Err> {
+        match name {
+            "hex" => Ok( Encoding::Hex ),
+            "base64" => Ok( Encoding::Base64 ),
+            "base64url" => Ok( Encoding::Base64Url ),
+            "raw" => Ok( Encoding::Raw ),
+            "utf8" => Ok( Encoding::Utf8 ),
+            _ => Err( EncodingError::UnknownEncoding )
+        }
+    }
+}
+
+impl Encoding {
+    // text encodings skip whitespace on input and end with a newline on output
+    pub fn is_text( &self ) -> bool {
+        matches!( self, Encoding::Hex | Encoding::Base64 | Encoding::Base64Url )
+    }
+}
+
+pub fn encode( data : &[u8], encoding : Encoding ) -> Result<Vec<u8>, EncodingError> {
+    let mut encoder = Encoder::new( encoding );
+    let mut output = encoder.update( data )?;
+
+    output.extend( encoder.finalize()? );
+
+    Ok( output )
+}
+
+pub fn decode( input : &[u8], encoding : Encoding ) -> Result<Vec<u8>, EncodingError> {
+    let mut decoder = Decoder::new( encoding );
+    let mut output = decoder.update( input )?;
+
+    output.extend( decoder.finalize()? );
+
+    Ok( output )
+}
+
+// Convert between an encoding and the hex text taken by the cipher functions
+pub fn to_hex_text( input : &[u8], encoding : Encoding ) -> Result<String, EncodingError> {
+    Ok( hex::encode( decode( input, encoding )? ) )
+}
+
+pub fn from_hex_text( hex_text : String, encoding : Encoding ) -> Result<Vec<u8>, EncodingError> {
+    let data = hex::decode( hex_text ).map_err( | _ | EncodingError::InvalidHex )?;
+
+    encode( &data, encoding )
+}
+
+// Incremental encoder. Bytes that cannot be encoded yet are kept until the next update
+pub struct Encoder {
+    encoding : Encoding,
+    pending : Vec<u8>
+}
+
+impl Encoder {
+    pub fn new( encoding : Encoding ) -> Self {
+        Encoder { encoding, pending : Vec::new() }
+    }
+
+    pub fn update( &mut self, data : &[u8] ) -> Result<Vec<u8>, EncodingError> {
+        self.pending.extend_from_slice( data );
+
+        let ready_len = match self.encoding {
+            Encoding::Hex | Encoding::Raw => self.pending.len(),
+            // 3 bytes are 4 base64 characters
+            Encoding::Base64 | Encoding::Base64Url => self.pending.len() / 3 * 3,
+            Encoding::Utf8 => get_utf8_complete_len( &self.pending )?
+        };
+
+        let ready : Vec<u8> = self.pending.drain( ..ready_len ).collect();
+
+        Ok( encode_block( &ready, self.encoding ) )
+    }
+
+    pub fn finalize( self ) -> Result<Vec<u8>, EncodingError> {
+        if self.encoding == Encoding::Utf8 && !self.pending.is_empty() {
+            return Err( EncodingError::InvalidUtf8 );
+        }
+
+        Ok( encode_block( &self.pending, self.encoding ) )
+    }
+}
+
+// Incremental decoder. Characters that cannot be decoded yet are kept until the next update
+pub struct Decoder {
+    encoding : Encoding,
+    pending : Vec<u8>
+}
+
+impl Decoder {
+    pub fn new( encoding : Encoding ) -> Self {
+        Decoder { encoding, pending : Vec::new() }
+    }
+
+    pub fn update( &mut self, input : &[u8] ) -> Result<Vec<u8>, EncodingError> {
+        if self.encoding.is_text() {
+            self.pending.extend( input.iter().filter( | byte | !byte.is_ascii_whitespace() ) );
+        }
+        else {
+            self.pending.extend_from_slice( input );
+        }
+
+        let ready_len = match self.encoding {
+            Encoding::Hex => self.pending.len() / 2 * 2,
+            // the padding can only be in the last 4 characters
+            Encoding::Base64 | Encoding::Base64Url => self.pending.len().saturating_sub( 1 ) / 4 * 4,
+            Encoding::Raw => self.pending.len(),
+            Encoding::Utf8 => get_utf8_complete_len( &self.pending )?
+        };
+
+        let ready : Vec<u8> = self.pending.drain( ..ready_len ).collect();
+
+        decode_block( &ready, self.encoding )
+    }
+
+    pub fn finalize( self ) -> Result<Vec<u8>, EncodingError> {
+        if self.encoding == Encoding::Utf8 && !self.pending.is_empty() {
+            return Err( EncodingError::InvalidUtf8 );
+        }
+
+        decode_block( &self.pending, self.encoding )
+    }
+}
+
+fn encode_block( data : &[u8], encoding : Encoding ) -> Vec<u8> {
+    match encoding {
+        Encoding::Hex => hex::encode( data ).into_bytes(),
+        Encoding::Base64 => BASE64.encode( data ).into_bytes(),
+        Encoding::Base64Url => BASE64_URL.encode( data ).into_bytes(),
+        Encoding::Raw | Encoding::Utf8 => data.to_vec()
+    }
+}
+
+fn decode_block( input : &[u8], encoding : Encoding ) -> Result<Vec<u8>, EncodingError> {
+    match encoding {
+        Encoding::Hex => hex::decode( input ).map_err( | _ | EncodingError::InvalidHex ),
+        Encoding::Base64 => BASE64.decode( input ).map_err( | _ | EncodingError::InvalidBase64 ),
+        Encoding::Base64Url => BASE64_URL.decode( input ).map_err( | _ | EncodingError::InvalidBase64 ),
+        Encoding::Raw | Encoding::Utf8 => Ok( input.to_vec() )
+    }
+}
+
+// Length of the valid UTF-8 prefix, leaving out a character cut at the end
+fn get_utf8_complete_len( data : &[u8] ) -> Result<usize, EncodingError> {
+    match str::from_utf8( data ) {
+        Ok( _ ) => Ok( data.len() ),
+        Err( e ) if e.error_len().is_none() => Ok( e.valid_up_to() ),
+        Err( _ ) => Err( EncodingError::InvalidUtf8 )
+    }
+}
+
+#[test]
+fn test_encode() {
+
+    //RFC 4648 Section 10: Test Vectors
+    let data = ["", "f", "fo", "foo", "foob", "fooba", "foobar"];
+
+    let expect_base64 = ["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"];
+    let expect_base64_url = ["", "Zg", "Zm8", "Zm9v", "Zm9vYg", "Zm9vYmE", "Zm9vYmFy"];
+    let expect_hex = ["", "66", "666f", "666f6f", "666f6f62", "666f6f6261", "666f6f626172"];
+
+    for i in 0..data.len() {
+        assert_eq!( encode( data[i].as_bytes(), Encoding::Base64 ).unwrap(), expect_base64[i].as_bytes() );
+        assert_eq!( encode( data[i].as_bytes(), Encoding::Base64Url ).unwrap(), expect_base64_url[i].as_bytes() );
+        assert_eq!( encode( data[i].as_bytes(), Encoding::Hex ).unwrap(), expect_hex[i].as_bytes() );
+
+        assert_eq!( decode( expect_base64[i].as_bytes(), Encoding::Base64 ).unwrap(), data[i].as_bytes() );
+        assert_eq!( decode( expect_base64_url[i].as_bytes(), Encoding::Base64Url ).unwrap(), data[i].as_bytes() );
+        assert_eq!( to_hex_text( data[i].as_bytes(), Encoding::Utf8 ).unwrap(), expect_hex[i] );
+    }
+}
+
+#[test]
+fn test_encoding_error() {
+    assert_eq!( "base32".parse::<Encoding>(), Err( EncodingError::UnknownEncoding ) );
+    assert_eq!( decode( b"0g", Encoding::Hex ), Err( EncodingError::InvalidHex ) );
+    assert_eq!( decode( b"Zm9v+", Encoding::Base64Url ), Err( EncodingError::InvalidBase64 ) );
+    assert_eq!( from_hex_text( "c328".to_string(), Encoding::Utf8 ), Err( EncodingError::InvalidUtf8 ) );
+    assert_eq!( from_hex_text( "e282".to_string(), Encoding::Utf8 ), Err( EncodingError::InvalidUtf8 ) );
+}
+
+#[test]
+fn test_update_split() {
+    let data = "AES \u{00e9}\u{20ac}\u{1f511} block cipher".as_bytes();
+    let encodings = [Encoding::Hex, Encoding::Base64, Encoding::Base64Url, Encoding::Raw, Encoding::Utf8];
+
+    // every split point, including inside a base64 group and a multi-byte character
+    for encoding in encodings {
+        let encoded = encode( data, encoding ).unwrap();
+
+        for split in 0..encoded.len() {
+            let mut decoder = Decoder::new( encoding );
+            let mut decoded = decoder.update( &encoded[..split] ).unwrap();
+            decoded.extend( decoder.update( &encoded[split..] ).unwrap() );
+            decoded.extend( decoder.finalize().unwrap() );
+
+            assert_eq!( decoded, data );
+        }
+
+        for split in 0..data.len() {
+            let mut encoder = Encoder::new( encoding );
+            let mut output = encoder.update( &data[..split] ).unwrap();
+            output.extend( encoder.update( &data[split..] ).unwrap() );
+            output.extend( encoder.finalize().unwrap() );
+
+            assert_eq!( output, encoded );
+        }
+    }
+
+    // whitespace such as line breaks is skipped in text encodings
+    assert_eq!( decode( b"Zm9v\nYmFy\n", Encoding::Base64 ).unwrap(), b"foobar" );
+}
\ No newline at end of file
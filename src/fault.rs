@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+use crate::aes::KeyError;
+use crate::aes_common::{self, KeySize};
+pub use crate::aes_common::Operation;
+use crate::block_cipher::{self, RoundKeys, State, BLOCK_LENGTH};
+use crate::key_inversion::{self, CipherKey};
+
+#[cfg(test)]
+use hex;
+
+type Block = [u8; BLOCK_LENGTH];
+
+// MixColumns matrix(FIPS 197 5.1.3). A difference in row f of a column becomes column f of it
+const MIX_COLUMNS_MATRIX : [[u8; 4]; 4] = [[0x02, 0x03, 0x01, 0x01],
+                                           [0x01, 0x02, 0x03, 0x01],
+                                           [0x01, 0x01, 0x02, 0x03],
+                                           [0x03, 0x01, 0x01, 0x02]];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    // the byte is xored with the mask
+    Xor( u8 ),
+    // the byte is replaced with the value
+    StuckAt( u8 ),
+    // the operation is not executed
+    SkipOperation,
+    // no operation of the round is executed
+    SkipRound
+}
+
+// A fault injected into the state just before an operation of a round.
+// Round 0 is the initial AddRoundKey. The position is a byte index of the block(row + 4 * column),
+// which SkipOperation and SkipRound ignore, and SkipRound also ignores the operation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fault {
+    pub round : usize,
+    pub operation : Operation,
+    pub position : usize,
+    pub kind : FaultKind
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultError {
+    // the round and Nr
+    InvalidRound { round : usize, round_num : usize },
+    // round 0 has only AddRoundKey, and the last round has no MixColumns
+    InvalidOperation { round : usize, operation : Operation },
+    InvalidPosition( usize )
+}
+
+impl fmt::Display for FaultError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            FaultError::InvalidRound { round, round_num } => write!( f, "round must be 0 to {}, but it is {}", round_num, round ),
+            FaultError::InvalidOperation { round, operation } => write!( f, "round {} has no {:?}", round, operation ),
+            FaultError::InvalidPosition( position ) => write!( f, "byte position must be 0 to 15, but it is {}", position )
+        }
+    }
+}
+
+impl error::Error for FaultError {}
+
+// The cipher text without and with the same fault
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CiphertextPair {
+    pub correct : Block,
+    pub faulty : Block
+}
+
+// AES encryption with the round functions of aes_common, where a fault can be injected between any two operations
+#[derive(Clone)]
+pub struct FaultyAes {
+    round_keys : RoundKeys
+}
+
+impl FaultyAes {
+    pub fn new( key : &[u8] ) -> Result<Self, KeyError> {
+        Ok( FaultyAes { round_keys : RoundKeys::new( key )? } )
+    }
+
+    pub fn encrypt( &self, block : &Block ) -> Block {
+        self.run( block, None )
+    }
+
+    pub fn encrypt_with_fault( &self, block : &Block, fault : &Fault ) -> Result<Block, FaultError> {
+        self.check_fault( fault )?;
+
+        Ok( self.run( block, Some( fault ) ) )
+    }
+
+    pub fn encrypt_pair( &self, block : &Block, fault : &Fault ) -> Result<CiphertextPair, FaultError> {
+        Ok( CiphertextPair { correct : self.encrypt( block ), faulty : self.encrypt_with_fault( block, fault )? } )
+    }
+
+    fn check_fault( &self, fault : &Fault ) -> Result<(), FaultError> {
+        let round_num = self.round_keys.round_num();
+
+        if fault.round > round_num {
+            return Err( FaultError::InvalidRound { round : fault.round, round_num } );
+        }
+
+        if fault.kind != FaultKind::SkipRound && !aes_common::operations( fault.round, round_num ).contains( &fault.operation ) {
+            return Err( FaultError::InvalidOperation { round : fault.round, operation : fault.operation } );
+        }
+
+        if fault.position >= BLOCK_LENGTH {
+            return Err( FaultError::InvalidPosition( fault.position ) );
+        }
+
+        Ok( () )
+    }
+
+    fn run( &self, block : &Block, fault : Option<&Fault> ) -> Block {
+        let round_num = self.round_keys.round_num();
+        let mut state = block_cipher::block_to_state( block );
+
+        for round in 0..=round_num {
+            let round_fault = fault.filter( | fault | fault.round == round );
+
+            if round_fault.map( | fault | fault.kind ) == Some( FaultKind::SkipRound ) {
+                continue;
+            }
+
+            for &operation in aes_common::operations( round, round_num ) {
+                if let Some( fault ) = round_fault.filter( | fault | fault.operation == operation ) {
+                    let ( row, col ) = ( fault.position % 4, fault.position / 4 );
+
+                    match fault.kind {
+                        FaultKind::Xor( mask ) => state[row][col] ^= mask,
+                        FaultKind::StuckAt( value ) => state[row][col] = value,
+                        FaultKind::SkipOperation => continue,
+                        FaultKind::SkipRound => unreachable!()
+                    }
+                }
+
+                state = self.apply( operation, state, round );
+            }
+        }
+
+        let mut output = [0; BLOCK_LENGTH];
+        block_cipher::state_to_block( state, &mut output );
+
+        output
+    }
+
+    fn apply( &self, operation : Operation, state : State, round : usize ) -> State {
+        match operation {
+            Operation::SubBytes => aes_common::sub_bytes( state ),
+            Operation::ShiftRows => aes_common::shift_rows( state ),
+            Operation::MixColumns => aes_common::mix_columns( state ),
+            Operation::AddRoundKey => block_cipher::add_round_key( state, self.round_keys.round_key( round ) )
+        }
+    }
+}
+
+// Differential fault analysis of Piret and Quisquater on AES-128.
+// Each pair has a fault in one byte of the state before MixColumns of round 9, which makes a difference
+// in the 4 bytes of one column, and in 4 bytes of the cipher text after the last ShiftRows.
+// The candidates of those 4 bytes of the last round key are intersected over the pairs of each column,
+// and the cipher key is recovered from the last round key by inverting the key schedule.
+// None when some column is not left with a single candidate. Usually two pairs per column are enough
+pub fn piret_quisquater( pairs : &[CiphertextPair] ) -> Option<CipherKey> {
+    let mut column_candidates : [Option<HashSet<[u8; 4]>>; 4] = [None, None, None, None];
+
+    for pair in pairs {
+        let col = match faulty_column( pair ) {
+            Some( col ) => col,
+            None => continue
+        };
+
+        let candidates = last_round_key_candidates( pair, col );
+
+        column_candidates[col] = Some( match column_candidates[col].take() {
+            Some( previous ) => previous.intersection( &candidates ).copied().collect(),
+            None => candidates
+        } );
+    }
+
+    let mut last_round_key = [0; BLOCK_LENGTH];
+
+    for ( col, candidates ) in column_candidates.iter().enumerate() {
+        let candidates = candidates.as_ref()?;
+
+        if candidates.len() != 1 {
+            return None;
+        }
+
+        let key_bytes = candidates.iter().next()?;
+
+        for row in 0..4 {
+            last_round_key[output_position( row, col )] = key_bytes[row];
+        }
+    }
+
+    key_inversion::recover_aes128_key( KeySize::Aes128.round_num(), &last_round_key ).ok()
+}
+
+// The cipher text byte of row in the column col before the last ShiftRows
+fn output_position( row : usize, col : usize ) -> usize {
+    row + 4 * ( ( col + 4 - row ) % 4 )
+}
+
+// The column before the last ShiftRows whose 4 bytes are the only differences of the pair
+fn faulty_column( pair : &CiphertextPair ) -> Option<usize> {
+    ( 0..4 ).find( | &col | {
+        ( 0..BLOCK_LENGTH ).all( | position | {
+            let is_in_column = ( 0..4 ).any( | row | output_position( row, col ) == position );
+            ( pair.correct[position] != pair.faulty[position] ) == is_in_column
+        } )
+    } )
+}
+
+// For each row, a key byte k is a candidate when InvSubBytes of the two cipher text bytes xor k differ by
+// the MixColumns coefficient of the row times the fault value e. All 4 rows have to agree on the faulty row and e
+fn last_round_key_candidates( pair : &CiphertextPair, col : usize ) -> HashSet<[u8; 4]> {
+    let mut key_bytes_by_difference = vec![vec![Vec::new(); 256]; 4];
+
+    for ( row, key_bytes ) in key_bytes_by_difference.iter_mut().enumerate() {
+        let position = output_position( row, col );
+
+        for k in 0..=255u8 {
+            let difference = inv_s_box( pair.correct[position] ^ k ) ^ inv_s_box( pair.faulty[position] ^ k );
+            key_bytes[difference as usize].push( k );
+        }
+    }
+
+    let mut candidates = HashSet::new();
+
+    for fault_row in 0..4 {
+        for e in 1..=255u8 {
+            let key_bytes : Vec<&Vec<u8>> = ( 0..4 ).map( | row | {
+                &key_bytes_by_difference[row][aes_common::multiplication( e, MIX_COLUMNS_MATRIX[row][fault_row] ) as usize]
+            } ).collect();
+
+            for &k0 in key_bytes[0] {
+                for &k1 in key_bytes[1] {
+                    for &k2 in key_bytes[2] {
+                        for &k3 in key_bytes[3] {
+                            candidates.insert( [k0, k1, k2, k3] );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    candidates
+}
+
+fn inv_s_box( input : u8 ) -> u8 {
+    aes_common::INV_S_BOX[( input >> 4 ) as usize][( input & 0x0f ) as usize]
+}
+
+#[test]
+fn test_faulty_aes() {
+    //FIPS 197 Appendix C.1 AES-128
+    let text = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+    let aes = FaultyAes::new( &hex::decode( "000102030405060708090a0b0c0d0e0f" ).unwrap() ).unwrap();
+
+    assert_eq!( hex::encode( aes.encrypt( &text ) ), "69c4e0d86a7b0430d8cdb78070b4c55a" );
+
+    // a fault after the last SubBytes and ShiftRows goes straight to the cipher text
+    let fault = Fault { round : 10, operation : Operation::AddRoundKey, position : 5, kind : FaultKind::Xor( 0x01 ) };
+    let pair = aes.encrypt_pair( &text, &fault ).unwrap();
+    assert_eq!( hex::encode( pair.faulty ), "69c4e0d86a7a0430d8cdb78070b4c55a" );
+
+    // without the last AddRoundKey, the difference is the last round key
+    let fault = Fault { round : 10, operation : Operation::AddRoundKey, position : 0, kind : FaultKind::SkipOperation };
+    let pair = aes.encrypt_pair( &text, &fault ).unwrap();
+    let difference : Vec<u8> = pair.correct.iter().zip( pair.faulty.iter() ).map( | ( x, y ) | x ^ y ).collect();
+    assert_eq!( hex::encode( difference ), "13111d7fe3944a17f307a78b4d2b30c5" );
+
+    // skipping the last round leaves round[10].start of Appendix C.1
+    let fault = Fault { round : 10, operation : Operation::SubBytes, position : 0, kind : FaultKind::SkipRound };
+    assert_eq!( hex::encode( aes.encrypt_with_fault( &text, &fault ).unwrap() ), "bd6e7c3df2b5779e0b61216e8b10b689" );
+
+    // round[1].start byte 0 is 00, so stuck at 00 changes nothing
+    let fault = Fault { round : 1, operation : Operation::SubBytes, position : 0, kind : FaultKind::StuckAt( 0x00 ) };
+    assert_eq!( aes.encrypt_with_fault( &text, &fault ).unwrap(), pair.correct );
+
+    let fault = Fault { round : 10, operation : Operation::MixColumns, position : 0, kind : FaultKind::Xor( 0x01 ) };
+    assert_eq!( aes.encrypt_pair( &text, &fault ).err(), Some( FaultError::InvalidOperation { round : 10, operation : Operation::MixColumns } ) );
+
+    let fault = Fault { round : 11, operation : Operation::SubBytes, position : 0, kind : FaultKind::Xor( 0x01 ) };
+    assert_eq!( aes.encrypt_pair( &text, &fault ).err(), Some( FaultError::InvalidRound { round : 11, round_num : 10 } ) );
+
+    let fault = Fault { round : 9, operation : Operation::MixColumns, position : 16, kind : FaultKind::Xor( 0x01 ) };
+    assert_eq!( aes.encrypt_pair( &text, &fault ).err(), Some( FaultError::InvalidPosition( 16 ) ) );
+}
+
+#[test]
+fn test_piret_quisquater() {
+    //FIPS 197 Appendix A.1 key
+    let key = hex::decode( "2b7e151628aed2a6abf7158809cf4f3c" ).unwrap();
+    let aes = FaultyAes::new( &key ).unwrap();
+
+    // xorshift64 for reproducible plain texts and faults
+    let mut seed : u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut next_byte = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as u8
+    };
+
+    let mut pairs = Vec::new();
+
+    // two faults in every column before MixColumns of round 9
+    for position in [0, 1, 4, 6, 8, 11, 13, 14] {
+        let mut text = [0; BLOCK_LENGTH];
+        text.iter_mut().for_each( | byte | *byte = next_byte() );
+
+        let fault = Fault { round : 9, operation : Operation::MixColumns, position, kind : FaultKind::Xor( next_byte() | 0x01 ) };
+        pairs.push( aes.encrypt_pair( &text, &fault ).unwrap() );
+    }
+
+    assert_eq!( piret_quisquater( &pairs ).unwrap().as_bytes(), &key[..] );
+
+    // one pair per column, from the faults at positions 0, 4, 8 and 13, leaves more than one candidate in every column
+    let one_per_column : Vec<CiphertextPair> = pairs.iter().step_by( 2 ).cloned().collect();
+    let mut columns : Vec<usize> = one_per_column.iter().map( | pair | faulty_column( pair ).unwrap() ).collect();
+
+    for ( pair, &col ) in one_per_column.iter().zip( &columns ) {
+        assert!( last_round_key_candidates( pair, col ).len() > 1 );
+    }
+
+    columns.sort();
+    assert_eq!( columns, [0, 1, 2, 3] );
+    assert!( piret_quisquater( &one_per_column ).is_none() );
+}
\ No newline at end of file
@@ -0,0 +1,172 @@
+use core::error;
+use core::fmt;
+use crate::aes_common::{self, KeySize};
+use crate::block_cipher::{self, RoundKeys, BLOCK_LENGTH, MAX_ROUND_KEY_NUM};
+use crate::zeroize::Zeroize;
+
+#[cfg(test)]
+use hex;
+
+// the longest key, AES-256
+const MAX_KEY_LENGTH : usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyInversionError {
+    // the number of words given and Nk of the key size
+    InvalidWordNum { word_num : usize, key_word_num : usize },
+    // the words run past the last word of the key schedule
+    InvalidPosition { first_word : usize, round_key_word_num : usize }
+}
+
+impl fmt::Display for KeyInversionError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            KeyInversionError::InvalidWordNum { word_num, key_word_num } => {
+                write!( f, "{} consecutive words of the key schedule are needed, but {} are given", key_word_num, word_num )
+            },
+            KeyInversionError::InvalidPosition { first_word, round_key_word_num } => {
+                write!( f, "words from {} run past the key schedule of {} words", first_word, round_key_word_num )
+            }
+        }
+    }
+}
+
+impl error::Error for KeyInversionError {}
+
+// A cipher key of any key size, wiped when dropped
+#[derive(Clone)]
+pub struct CipherKey {
+    bytes : [u8; MAX_KEY_LENGTH],
+    key_size : KeySize
+}
+
+impl CipherKey {
+    pub fn key_size( &self ) -> KeySize {
+        self.key_size
+    }
+
+    pub fn as_bytes( &self ) -> &[u8] {
+        &self.bytes[..self.key_size.key_length()]
+    }
+}
+
+impl Drop for CipherKey {
+    fn drop( &mut self ) {
+        self.bytes.zeroize();
+    }
+}
+
+// Run the key expansion(FIPS 197 5.2) backwards from Nk consecutive words w[first_word..first_word + Nk].
+// Each word w[i - Nk] is w[i] xor a function of w[i - 1], so Nk words in a row determine the key.
+// That is one round key for AES-128, one and a half for AES-192 and two for AES-256
+pub fn recover_cipher_key( key_size : KeySize, first_word : usize, words : &[u32] ) -> Result<CipherKey, KeyInversionError> {
+    let key_word_num = key_size.key_length() / aes_common::WORD_IN_BYTES_NUM;
+    let round_key_word_num = aes_common::BLOCK_SIZE * ( key_size.round_num() + 1 );
+
+    if words.len() != key_word_num {
+        return Err( KeyInversionError::InvalidWordNum { word_num : words.len(), key_word_num } );
+    }
+
+    if first_word + key_word_num > round_key_word_num {
+        return Err( KeyInversionError::InvalidPosition { first_word, round_key_word_num } );
+    }
+
+    let mut schedule = [0; MAX_ROUND_KEY_NUM];
+    schedule[first_word..( first_word + key_word_num )].copy_from_slice( words );
+
+    for i in ( key_word_num..( first_word + key_word_num ) ).rev() {
+        schedule[i - key_word_num] = schedule[i] ^ block_cipher::expansion_temp( schedule[i - 1], i, key_word_num, aes_common::sub_word );
+    }
+
+    let mut key = CipherKey { bytes : [0; MAX_KEY_LENGTH], key_size };
+
+    for ( i, word ) in schedule.iter().take( key_word_num ).enumerate() {
+        key.bytes[( i * 4 )..( i * 4 + 4 )].copy_from_slice( &word.to_be_bytes() );
+    }
+
+    schedule.zeroize();
+
+    Ok( key )
+}
+
+// The whole key schedule from the same words as recover_cipher_key
+pub fn recover_round_keys( key_size : KeySize, first_word : usize, words : &[u32] ) -> Result<RoundKeys, KeyInversionError> {
+    let key = recover_cipher_key( key_size, first_word, words )?;
+
+    Ok( RoundKeys::new( key.as_bytes() ).expect( "Failed to expand a recovered key" ) )
+}
+
+// The AES-128 key from the round key of any round, such as the last round key found by an attack
+pub fn recover_aes128_key( round : usize, round_key : &[u8; BLOCK_LENGTH] ) -> Result<CipherKey, KeyInversionError> {
+    let mut words = [0; aes_common::BLOCK_SIZE];
+
+    for ( word, bytes ) in words.iter_mut().zip( round_key.chunks_exact( aes_common::WORD_IN_BYTES_NUM ) ) {
+        *word = u32::from_be_bytes( [bytes[0], bytes[1], bytes[2], bytes[3]] );
+    }
+
+    let key = recover_cipher_key( KeySize::Aes128, round * aes_common::BLOCK_SIZE, &words );
+    words.zeroize();
+
+    key
+}
+
+// every word of the key schedule, from the round keys
+#[cfg(all(test, feature = "std"))]
+fn schedule_words( round_keys : &RoundKeys ) -> Vec<u32> {
+    ( 0..=round_keys.round_num() ).flat_map( | round | round_keys.round_key( round ).to_vec() ).collect()
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_recover_cipher_key() {
+    //FIPS 197 Appendix A Key Expansion Examples, the last words of each expansion
+    let key = ["2b7e151628aed2a6abf7158809cf4f3c",
+               "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+               "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4"];
+
+    let key_size = [KeySize::Aes128, KeySize::Aes192, KeySize::Aes256];
+
+    let last_words : [&[u32]; 3] = [&[0xd014_f9a8, 0xc9ee_2589, 0xe13f_0cc8, 0xb663_0ca6],
+                                    &[0xe98b_a06f, 0x448c_773c, 0x8ecc_7204, 0x0100_2202],
+                                    &[0xfe48_90d1, 0xe618_8d0b, 0x046d_f344, 0x706c_631e]];
+
+    for i in 0..key.len() {
+        let round_keys = RoundKeys::new( &hex::decode( key[i] ).unwrap() ).unwrap();
+        let words = schedule_words( &round_keys );
+        let key_word_num = key_size[i].key_length() / 4;
+
+        assert_eq!( &words[( words.len() - 4 )..], last_words[i] );
+
+        // from every position of the schedule
+        for first_word in 0..=( words.len() - key_word_num ) {
+            let recovered_key = recover_cipher_key( key_size[i], first_word, &words[first_word..( first_word + key_word_num )] ).unwrap();
+            assert_eq!( hex::encode( recovered_key.as_bytes() ), key[i] );
+            assert_eq!( recovered_key.key_size(), key_size[i] );
+        }
+
+        let first_word = words.len() - key_word_num;
+        let recovered_round_keys = recover_round_keys( key_size[i], first_word, &words[first_word..] ).unwrap();
+        assert_eq!( schedule_words( &recovered_round_keys ), words );
+    }
+}
+
+#[test]
+fn test_recover_aes128_key() {
+    //FIPS 197 Appendix A.1, the round keys of round 1 and round 10
+    let round_key = ["a0fafe1788542cb123a339392a6c7605",
+                     "d014f9a8c9ee2589e13f0cc8b6630ca6"];
+
+    let round = [1, 10];
+
+    for i in 0..round_key.len() {
+        let mut block = [0; BLOCK_LENGTH];
+        block.copy_from_slice( &hex::decode( round_key[i] ).unwrap() );
+
+        assert_eq!( hex::encode( recover_aes128_key( round[i], &block ).unwrap().as_bytes() ), "2b7e151628aed2a6abf7158809cf4f3c" );
+    }
+
+    assert_eq!( recover_aes128_key( 11, &[0; BLOCK_LENGTH] ).err(),
+                Some( KeyInversionError::InvalidPosition { first_word : 44, round_key_word_num : 44 } ) );
+    assert_eq!( recover_cipher_key( KeySize::Aes256, 0, &[0; 4] ).err(),
+                Some( KeyInversionError::InvalidWordNum { word_num : 4, key_word_num : 8 } ) );
+}
\ No newline at end of file
@@ -0,0 +1,192 @@
+use std::error;
+use std::fmt;
+use hex;
+use crate::zeroize::Zeroizing;
+use crate::block_cipher::CipherScope;
+
+#[cfg(test)]
+use crate::{aes128, aes192, aes256};
+
+const SEMIBLOCK_SIZE : usize = 8;
+
+// RFC 3394 Section 2.2.3.1 default initial value
+const DEFAULT_IV : [u8; SEMIBLOCK_SIZE] = [0xa6; SEMIBLOCK_SIZE];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyWrapError {
+    InvalidHex,
+    InvalidLength( usize ),
+    TooShort( usize ),
+    IntegrityCheckFailed
+}
+
+impl fmt::Display for KeyWrapError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            KeyWrapError::InvalidHex => write!( f, "key data must be a hex string" ),
+            KeyWrapError::InvalidLength( length ) => write!( f, "key data must be a multiple of 64 bits, but it is {} bytes", length ),
+            KeyWrapError::TooShort( min_semiblocks ) => write!( f, "key data must be at least {} 64-bit blocks", min_semiblocks ),
+            KeyWrapError::IntegrityCheckFailed => write!( f, "integrity check of the unwrapped key failed" )
+        }
+    }
+}
+
+impl error::Error for KeyWrapError {}
+
+// RFC 3394 Section 2.2.1 Key Wrap
+pub fn wrap_key( key_data : String, kek : String, cipher_func : fn( String, &str ) -> String ) -> Result<String, KeyWrapError> {
+    let key_data = Zeroizing::new( key_data );
+    let kek = Zeroizing::new( kek );
+    let _cipher_scope = CipherScope::new( &kek );
+    let plain_data = Zeroizing::new( hex::decode( &*key_data ).map_err( | _ | KeyWrapError::InvalidHex )? );
+
+    check_length( plain_data.len(), 2 )?;
+
+    let mut a = DEFAULT_IV;
+    let mut r : Zeroizing<Vec<[u8; SEMIBLOCK_SIZE]>> = Zeroizing::new( plain_data.chunks( SEMIBLOCK_SIZE ).map( to_semiblock ).collect() );
+    let n = r.len();
+
+    for j in 0..6 {
+        for i in 0..n {
+            let b = process_block( a, r[i], &kek, cipher_func );
+            let t = ( n * j + i + 1 ) as u64;
+
+            a = xor_semiblock( to_semiblock( &b[..SEMIBLOCK_SIZE] ), t.to_be_bytes() );
+            r[i] = to_semiblock( &b[SEMIBLOCK_SIZE..] );
+        }
+    }
+
+    Ok( hex::encode( a ) + &hex::encode( &*Zeroizing::new( r.concat() ) ) )
+}
+
+// RFC 3394 Section 2.2.2 Key Unwrap
+pub fn unwrap_key( wrapped_key : String, kek : String, inv_cipher_func : fn( String, &str ) -> String ) -> Result<Zeroizing<String>, KeyWrapError> {
+    let kek = Zeroizing::new( kek );
+    let _cipher_scope = CipherScope::new( &kek );
+    let cipher_data = hex::decode( wrapped_key ).map_err( | _ | KeyWrapError::InvalidHex )?;
+
+    check_length( cipher_data.len(), 3 )?;
+
+    let mut a = to_semiblock( &cipher_data[..SEMIBLOCK_SIZE] );
+    let mut r : Zeroizing<Vec<[u8; SEMIBLOCK_SIZE]>> = Zeroizing::new( cipher_data[SEMIBLOCK_SIZE..].chunks( SEMIBLOCK_SIZE ).map( to_semiblock ).collect() );
+    let n = r.len();
+
+    for j in ( 0..6 ).rev() {
+        for i in ( 0..n ).rev() {
+            let t = ( n * j + i + 1 ) as u64;
+            let b = process_block( xor_semiblock( a, t.to_be_bytes() ), r[i], &kek, inv_cipher_func );
+
+            a = to_semiblock( &b[..SEMIBLOCK_SIZE] );
+            r[i] = to_semiblock( &b[SEMIBLOCK_SIZE..] );
+        }
+    }
+
+    // compare every byte so that the time does not depend on the position of a mismatch
+    let difference = xor_semiblock( a, DEFAULT_IV ).iter().fold( 0, | acc, byte | acc | byte );
+
+    if difference != 0 {
+        return Err( KeyWrapError::IntegrityCheckFailed );
+    }
+
+    Ok( Zeroizing::new( hex::encode( &*Zeroizing::new( r.concat() ) ) ) )
+}
+
+// A || R[i] through the cipher. The register holds the key data, so every copy of the block is wiped
+fn process_block( a : [u8; SEMIBLOCK_SIZE], r : [u8; SEMIBLOCK_SIZE], kek : &str, block_func : fn( String, &str ) -> String ) -> Zeroizing<Vec<u8>> {
+    let input = Zeroizing::new( [a, r].concat() );
+    let output = Zeroizing::new( block_func( hex::encode( &*input ), kek ) );
+
+    Zeroizing::new( hex::decode( &*output ).expect( "Failed to convert block in key_wrap" ) )
+}
+
+fn check_length( length : usize, min_semiblocks : usize ) -> Result<(), KeyWrapError> {
+    if !length.is_multiple_of( SEMIBLOCK_SIZE ) {
+        return Err( KeyWrapError::InvalidLength( length ) );
+    }
+
+    if length < SEMIBLOCK_SIZE * min_semiblocks {
+        return Err( KeyWrapError::TooShort( min_semiblocks ) );
+    }
+
+    Ok( () )
+}
+
+fn to_semiblock( input : &[u8] ) -> [u8; SEMIBLOCK_SIZE] {
+    let mut semiblock : [u8; SEMIBLOCK_SIZE] = [0; SEMIBLOCK_SIZE];
+
+    semiblock.copy_from_slice( input );
+
+    semiblock
+}
+
+fn xor_semiblock( input1 : [u8; SEMIBLOCK_SIZE], input2 : [u8; SEMIBLOCK_SIZE] ) -> [u8; SEMIBLOCK_SIZE] {
+    let mut output : [u8; SEMIBLOCK_SIZE] = [0; SEMIBLOCK_SIZE];
+
+    for i in 0..SEMIBLOCK_SIZE {
+        output[i] = input1[i] ^ input2[i];
+    }
+
+    output
+}
+
+#[test]
+fn test_wrap_key() {
+
+    //RFC 3394 Section 4: Test Vectors 4.1 - 4.6
+    let kek = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let key_data = ["00112233445566778899aabbccddeeff",
+                    "00112233445566778899aabbccddeeff",
+                    "00112233445566778899aabbccddeeff",
+                    "00112233445566778899aabbccddeeff0001020304050607",
+                    "00112233445566778899aabbccddeeff0001020304050607",
+                    "00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f"];
+
+    let cipher_func = [ aes128::encrypt,
+                        aes192::encrypt,
+                        aes256::encrypt,
+                        aes192::encrypt,
+                        aes256::encrypt,
+                        aes256::encrypt];
+
+    let inv_cipher_func = [ aes128::decrypt,
+                            aes192::decrypt,
+                            aes256::decrypt,
+                            aes192::decrypt,
+                            aes256::decrypt,
+                            aes256::decrypt];
+
+    let expect = ["1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5",
+                  "96778b25ae6ca435f92b5b97c050aed2468ab8a17ad84e5d",
+                  "64e8c3f9ce0f5ba263e9777905818a2a93c8191e7d6e8ae7",
+                  "031d33264e15d33268f24ec260743edce1c6c7ddee725a936ba814915c6762d2",
+                  "a8f9bc1612c68b3ff6e6f4fbe30e71e4769c8b80a32cb8958cd5d17d6b254da1",
+                  "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21"];
+
+    for i in 0..kek.len() {
+        let actual_result = wrap_key( key_data[i].to_string(), kek[i].to_string(), cipher_func[i] );
+        assert_eq!( actual_result, Ok( expect[i].to_string() ) );
+
+        let actual_result = unwrap_key( expect[i].to_string(), kek[i].to_string(), inv_cipher_func[i] ).unwrap();
+        assert_eq!( *actual_result, key_data[i] );
+    }
+}
+
+#[test]
+fn test_unwrap_key_error() {
+    let kek = "000102030405060708090a0b0c0d0e0f";
+
+    // one bit flipped in the last semiblock
+    let wrapped_key = "1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe4";
+
+    assert_eq!( unwrap_key( wrapped_key.to_string(), kek.to_string(), aes128::decrypt ).err(), Some( KeyWrapError::IntegrityCheckFailed ) );
+    assert_eq!( unwrap_key( wrapped_key[..32].to_string(), kek.to_string(), aes128::decrypt ).err(), Some( KeyWrapError::TooShort( 3 ) ) );
+    assert_eq!( wrap_key( "0011223344556677".to_string(), kek.to_string(), aes128::encrypt ), Err( KeyWrapError::TooShort( 2 ) ) );
+    assert_eq!( wrap_key( "00112233445566778899aabbccddeeff0011".to_string(), kek.to_string(), aes128::encrypt ), Err( KeyWrapError::InvalidLength( 18 ) ) );
+    assert_eq!( wrap_key( "00112233445566778899aabbccddeefg".to_string(), kek.to_string(), aes128::encrypt ), Err( KeyWrapError::InvalidHex ) );
+}
\ No newline at end of file
@@ -0,0 +1,453 @@
+use std::io::{self, Write};
+use crate::aes_common::{self, Operation};
+use crate::block_cipher::{self, ReferenceAes, State, BLOCK_LENGTH};
+use crate::masked::MaskedAes;
+
+#[cfg(test)]
+use hex;
+#[cfg(test)]
+use crate::block_cipher::BlockCipher;
+#[cfg(test)]
+use crate::masked;
+
+type Block = [u8; BLOCK_LENGTH];
+
+// NPY format version 1.0, and the alignment of its header
+const NPY_MAGIC : &[u8] = b"\x93NUMPY\x01\x00";
+const NPY_HEADER_ALIGNMENT : usize = 64;
+
+// What a sample leaks of a state byte: its Hamming weight, or the Hamming distance from the same byte before the operation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LeakageModel {
+    HammingWeight,
+    HammingDistance
+}
+
+// A cipher that shows its state after every operation, so that the leakage can be simulated.
+// observe is called with the round, the operation, the share and the state. An unmasked cipher has only share 0
+pub trait LeakageTarget {
+    fn encrypt_observed( &self, block : &mut Block, observe : &mut dyn FnMut( usize, Operation, usize, &State ) );
+}
+
+// The cipher(FIPS 197 5.1) with the round functions of aes_common, one operation at a time
+impl LeakageTarget for ReferenceAes {
+    fn encrypt_observed( &self, block : &mut Block, observe : &mut dyn FnMut( usize, Operation, usize, &State ) ) {
+        let round_keys = self.round_keys();
+        let round_num = round_keys.round_num();
+        let mut state = block_cipher::block_to_state( block );
+
+        for round in 0..=round_num {
+            for &operation in aes_common::operations( round, round_num ) {
+                state = match operation {
+                    Operation::SubBytes => aes_common::sub_bytes( state ),
+                    Operation::ShiftRows => aes_common::shift_rows( state ),
+                    Operation::MixColumns => aes_common::mix_columns( state ),
+                    Operation::AddRoundKey => block_cipher::add_round_key( state, round_keys.round_key( round ) )
+                };
+
+                observe( round, operation, 0, &state );
+            }
+        }
+
+        block_cipher::state_to_block( state, block );
+    }
+}
+
+// Share 0 is the masked state and share 1 its mask, each leaking on its own
+impl LeakageTarget for MaskedAes {
+    fn encrypt_observed( &self, block : &mut Block, observe : &mut dyn FnMut( usize, Operation, usize, &State ) ) {
+        self.encrypt_shares( block, observe );
+    }
+}
+
+// Where a sample comes from. The position is a byte index of the block(row + 4 * column)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplePoint {
+    pub round : usize,
+    pub operation : Operation,
+    pub share : usize,
+    pub position : usize
+}
+
+// One trace per plain text, each with a sample per byte of the state after every operation.
+// The samples of every trace are in the order of points
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceSet {
+    pub plain_texts : Vec<Block>,
+    pub cipher_texts : Vec<Block>,
+    pub points : Vec<SamplePoint>,
+    pub samples : Vec<Vec<f32>>
+}
+
+impl TraceSet {
+    // The samples as a little endian float32 array of shape (traces, samples)
+    pub fn write_npy<W : Write>( &self, writer : &mut W ) -> io::Result<()> {
+        write_npy_header( writer, "<f4", self.samples.len(), self.points.len() )?;
+
+        for trace in &self.samples {
+            for sample in trace {
+                writer.write_all( &sample.to_le_bytes() )?;
+            }
+        }
+
+        Ok( () )
+    }
+
+    // The plain texts as a uint8 array of shape (traces, 16)
+    pub fn write_plain_texts_npy<W : Write>( &self, writer : &mut W ) -> io::Result<()> {
+        write_npy_header( writer, "|u1", self.plain_texts.len(), BLOCK_LENGTH )?;
+
+        for text in &self.plain_texts {
+            writer.write_all( text )?;
+        }
+
+        Ok( () )
+    }
+}
+
+// The header of an NPY file(format version 1.0) for a 2-dimensional array in C order.
+// The header is padded with spaces so that the data starts at a multiple of 64 bytes
+fn write_npy_header<W : Write>( writer : &mut W, descr : &str, rows : usize, cols : usize ) -> io::Result<()> {
+    let mut header = format!( "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}", descr, rows, cols );
+    let unpadded_len = NPY_MAGIC.len() + 2 + header.len() + 1;
+    let padding = ( NPY_HEADER_ALIGNMENT - unpadded_len % NPY_HEADER_ALIGNMENT ) % NPY_HEADER_ALIGNMENT;
+
+    header.push_str( &" ".repeat( padding ) );
+    header.push( '\n' );
+
+    writer.write_all( NPY_MAGIC )?;
+    writer.write_all( &( header.len() as u16 ).to_le_bytes() )?;
+    writer.write_all( header.as_bytes() )
+}
+
+// Synthetic power traces for teaching side-channel analysis.
+// Each sample is the leakage of a state byte plus Gaussian noise, and the plain texts and the noise
+// come from a generator seeded by seed, so the same seed gives the same traces
+pub struct LeakageSimulator {
+    model : LeakageModel,
+    noise_std_dev : f64,
+    rng : Xorshift64
+}
+
+impl LeakageSimulator {
+    pub fn new( model : LeakageModel, noise_std_dev : f64, seed : u64 ) -> Self {
+        LeakageSimulator { model, noise_std_dev, rng : Xorshift64::new( seed ) }
+    }
+
+    pub fn model( &self ) -> LeakageModel {
+        self.model
+    }
+
+    // Encrypt trace_num random plain texts with the target and record their leakage
+    pub fn simulate<T : LeakageTarget + ?Sized>( &mut self, target : &T, trace_num : usize ) -> TraceSet {
+        let mut traces = TraceSet { plain_texts : Vec::new(), cipher_texts : Vec::new(), points : Vec::new(), samples : Vec::new() };
+
+        for _ in 0..trace_num {
+            let mut text = [0; BLOCK_LENGTH];
+            text.iter_mut().for_each( | byte | *byte = self.rng.next_u64() as u8 );
+
+            let mut points = Vec::new();
+            let mut samples = Vec::new();
+            // the state each share held before the operation, which starts from the plain text for share 0
+            let mut previous_states : Vec<State> = vec![block_cipher::block_to_state( &text )];
+            let mut block = text;
+
+            target.encrypt_observed( &mut block, &mut | round, operation, share, state | {
+                if previous_states.len() <= share {
+                    previous_states.resize( share + 1, [[0; 4]; 4] );
+                }
+
+                for position in 0..BLOCK_LENGTH {
+                    let leakage = byte_leakage( self.model, state, &previous_states[share], position );
+
+                    points.push( SamplePoint { round, operation, share, position } );
+                    samples.push( ( leakage as f64 + self.noise_std_dev * self.rng.next_gaussian() ) as f32 );
+                }
+
+                previous_states[share] = *state;
+            } );
+
+            traces.points = points;
+            traces.plain_texts.push( text );
+            traces.cipher_texts.push( block );
+            traces.samples.push( samples );
+        }
+
+        traces
+    }
+}
+
+fn byte_leakage( model : LeakageModel, state : &State, previous_state : &State, position : usize ) -> u32 {
+    let ( row, col ) = ( position % 4, position / 4 );
+
+    match model {
+        LeakageModel::HammingWeight => state[row][col].count_ones(),
+        LeakageModel::HammingDistance => ( state[row][col] ^ previous_state[row][col] ).count_ones()
+    }
+}
+
+// The guess of each byte and its correlation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpaResult {
+    pub round_key : Block,
+    pub correlation : [f64; BLOCK_LENGTH]
+}
+
+// Correlation power analysis of the first round. For each byte of the round key and each guess k,
+// the leakage of SubBytes(p xor k) in the model is predicted from the plain texts, and the guess whose prediction
+// has the largest Pearson correlation with any sample after SubBytes of round 1 is taken.
+// The first round key is the cipher key of AES-128, and the first half of the key otherwise
+pub fn cpa_first_round_key( traces : &TraceSet, model : LeakageModel ) -> CpaResult {
+    let sample_indexes : Vec<usize> = ( 0..traces.points.len() ).filter( | &i | {
+        traces.points[i].round == 1 && traces.points[i].operation == Operation::SubBytes
+    } ).collect();
+
+    let columns : Vec<Vec<f64>> = sample_indexes.iter().map( | &i | {
+        traces.samples.iter().map( | trace | trace[i] as f64 ).collect()
+    } ).collect();
+
+    let mut result = CpaResult { round_key : [0; BLOCK_LENGTH], correlation : [0.0; BLOCK_LENGTH] };
+
+    for position in 0..BLOCK_LENGTH {
+        for k in 0..=255u8 {
+            let hypothesis : Vec<f64> = traces.plain_texts.iter().map( | text | {
+                let input = text[position] ^ k;
+                let output = s_box( input );
+
+                match model {
+                    LeakageModel::HammingWeight => output.count_ones() as f64,
+                    LeakageModel::HammingDistance => ( input ^ output ).count_ones() as f64
+                }
+            } ).collect();
+
+            for column in &columns {
+                let correlation = pearson_correlation( &hypothesis, column ).abs();
+
+                if correlation > result.correlation[position] {
+                    result.correlation[position] = correlation;
+                    result.round_key[position] = k;
+                }
+            }
+        }
+    }
+
+    result
+}
+
+// 0 when either side is constant
+fn pearson_correlation( x : &[f64], y : &[f64] ) -> f64 {
+    let n = x.len() as f64;
+    let ( mut sum_x, mut sum_y, mut sum_xx, mut sum_yy, mut sum_xy ) = ( 0.0, 0.0, 0.0, 0.0, 0.0 );
+
+    for ( &a, &b ) in x.iter().zip( y.iter() ) {
+        sum_x += a;
+        sum_y += b;
+        sum_xx += a * a;
+        sum_yy += b * b;
+        sum_xy += a * b;
+    }
+
+    let covariance = n * sum_xy - sum_x * sum_y;
+    let variance = ( n * sum_xx - sum_x * sum_x ) * ( n * sum_yy - sum_y * sum_y );
+
+    if variance <= 0.0 {
+        return 0.0;
+    }
+
+    covariance / variance.sqrt()
+}
+
+fn s_box( input : u8 ) -> u8 {
+    aes_common::S_BOX[( input >> 4 ) as usize][( input & 0x0f ) as usize]
+}
+
+// xorshift64*, which is fast and reproducible but not for keys
+struct Xorshift64 {
+    state : u64
+}
+
+impl Xorshift64 {
+    fn new( seed : u64 ) -> Self {
+        // the state must not be 0
+        Xorshift64 { state : if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed } }
+    }
+
+    fn next_u64( &mut self ) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul( 0x2545_f491_4f6c_dd1d )
+    }
+
+    // uniform in (0, 1]
+    fn next_f64( &mut self ) -> f64 {
+        ( ( self.next_u64() >> 11 ) + 1 ) as f64 / ( 1u64 << 53 ) as f64
+    }
+
+    // standard normal by the Box-Muller transform
+    fn next_gaussian( &mut self ) -> f64 {
+        let ( u1, u2 ) = ( self.next_f64(), self.next_f64() );
+
+        ( -2.0 * u1.ln() ).sqrt() * ( 2.0 * std::f64::consts::PI * u2 ).cos()
+    }
+}
+
+// The correlation of every sample with the noiseless leakage of the same byte after the same operation of the unmasked cipher
+// under the known key. A first-order masked cipher leaves none but at the unmasked cipher text
+#[cfg(test)]
+fn known_key_correlation( traces : &TraceSet, reference : &ReferenceAes, model : LeakageModel ) -> Vec<f64> {
+    let mut reference_points = Vec::new();
+
+    let reference_samples : Vec<Vec<f64>> = traces.plain_texts.iter().map( | text | {
+        let mut samples = Vec::new();
+        let mut previous_state = block_cipher::block_to_state( text );
+        let mut block = *text;
+
+        reference_points.clear();
+        reference.encrypt_observed( &mut block, &mut | round, operation, _, state | {
+            for position in 0..BLOCK_LENGTH {
+                reference_points.push( ( round, operation, position ) );
+                samples.push( byte_leakage( model, state, &previous_state, position ) as f64 );
+            }
+
+            previous_state = *state;
+        } );
+
+        samples
+    } ).collect();
+
+    traces.points.iter().enumerate().map( | ( i, point ) | {
+        let j = reference_points.iter().position( | &reference_point | reference_point == ( point.round, point.operation, point.position ) )
+            .expect( "Failed to find the sample point in the reference" );
+
+        let x : Vec<f64> = traces.samples.iter().map( | trace | trace[i] as f64 ).collect();
+        let y : Vec<f64> = reference_samples.iter().map( | trace | trace[j] ).collect();
+
+        pearson_correlation( &x, &y )
+    } ).collect()
+}
+
+#[test]
+fn test_leakage_simulator() {
+    //FIPS 197 Appendix C.1 AES-128
+    let aes = ReferenceAes::new( &hex::decode( "000102030405060708090a0b0c0d0e0f" ).unwrap() ).unwrap();
+
+    for model in [LeakageModel::HammingWeight, LeakageModel::HammingDistance] {
+        let traces = LeakageSimulator::new( model, 0.0, 1 ).simulate( &aes, 3 );
+
+        // 1 + 4 * 9 + 3 operations of 16 bytes
+        assert_eq!( traces.points.len(), 40 * BLOCK_LENGTH );
+        assert_eq!( traces.points[16], SamplePoint { round : 1, operation : Operation::SubBytes, share : 0, position : 0 } );
+        assert_eq!( traces.samples.len(), 3 );
+
+        for i in 0..traces.plain_texts.len() {
+            let mut block = traces.plain_texts[i];
+            aes.encrypt_block( &mut block );
+            assert_eq!( traces.cipher_texts[i], block );
+
+            // the state after AddRoundKey of round 0 is the plain text xor the key,
+            // and its distance from the plain text is the weight of the key
+            for position in 0..BLOCK_LENGTH {
+                let expect = match model {
+                    LeakageModel::HammingWeight => ( traces.plain_texts[i][position] ^ position as u8 ).count_ones(),
+                    LeakageModel::HammingDistance => ( position as u8 ).count_ones()
+                };
+
+                assert_eq!( traces.samples[i][position], expect as f32 );
+            }
+        }
+    }
+
+    // the same seed gives the same traces
+    let traces = LeakageSimulator::new( LeakageModel::HammingWeight, 1.0, 7 ).simulate( &aes, 2 );
+    assert_eq!( LeakageSimulator::new( LeakageModel::HammingWeight, 1.0, 7 ).simulate( &aes, 2 ), traces );
+    assert_ne!( LeakageSimulator::new( LeakageModel::HammingWeight, 1.0, 8 ).simulate( &aes, 2 ), traces );
+}
+
+#[test]
+fn test_write_npy() {
+    let aes = ReferenceAes::new( &[0; 16] ).unwrap();
+    let traces = LeakageSimulator::new( LeakageModel::HammingWeight, 0.5, 1 ).simulate( &aes, 5 );
+
+    let mut npy = Vec::new();
+    traces.write_npy( &mut npy ).unwrap();
+
+    assert_eq!( &npy[..8], NPY_MAGIC );
+    let header_len = u16::from_le_bytes( [npy[8], npy[9]] ) as usize;
+    let header = std::str::from_utf8( &npy[10..( 10 + header_len )] ).unwrap();
+    assert_eq!( header.trim_end(), "{'descr': '<f4', 'fortran_order': False, 'shape': (5, 640), }" );
+    assert!( header.ends_with( '\n' ) );
+    assert_eq!( ( 10 + header_len ) % NPY_HEADER_ALIGNMENT, 0 );
+    assert_eq!( npy.len(), 10 + header_len + 5 * 640 * 4 );
+
+    let data = &npy[( 10 + header_len )..];
+    assert_eq!( f32::from_le_bytes( [data[4], data[5], data[6], data[7]] ), traces.samples[0][1] );
+
+    let mut npy = Vec::new();
+    traces.write_plain_texts_npy( &mut npy ).unwrap();
+    let header_len = u16::from_le_bytes( [npy[8], npy[9]] ) as usize;
+    assert_eq!( npy.len(), 10 + header_len + 5 * BLOCK_LENGTH );
+    assert_eq!( std::str::from_utf8( &npy[10..( 10 + header_len )] ).unwrap().trim_end(), "{'descr': '|u1', 'fortran_order': False, 'shape': (5, 16), }" );
+    assert_eq!( &npy[( npy.len() - 16 )..], &traces.plain_texts[4] );
+}
+
+#[test]
+fn test_cpa_first_round_key() {
+    //FIPS 197 Appendix A.1 key
+    let key = hex::decode( "2b7e151628aed2a6abf7158809cf4f3c" ).unwrap();
+    let aes = ReferenceAes::new( &key ).unwrap();
+
+    for model in [LeakageModel::HammingWeight, LeakageModel::HammingDistance] {
+        let traces = LeakageSimulator::new( model, 1.0, 2024 ).simulate( &aes, 200 );
+        let result = cpa_first_round_key( &traces, model );
+
+        assert_eq!( &result.round_key[..], &key[..] );
+        assert!( result.correlation.iter().all( | &correlation | correlation > 0.5 ) );
+    }
+}
+
+#[test]
+fn test_cpa_masked_aes() {
+    let key = hex::decode( "2b7e151628aed2a6abf7158809cf4f3c" ).unwrap();
+    let aes = MaskedAes::with_seed( &key, &[0x5a; masked::SEED_LENGTH] ).unwrap();
+    let reference = ReferenceAes::new( &key ).unwrap();
+
+    for model in [LeakageModel::HammingWeight, LeakageModel::HammingDistance] {
+        // even without noise, neither share correlates with the S-box output
+        let traces = LeakageSimulator::new( model, 0.0, 2024 ).simulate( &aes, 500 );
+        assert_eq!( traces.points.iter().filter( | point | point.share == 1 ).count(), traces.points.len() / 2 );
+
+        let result = cpa_first_round_key( &traces, model );
+
+        assert_ne!( &result.round_key[..], &key[..] );
+        assert!( result.correlation.iter().all( | &correlation | correlation < 0.3 ) );
+
+        // nor with any byte of the unmasked cipher, including the distances ShiftRows and MixColumns make between bytes.
+        // Only the cipher text after the last AddRoundKey is unmasked
+        let correlation = known_key_correlation( &traces, &reference, model );
+
+        for ( point, correlation ) in traces.points.iter().zip( correlation ) {
+            if point.round == aes.round_num() && point.operation == Operation::AddRoundKey && point.share == 0 {
+                continue;
+            }
+
+            assert!( correlation.abs() < 0.3, "{:?} correlates by {}", point, correlation );
+        }
+
+        let operations = [Operation::ShiftRows, Operation::MixColumns];
+        assert_eq!( traces.points.iter().filter( | point | point.round == 1 && operations.contains( &point.operation ) ).count(), 4 * BLOCK_LENGTH );
+
+        // the same traces of the unmasked cipher give the key away, and match their own leakage at every sample
+        let traces = LeakageSimulator::new( model, 0.0, 2024 ).simulate( &reference, 500 );
+        assert_eq!( &cpa_first_round_key( &traces, model ).round_key[..], &key[..] );
+
+        for ( point, correlation ) in traces.points.iter().zip( known_key_correlation( &traces, &reference, model ) ) {
+            // but for the distance of the first row, which ShiftRows leaves in place
+            let unmoved = model == LeakageModel::HammingDistance && point.operation == Operation::ShiftRows && point.position % 4 == 0;
+
+            if point.round == 1 && operations.contains( &point.operation ) && !unmoved {
+                assert!( correlation > 0.99 );
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -1,6 +1,52 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// The block ciphers, the key schedule, block_mode and block_cmac use core only.
+// The hex String functions and everything built on them need the std feature
+pub mod aes;
 pub mod aes_common;
+pub mod zeroize;
+#[cfg(feature = "std")]
 pub mod aes128;
+#[cfg(feature = "std")]
 pub mod aes192;
+#[cfg(feature = "std")]
 pub mod aes256;
+pub mod block_cipher;
+#[cfg(feature = "std")]
 pub mod block_cipher_mode;
-pub mod cmac;
\ No newline at end of file
+#[cfg(feature = "std")]
+pub mod block_cipher_io;
+pub mod block_mode;
+pub mod block_cmac;
+#[cfg(feature = "std")]
+pub mod trace;
+pub mod ttable;
+pub mod bitsliced;
+pub mod masked;
+pub mod reduced_round;
+pub mod key_inversion;
+#[cfg(feature = "std")]
+pub mod fault;
+#[cfg(feature = "std")]
+pub mod leakage;
+// runtime detection of the AES instructions needs std
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+pub mod aesni;
+#[cfg(feature = "std")]
+pub mod cmac;
+#[cfg(feature = "std")]
+pub mod kdf;
+#[cfg(feature = "std")]
+pub mod pmac;
+#[cfg(feature = "std")]
+pub mod ctr_drbg;
+#[cfg(feature = "std")]
+pub mod encoding;
+#[cfg(feature = "std")]
+pub mod key_wrap;
+#[cfg(feature = "std")]
+pub mod container;
+#[cfg(feature = "std")]
+pub mod eax;
+#[cfg(feature = "std")]
+pub mod stream;
\ No newline at end of file
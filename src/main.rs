@@ -1,14 +1,42 @@
 #[macro_use]
 extern crate clap;
 
-use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind};
-use aes_rustlang::{aes128, aes192, aes256, block_cipher_mode, cmac};
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
+use std::convert::TryInto;
+use std::env;
+use std::error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+use std::thread;
+use aes_rustlang::{block_cipher_mode, cmac, pmac, ctr_drbg, key_wrap, container, stream, trace};
+use aes_rustlang::aes::Aes;
+use aes_rustlang::block_cipher_io::{CipherMode, DecryptReader, EncryptWriter};
+use aes_rustlang::aes_common::{self, KeySize};
+use aes_rustlang::encoding::{Decoder, Encoder, Encoding};
+use aes_rustlang::zeroize::Zeroizing;
+
+// environment variable holding the key when neither KEYS nor --key-file is given
+const KEY_ENV_NAME : &str = "AES_RUSTLANG_KEY";
+
+// bytes read from the input at once
+const READ_CHUNK_SIZE : usize = 64 * 1024;
+
+// block size in hex characters
+const HEX_BLOCK_SIZE : usize = 32;
+
+const ENCODINGS : [&str; 5] = ["hex", "base64", "base64url", "raw", "utf8"];
 
 fn main() {
     let args = get_args();
 
     match args {
-        Ok( args ) => println!( "{}", execute_aes( args ) ),
+        Ok( args ) => {
+            if let Err( e ) = execute_aes( args ) {
+                eprintln!( "error: {}", e );
+                process::exit( 1 );
+            }
+        },
         Err( e ) => {
             if e.kind == ErrorKind::HelpDisplayed || e.kind == ErrorKind::VersionDisplayed {
                 println!( "{}", e.message )
@@ -21,37 +49,159 @@ fn main() {
 }
 
 fn get_args<'a>() -> clap::Result<ArgMatches<'a>> {
-    App::new( crate_name!() )
+    let app = App::new( crate_name!() )
         .version( crate_version!() )
         .about( crate_description!() )
         .setting( AppSettings::UnifiedHelpMessage )
+        .setting( AppSettings::VersionlessSubcommands )
+        .usage( "aes_rustlang <SUBCOMMAND> [OPTIONS] [ARGS]\n    aes_rustlang [OPTIONS] <TEXT> <KEYS>    (deprecated)" )
+        .subcommand(
+            SubCommand::with_name( "encrypt" )
+                .about( "Encrypt text in a block cipher mode" )
+                .args( &get_text_args( "Plain text" ) )
+                .args( &get_output_args() )
+                .arg( get_key_length_arg() )
+                .arg( get_cipher_mode_arg() )
+                .arg(
+                    Arg::with_name( "IV" )
+                        .long( "iv" )
+                        .help( "Initialization vector, or the initial counter block in ctr mode. A random one is prepended to the cipher text if omitted" )
+                        .takes_value( true )
+                )
+                .arg( get_threads_arg() )
+                .arg( get_trace_arg() )
+        )
+        .subcommand(
+            SubCommand::with_name( "decrypt" )
+                .about( "Decrypt text in a block cipher mode" )
+                .args( &get_text_args( "Cipher text" ) )
+                .args( &get_output_args() )
+                .arg( get_key_length_arg() )
+                .arg( get_cipher_mode_arg() )
+                .arg(
+                    Arg::with_name( "IV" )
+                        .long( "iv" )
+                        .help( "Initialization vector, or the initial counter block in ctr mode. Taken from the first block if omitted" )
+                        .takes_value( true )
+                )
+                .arg( get_threads_arg() )
+                .arg( get_trace_arg() )
+        )
+        .subcommand(
+            SubCommand::with_name( "mac" )
+                .about( "Generate a message authentication code" )
+                .args( &get_text_args( "Message" ) )
+                .args( &get_output_args() )
+                .arg( get_key_length_arg() )
+                .arg( get_mac_algorithm_arg() )
+                .arg( get_threads_arg() )
+        )
+        .subcommand(
+            SubCommand::with_name( "verify" )
+                .about( "Verify a message authentication code" )
+                .args( &get_text_args( "Message" ) )
+                .arg( get_key_length_arg() )
+                .arg( get_mac_algorithm_arg() )
+                .arg( get_threads_arg() )
+                .arg(
+                    Arg::with_name( "TAG" )
+                        .long( "tag" )
+                        .help( "Expected message authentication code in hex" )
+                        .required( true )
+                        .takes_value( true )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name( "wrap" )
+                .about( "Wrap a key with a key encryption key(RFC 3394)" )
+                .args( &get_text_args( "Key to wrap" ) )
+                .args( &get_output_args() )
+                .arg( get_key_length_arg() )
+        )
+        .subcommand(
+            SubCommand::with_name( "unwrap" )
+                .about( "Unwrap a key with a key encryption key(RFC 3394)" )
+                .args( &get_text_args( "Wrapped key" ) )
+                .args( &get_output_args() )
+                .arg( get_key_length_arg() )
+        )
+        .subcommand(
+            SubCommand::with_name( "seal" )
+                .about( "Encrypt and authenticate text into a container with its parameters" )
+                .args( &get_text_args( "Plain text" ) )
+                .args( &get_output_args() )
+                .arg( get_key_length_arg() )
+                .arg( get_aad_arg( "Additional authenticated data in hex, stored unencrypted in the container" ) )
+                .arg(
+                    Arg::with_name( "CHUNK_SIZE" )
+                        .long( "chunk-size" )
+                        .help( "Bytes of plain text in each authenticated chunk" )
+                        .default_value( "65536" )
+                        .takes_value( true )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name( "open" )
+                .about( "Verify and decrypt a container made by seal" )
+                .args( &get_text_args( "Container" ) )
+                .args( &get_output_args() )
+                .arg( get_key_length_arg() )
+                .arg( get_aad_arg( "Additional authenticated data in hex that the container must have" ) )
+        )
+        .subcommand(
+            SubCommand::with_name( "keygen" )
+                .about( "Generate a random key from the OS random source" )
+                .arg( get_default_key_length_arg() )
+                .arg(
+                    Arg::with_name( "OUTPUT" )
+                        .long( "out" )
+                        .help( "Write the key to a file, - for stdout" )
+                        .takes_value( true )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name( "random" )
+                .about( "Generate random bytes with CTR_DRBG(SP 800-90A)" )
+                .args( &get_output_args() )
+                .arg( get_default_key_length_arg() )
+                .arg(
+                    Arg::with_name( "BYTE_NUM" )
+                        .help( "Number of random bytes" )
+                        .required( true )
+                        .index( 1 )
+                )
+                .arg(
+                    Arg::with_name( "PERSONALIZATION" )
+                        .long( "personalization" )
+                        .help( "Personalization string in hex" )
+                        .takes_value( true )
+                )
+        )
+        // deprecated syntax without a subcommand
         .arg(
             Arg::with_name( "TEXT" )
-                .help( "Plain text for encryption or encrypted text for decryption" )
-                .required( true )
+                .help( "Deprecated: plain text for encryption or encrypted text for decryption, - for stdin" )
                 .index( 1 )
         )
         .arg(
             Arg::with_name( "KEYS" )
-                .help( "Keys for encryption or decryption" )
-                .required( true )
+                .help( "Deprecated: keys for encryption or decryption" )
                 .index( 2 )
         )
         .arg(
             Arg::with_name( "KEY_LENGTH" )
                 .short( "kl" )
                 .long( "key_length" )
-                .help( "Key length parameter")
+                .help( "Deprecated: key length parameter. Inferred from the key if omitted")
                 .possible_values( &["aes128", "aes192", "aes256"] )
-                .default_value( "aes128" )
                 .takes_value( true )
         )
         .arg(
             Arg::with_name( "OPERATE_MODE" )
                 .short( "opmode" )
                 .long( "operate_mode" )
-                .help( "Operation mode")
-                .possible_values( &["encrypt", "decrypt", "ecb-encrypt", "ecb-decrypt", "cbc-encrypt", "cbc-decrypt", "cmac"] )
+                .help( "Deprecated: operation mode")
+                .possible_values( &["encrypt", "decrypt", "ecb-encrypt", "ecb-decrypt", "cbc-encrypt", "cbc-decrypt", "cmac", "pmac", "drbg"] )
                 .default_value( "encrypt" )
                 .takes_value( true )
         )
@@ -59,45 +209,836 @@ fn get_args<'a>() -> clap::Result<ArgMatches<'a>> {
             Arg::with_name( "INITIALIZATION_VECTOR" )
                 .short( "iv" )
                 .long( "initilzation_vector" )
-                .help( "Initilzation vector")
+                .help( "Deprecated: initilzation vector")
                 .default_value( "" )
                 .takes_value( true )
         )
-        .get_matches_safe()
+        .args( &get_text_args( "Plain text" )[2..] )
+        .args( &get_output_args() );
+
+    // without any argument, the help is shown instead of running the deprecated syntax
+    if env::args_os().len() <= 1 {
+        return app.get_matches_from_safe( vec![crate_name!(), "--help"] );
+    }
+
+    app.get_matches_safe()
+}
+
+// TEXT, KEYS and the options to read them from elsewhere
+fn get_text_args<'a, 'b>( text_help : &'a str ) -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name( "TEXT" )
+            .help( text_help )
+            .index( 1 ),
+        Arg::with_name( "KEYS" )
+            .help( "Key. Also read from --key-file or AES_RUSTLANG_KEY" )
+            .index( 2 ),
+        Arg::with_name( "INPUT" )
+            .long( "in" )
+            .help( "Read the text from a file instead of TEXT, - for stdin" )
+            .takes_value( true ),
+        Arg::with_name( "KEY_FILE" )
+            .long( "key-file" )
+            .help( "Read the key from a file instead of KEYS or AES_RUSTLANG_KEY" )
+            .takes_value( true ),
+        Arg::with_name( "INPUT_FORMAT" )
+            .long( "input-format" )
+            .help( "Encoding of TEXT and the input file" )
+            .possible_values( &ENCODINGS )
+            .default_value( "hex" )
+            .takes_value( true )
+    ]
+}
+
+fn get_output_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name( "OUTPUT" )
+            .long( "out" )
+            .help( "Write the result to a file, - for stdout" )
+            .takes_value( true ),
+        Arg::with_name( "OUTPUT_FORMAT" )
+            .long( "output-format" )
+            .help( "Encoding of the result" )
+            .possible_values( &ENCODINGS )
+            .default_value( "hex" )
+            .takes_value( true )
+    ]
+}
+
+fn get_key_length_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name( "KEY_LENGTH" )
+        .short( "k" )
+        .long( "key-length" )
+        .alias( "key_length" )
+        .help( "Key length parameter. Inferred from the key if omitted" )
+        .possible_values( &["aes128", "aes192", "aes256"] )
+        .takes_value( true )
+}
+
+// for subcommands without a key to infer the length from
+fn get_default_key_length_arg<'a, 'b>() -> Arg<'a, 'b> {
+    get_key_length_arg()
+        .help( "Key length parameter" )
+        .default_value( "aes128" )
+}
+
+fn get_cipher_mode_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name( "MODE" )
+        .short( "m" )
+        .long( "mode" )
+        .help( "Block cipher mode. block processes a single block without padding, and stream authenticates large files segment by segment" )
+        .possible_values( &["cbc", "ecb", "ctr", "cfb", "ofb", "block", "stream"] )
+        .default_value( "cbc" )
+        .takes_value( true )
+}
+
+fn get_mac_algorithm_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name( "ALGORITHM" )
+        .short( "a" )
+        .long( "algorithm" )
+        .help( "Message authentication code algorithm" )
+        .possible_values( &["cmac", "pmac"] )
+        .default_value( "cmac" )
+        .takes_value( true )
+}
+
+fn get_threads_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name( "THREADS" )
+        .long( "threads" )
+        .help( "Worker threads for ecb, cbc decryption, ctr and pmac. The number of CPUs if omitted" )
+        .takes_value( true )
+}
+
+fn get_trace_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name( "TRACE" )
+        .long( "trace" )
+        .help( "Print the state after every step of every round of a single block, in the layout of FIPS 197 Appendix C" )
+}
+
+fn get_aad_arg<'a, 'b>( aad_help : &'a str ) -> Arg<'a, 'b> {
+    Arg::with_name( "AAD" )
+        .long( "aad" )
+        .help( aad_help )
+        .takes_value( true )
+}
+
+fn execute_aes( args : ArgMatches ) -> io::Result<()>
+{
+    match args.subcommand() {
+        ( "encrypt", Some( sub_args ) ) => execute_cipher( sub_args, true ),
+        ( "decrypt", Some( sub_args ) ) => execute_cipher( sub_args, false ),
+        ( "mac", Some( sub_args ) ) => execute_mac( sub_args ),
+        ( "verify", Some( sub_args ) ) => execute_verify( sub_args ),
+        ( "wrap", Some( sub_args ) ) => execute_key_wrap( sub_args, true ),
+        ( "unwrap", Some( sub_args ) ) => execute_key_wrap( sub_args, false ),
+        ( "seal", Some( sub_args ) ) => execute_seal( sub_args ),
+        ( "open", Some( sub_args ) ) => execute_open( sub_args ),
+        ( "keygen", Some( sub_args ) ) => execute_keygen( sub_args ),
+        ( "random", Some( sub_args ) ) => execute_random( sub_args ),
+        _ => execute_deprecated( &args )
+    }
+}
+
+fn execute_cipher( args : &ArgMatches, is_encrypt : bool ) -> io::Result<()>
+{
+    if args.is_present( "TRACE" ) {
+        return execute_trace( args, is_encrypt );
+    }
+
+    let mode = args.value_of( "MODE" ).unwrap_or_default();
+
+    if args.is_present( "IV" ) && get_chaining_mode( mode ).is_none() {
+        return Err( invalid_input( "--iv is only used in cbc, ctr, cfb and ofb mode" ) );
+    }
+
+    let iv = args.value_of( "IV" ).unwrap_or_default();
+    check_iv( iv )?;
+
+    let thread_num = get_thread_num( args.value_of( "THREADS" ) )?;
+    let ( mut input, key, key_size ) = open_text_and_key( args )?;
+    let mut output = open_text_output( args )?;
+
+    if is_encrypt {
+        encrypt_text( &mut input, &mut output, &key, key_size, mode, iv, thread_num )?;
+    }
+    else {
+        decrypt_text( &mut input, &mut output, &key, key_size, mode, iv, thread_num )?;
+    }
+
+    output.finish()
+}
+
+// --trace processes a single block, so the mode may only be block
+fn execute_trace( args : &ArgMatches, is_encrypt : bool ) -> io::Result<()>
+{
+    if ( args.occurrences_of( "MODE" ) > 0 && args.value_of( "MODE" ) != Some( "block" ) ) || args.is_present( "IV" ) {
+        return Err( invalid_input( "--trace traces a single block, so it takes neither --iv nor a mode other than block" ) );
+    }
+
+    let ( mut input, key, _ ) = open_text_and_key( args )?;
+    let text = input.read_all()?;
+    let block : [u8; HEX_BLOCK_SIZE / 2] = hex::decode( &text ).ok()
+                                                               .and_then( | block | block.try_into().ok() )
+                                                               .ok_or_else( || invalid_input( "--trace needs a single block of 16 bytes" ) )?;
+    let key_u8 = Zeroizing::new( hex::decode( &*key ).expect( "Failed to convert key" ) );
+    let mut output = open_output( args.value_of( "OUTPUT" ) )?;
+
+    if is_encrypt {
+        let cipher_trace = trace::trace_encrypt( &block, &key_u8 ).map_err( | e | io::Error::new( io::ErrorKind::InvalidInput, e ) )?;
+
+        writeln!( output, "PLAINTEXT: {}\nKEY: {}\nCIPHER (ENCRYPT):\n{}", text, *key, cipher_trace )?;
+    }
+    else {
+        let inv_cipher_trace = trace::trace_decrypt( &block, &key_u8 ).map_err( | e | io::Error::new( io::ErrorKind::InvalidInput, e ) )?;
+
+        writeln!( output, "CIPHERTEXT: {}\nKEY: {}\nINVERSE CIPHER (DECRYPT):\n{}", text, *key, inv_cipher_trace )?;
+    }
+
+    output.flush()
+}
+
+fn execute_mac( args : &ArgMatches ) -> io::Result<()>
+{
+    let algorithm = args.value_of( "ALGORITHM" ).unwrap_or_default();
+    let thread_num = get_thread_num( args.value_of( "THREADS" ) )?;
+    let ( mut input, key, key_size ) = open_text_and_key( args )?;
+    let mut output = open_text_output( args )?;
+
+    output.write_text( &generate_mac( &mut input, &key, key_size, algorithm, thread_num )? )?;
+    output.finish()
+}
+
+fn execute_verify( args : &ArgMatches ) -> io::Result<()>
+{
+    let algorithm = args.value_of( "ALGORITHM" ).unwrap_or_default();
+    let tag = hex::decode( args.value_of( "TAG" ).unwrap_or_default() ).map_err( | _ | invalid_input( "Tag is not hex" ) )?;
+    let thread_num = get_thread_num( args.value_of( "THREADS" ) )?;
+    let ( mut input, key, key_size ) = open_text_and_key( args )?;
+
+    let expect = hex::decode( generate_mac( &mut input, &key, key_size, algorithm, thread_num )? ).expect( "Failed to convert tag" );
+
+    // compare every byte so that the time does not depend on the position of a mismatch
+    let difference = expect.iter().zip( tag.iter() ).fold( 0, | acc, ( byte1, byte2 ) | acc | ( byte1 ^ byte2 ) );
+
+    if tag.len() != expect.len() || difference != 0 {
+        return Err( io::Error::new( io::ErrorKind::InvalidData, "MAC verification failed" ) );
+    }
+
+    println!( "OK" );
+    Ok( () )
+}
+
+fn execute_key_wrap( args : &ArgMatches, is_wrap : bool ) -> io::Result<()>
+{
+    let ( mut input, key, key_size ) = open_text_and_key( args )?;
+    let mut output = open_text_output( args )?;
+    let key_data = input.read_all()?;
+
+    let result = if is_wrap {
+        key_wrap::wrap_key( key_data, key.to_string(), key_size.cipher_func() ).map( Zeroizing::new )
+    }
+    else {
+        key_wrap::unwrap_key( key_data, key.to_string(), key_size.inv_cipher_func() )
+    };
+
+    output.write_text( &result.map_err( invalid_data )? )?;
+    output.finish()
+}
+
+fn execute_seal( args : &ArgMatches ) -> io::Result<()>
+{
+    let aad = get_aad( args.value_of( "AAD" ) )?.unwrap_or_default();
+    let chunk_size : usize = args.value_of( "CHUNK_SIZE" ).unwrap_or_default().parse().map_err( | _ | invalid_input( "Failed to parse the chunk size" ) )?;
+
+    if chunk_size == 0 || chunk_size > container::MAX_CHUNK_SIZE {
+        return Err( invalid_input( "Chunk size must be 1 to 16777216 bytes" ) );
+    }
+
+    let ( mut input, key, _ ) = open_text_and_key( args )?;
+    let mut output = open_text_output( args )?;
+    let mut sealer = container::Sealer::new( key.to_string(), &aad, chunk_size ).map_err( invalid_data )?;
+
+    while let Some( chunk ) = input.read_chunk()? {
+        output.write_text( &hex::encode( sealer.update( &hex::decode( chunk ).expect( "Failed to convert text" ) ) ) )?;
+    }
+
+    output.write_text( &hex::encode( sealer.finalize() ) )?;
+    output.finish()
+}
+
+// Plain text is written chunk by chunk as each tag is verified, so a truncated container fails only at the end
+fn execute_open( args : &ArgMatches ) -> io::Result<()>
+{
+    let expect_aad = get_aad( args.value_of( "AAD" ) )?;
+    let ( mut input, key, _ ) = open_text_and_key( args )?;
+    let mut output = open_text_output( args )?;
+    let mut opener = container::Opener::new( key.to_string() ).map_err( invalid_data )?;
+
+    while let Some( chunk ) = input.read_chunk()? {
+        let plain_data = opener.update( &hex::decode( chunk ).expect( "Failed to convert text" ) ).map_err( invalid_data )?;
+
+        if let ( Some( expect_aad ), Some( aad ) ) = ( &expect_aad, opener.aad() ) {
+            if expect_aad.as_slice() != aad {
+                return Err( io::Error::new( io::ErrorKind::InvalidData, "AAD of the container does not match --aad" ) );
+            }
+        }
+
+        output.write_text( &hex::encode( plain_data ) )?;
+    }
+
+    opener.finalize().map_err( invalid_data )?;
+    output.finish()
 }
 
-fn execute_aes( args : ArgMatches ) -> String
+fn execute_keygen( args : &ArgMatches ) -> io::Result<()>
 {
-    let text = args.value_of( "TEXT" ).unwrap_or_default();
-    let key = args.value_of( "KEYS" ).unwrap_or_default();
-    let key_length = args.value_of( "KEY_LENGTH" ).unwrap_or_default();
+    let key_size = get_key_size( args.value_of( "KEY_LENGTH" ).unwrap_or_default() );
+    let mut output = open_output( args.value_of( "OUTPUT" ) )?;
+
+    writeln!( output, "{}", aes_common::generate_random_text( key_size.key_length() ) )?;
+    output.flush()
+}
+
+fn execute_random( args : &ArgMatches ) -> io::Result<()>
+{
+    let key_size = get_key_size( args.value_of( "KEY_LENGTH" ).unwrap_or_default() );
+    let byte_num = args.value_of( "BYTE_NUM" ).unwrap_or_default();
+    let personalization = args.value_of( "PERSONALIZATION" ).unwrap_or_default();
+    let mut output = open_text_output( args )?;
+
+    generate_random_bytes( byte_num, personalization, key_size, &mut output )?;
+    output.finish()
+}
+
+// The flag syntax before the subcommands, kept as an alias
+fn execute_deprecated( args : &ArgMatches ) -> io::Result<()>
+{
+    eprintln!( "warning: running without a subcommand is deprecated. Use encrypt, decrypt, mac or random instead" );
+
     let operate_mode = args.value_of( "OPERATE_MODE" ).unwrap_or_default();
     let iv = args.value_of( "INITIALIZATION_VECTOR" ).unwrap_or_default();
 
-    let result = match &*format!( "{}-{}", key_length, operate_mode ) {
-        "aes128-encrypt" => aes128::encrypt( text.to_string(), key.to_string() ),
-        "aes192-encrypt" => aes192::encrypt( text.to_string(), key.to_string() ),
-        "aes256-encrypt" => aes256::encrypt( text.to_string(), key.to_string() ),
-        "aes128-ecb-encrypt" => block_cipher_mode::encrypt_ecb_mode(text.to_string(), key.to_string(), aes128::encrypt ),
-        "aes192-ecb-encrypt" => block_cipher_mode::encrypt_ecb_mode(text.to_string(), key.to_string(), aes192::encrypt ),
-        "aes256-ecb-encrypt" => block_cipher_mode::encrypt_ecb_mode(text.to_string(), key.to_string(), aes256::encrypt ),
-        "aes128-cbc-encrypt" => block_cipher_mode::encrypt_cbc_mode(text.to_string(), key.to_string(), iv.to_string(), aes128::encrypt ),
-        "aes192-cbc-encrypt" => block_cipher_mode::encrypt_cbc_mode(text.to_string(), key.to_string(), iv.to_string(), aes192::encrypt ),
-        "aes256-cbc-encrypt" => block_cipher_mode::encrypt_cbc_mode(text.to_string(), key.to_string(), iv.to_string(), aes256::encrypt ),
-        "aes128-decrypt" => aes128::decrypt( text.to_string(), key.to_string() ),
-        "aes192-decrypt" => aes192::decrypt( text.to_string(), key.to_string() ),
-        "aes256-decrypt" => aes256::decrypt( text.to_string(), key.to_string() ),
-        "aes128-ecb-decrypt" => block_cipher_mode::decrypt_ecb_mode(text.to_string(), key.to_string(), aes128::decrypt ),
-        "aes192-ecb-decrypt" => block_cipher_mode::decrypt_ecb_mode(text.to_string(), key.to_string(), aes192::decrypt ),
-        "aes256-ecb-decrypt" => block_cipher_mode::decrypt_ecb_mode(text.to_string(), key.to_string(), aes256::decrypt ),
-        "aes128-cbc-decrypt" => block_cipher_mode::decrypt_cbc_mode(text.to_string(), key.to_string(), iv.to_string(), aes128::decrypt ),
-        "aes192-cbc-decrypt" => block_cipher_mode::decrypt_cbc_mode(text.to_string(), key.to_string(), iv.to_string(), aes192::decrypt ),
-        "aes256-cbc-decrypt" => block_cipher_mode::decrypt_cbc_mode(text.to_string(), key.to_string(), iv.to_string(), aes256::decrypt ),
-        "aes128-cmac" => cmac::generate_aes_cmac(text.to_string(), key.to_string(), aes128::encrypt ),
-        "aes192-cmac" => cmac::generate_aes_cmac(text.to_string(), key.to_string(), aes192::encrypt ),
-        "aes256-cmac" => cmac::generate_aes_cmac(text.to_string(), key.to_string(), aes256::encrypt ),
+    if operate_mode == "drbg" {
+        let key_size = get_key_size( args.value_of( "KEY_LENGTH" ).unwrap_or( "aes128" ) );
+        let byte_num = args.value_of( "TEXT" ).ok_or_else( || invalid_input( "No number of random bytes given" ) )?;
+        let personalization = args.value_of( "KEYS" ).unwrap_or_default();
+        let mut output = open_text_output( args )?;
+
+        generate_random_bytes( byte_num, personalization, key_size, &mut output )?;
+        return output.finish();
+    }
+
+    check_iv( iv )?;
+
+    let thread_num = get_thread_num( None )?;
+    let ( mut input, key, key_size ) = open_text_and_key( args )?;
+    let mut output = open_text_output( args )?;
+
+    match operate_mode {
+        "encrypt" => encrypt_text( &mut input, &mut output, &key, key_size, "block", iv, thread_num )?,
+        "decrypt" => decrypt_text( &mut input, &mut output, &key, key_size, "block", iv, thread_num )?,
+        "ecb-encrypt" => encrypt_text( &mut input, &mut output, &key, key_size, "ecb", iv, thread_num )?,
+        "ecb-decrypt" => decrypt_text( &mut input, &mut output, &key, key_size, "ecb", iv, thread_num )?,
+        "cbc-encrypt" => encrypt_text( &mut input, &mut output, &key, key_size, "cbc", iv, thread_num )?,
+        "cbc-decrypt" => decrypt_text( &mut input, &mut output, &key, key_size, "cbc", iv, thread_num )?,
+        "cmac" | "pmac" => output.write_text( &generate_mac( &mut input, &key, key_size, operate_mode, thread_num )? )?,
         _ => unreachable!()
+    }
+
+    output.finish()
+}
+
+// With --in, the first positional argument is the key.
+// The key size is inferred from the key, and --key-length has to agree with it if given.
+fn open_text_and_key( args : &ArgMatches ) -> io::Result<( TextReader, Zeroizing<String>, KeySize )>
+{
+    let mut positionals : Vec<&str> = args.value_of( "TEXT" ).into_iter().chain( args.value_of( "KEYS" ) ).collect();
+    let input_format = get_encoding( args.value_of( "INPUT_FORMAT" ).unwrap_or_default() );
+
+    let reader : Box<dyn Read> = match args.value_of( "INPUT" ) {
+        Some( path ) => open_input( path )?,
+        None if positionals.is_empty() => return Err( invalid_input( "No text given. Use TEXT or --in" ) ),
+        None => match positionals.remove( 0 ) {
+            "-" => Box::new( io::stdin() ),
+            text => Box::new( io::Cursor::new( text.to_string().into_bytes() ) )
+        }
     };
 
-    result
+    let key = get_key( args.value_of( "KEY_FILE" ), positionals.pop() )?;
+
+    if !positionals.is_empty() {
+        return Err( invalid_input( "Too many arguments" ) );
+    }
+
+    let aes = match args.value_of( "KEY_LENGTH" ) {
+        Some( key_length ) => Aes::with_key_size( key.to_string(), get_key_size( key_length ) ),
+        None => Aes::new( key.to_string() )
+    };
+
+    let key_size = aes.map_err( | e | io::Error::new( io::ErrorKind::InvalidInput, e ) )?.key_size();
+
+    Ok( ( TextReader::new( reader, input_format ), key, key_size ) )
+}
+
+fn open_text_output( args : &ArgMatches ) -> io::Result<TextWriter>
+{
+    let output_format = get_encoding( args.value_of( "OUTPUT_FORMAT" ).unwrap_or_default() );
+
+    Ok( TextWriter::new( open_output( args.value_of( "OUTPUT" ) )?, output_format ) )
+}
+
+fn encrypt_text( input : &mut TextReader, output : &mut TextWriter, key : &str, key_size : KeySize, mode : &str, iv : &str,
+                 thread_num : usize ) -> io::Result<()>
+{
+    let cipher_func = key_size.cipher_func();
+
+    match mode {
+        "block" => output.write_text( &cipher_func( read_block( input )?, key ) ),
+        "ecb" => {
+            let rest = stream_blocks( input, output, false, | blocks | {
+                block_cipher_mode::encrypt_ecb_blocks_parallel( blocks, key, cipher_func, thread_num )
+            } )?;
+
+            output.write_text( &block_cipher_mode::encrypt_ecb_mode( rest, key.to_string(), cipher_func ) )
+        },
+        "cbc" => {
+            let mut iv = iv.to_string();
+
+            // without an IV, a random one is generated and prepended to the cipher text
+            if iv.is_empty() {
+                iv = aes_common::generate_random_text( HEX_BLOCK_SIZE / 2 );
+                output.write_text( &iv )?;
+            }
+
+            let rest = stream_blocks( input, output, false, | blocks | {
+                let cipher_text = block_cipher_mode::encrypt_cbc_blocks( blocks, key, iv.clone(), cipher_func );
+                iv = cipher_text[( cipher_text.len() - HEX_BLOCK_SIZE )..].to_string();
+                cipher_text
+            } )?;
+
+            output.write_text( &block_cipher_mode::encrypt_cbc_mode( rest, key.to_string(), iv, cipher_func ) )
+        },
+        "ctr" | "cfb" | "ofb" => {
+            let mut iv = iv.to_string();
+
+            if iv.is_empty() {
+                iv = aes_common::generate_random_text( HEX_BLOCK_SIZE / 2 );
+                output.write_text( &iv )?;
+            }
+
+            let mut writer = EncryptWriter::new( output, key.to_string(), get_chaining_mode( mode ).expect( "Failed to get mode" ), iv )?.with_threads( thread_num );
+
+            io::copy( input, &mut writer )?;
+            writer.finish().map( | _ | () )
+        },
+        "stream" => {
+            let mut writer = stream::AeadWriter::new( output, key.to_string(), stream::DEFAULT_SEGMENT_SIZE )?;
+
+            io::copy( input, &mut writer )?;
+            writer.finish().map( | _ | () )
+        },
+        _ => unreachable!()
+    }
+}
+
+fn decrypt_text( input : &mut TextReader, output : &mut TextWriter, key : &str, key_size : KeySize, mode : &str, iv : &str,
+                 thread_num : usize ) -> io::Result<()>
+{
+    let inv_cipher_func = key_size.inv_cipher_func();
+
+    match mode {
+        "block" => output.write_text( &inv_cipher_func( read_block( input )?, key ) ),
+        "ecb" => {
+            let rest = stream_blocks( input, output, true, | blocks | {
+                block_cipher_mode::decrypt_ecb_blocks_parallel( blocks, key, inv_cipher_func, thread_num )
+            } )?;
+
+            check_last_block( &rest )?;
+            output.write_text( &remove_padding( block_cipher_mode::decrypt_ecb_blocks( rest, key, inv_cipher_func ) )? )
+        },
+        "cbc" => {
+            let mut iv = iv.to_string();
+
+            // without an IV, the first block of the cipher text is taken as the IV
+            if iv.is_empty() {
+                iv = input.read_prefix( HEX_BLOCK_SIZE )?;
+            }
+
+            let rest = stream_blocks( input, output, true, | blocks | {
+                let next_iv = blocks[( blocks.len() - HEX_BLOCK_SIZE )..].to_string();
+                let plain_text = block_cipher_mode::decrypt_cbc_blocks_parallel( blocks, key, iv.clone(), inv_cipher_func, thread_num );
+                iv = next_iv;
+                plain_text
+            } )?;
+
+            check_last_block( &rest )?;
+            output.write_text( &remove_padding( block_cipher_mode::decrypt_cbc_blocks( rest, key, iv, inv_cipher_func ) )? )
+        },
+        "ctr" | "cfb" | "ofb" => {
+            let mut iv = iv.to_string();
+
+            if iv.is_empty() {
+                iv = input.read_prefix( HEX_BLOCK_SIZE )?;
+            }
+
+            let mut reader = DecryptReader::new( input, key.to_string(), get_chaining_mode( mode ).expect( "Failed to get mode" ), iv )?.with_threads( thread_num );
+
+            io::copy( &mut reader, output ).map( | _ | () )
+        },
+        "stream" => {
+            let mut reader = stream::AeadReader::new( input, key.to_string(), stream::DEFAULT_SEGMENT_SIZE )?;
+
+            io::copy( &mut reader, output ).map( | _ | () )
+        },
+        _ => unreachable!()
+    }
+}
+
+fn generate_mac( input : &mut TextReader, key : &str, key_size : KeySize, algorithm : &str, thread_num : usize ) -> io::Result<String>
+{
+    let cipher_func = key_size.cipher_func();
+
+    match algorithm {
+        "cmac" => {
+            let mut cmac = cmac::Cmac::new( key.to_string(), cipher_func );
+
+            while let Some( chunk ) = input.read_chunk()? {
+                cmac.update( chunk );
+            }
+
+            Ok( cmac.finalize() )
+        },
+        "pmac" => {
+            let mut pmac = pmac::Pmac::new( key.to_string(), cipher_func, thread_num );
+
+            while let Some( chunk ) = input.read_chunk()? {
+                pmac.update( chunk );
+            }
+
+            Ok( pmac.finalize() )
+        },
+        _ => unreachable!()
+    }
+}
+
+// modes that take an IV
+fn get_chaining_mode( mode : &str ) -> Option<CipherMode>
+{
+    match mode {
+        "cbc" => Some( CipherMode::Cbc ),
+        "ctr" => Some( CipherMode::Ctr ),
+        "cfb" => Some( CipherMode::Cfb ),
+        "ofb" => Some( CipherMode::Ofb ),
+        _ => None
+    }
+}
+
+// The number of CPUs without --threads
+fn get_thread_num( threads : Option<&str> ) -> io::Result<usize>
+{
+    match threads {
+        Some( threads ) => match threads.parse() {
+            Ok( thread_num ) if thread_num > 0 => Ok( thread_num ),
+            _ => Err( invalid_input( "Number of threads must be a positive integer" ) )
+        },
+        None => Ok( thread::available_parallelism().map( | n | n.get() ).unwrap_or( 1 ) )
+    }
+}
+
+fn get_key_size( key_length : &str ) -> KeySize
+{
+    match key_length {
+        "aes128" => KeySize::Aes128,
+        "aes192" => KeySize::Aes192,
+        "aes256" => KeySize::Aes256,
+        _ => unreachable!()
+    }
+}
+
+fn get_aad( aad : Option<&str> ) -> io::Result<Option<Vec<u8>>>
+{
+    let aad = match aad {
+        Some( aad ) => hex::decode( aad ).map_err( | _ | invalid_input( "AAD is not hex" ) )?,
+        None => return Ok( None )
+    };
+
+    if aad.len() > container::MAX_AAD_LENGTH {
+        return Err( invalid_input( "AAD must be at most 65536 bytes" ) );
+    }
+
+    Ok( Some( aad ) )
+}
+
+fn get_encoding( format : &str ) -> Encoding
+{
+    format.parse().expect( "Failed to parse encoding" )
+}
+
+// --key-file takes priority over KEYS, and KEYS over the environment variable
+fn get_key( key_file : Option<&str>, key : Option<&str> ) -> io::Result<Zeroizing<String>>
+{
+    if let Some( path ) = key_file {
+        let key_text = Zeroizing::new( fs::read_to_string( path )? );
+        let mut key = Zeroizing::new( String::with_capacity( key_text.len() ) );
+
+        // the capacity is reserved up front, so that no copy of the key is left behind by a reallocation
+        key.extend( key_text.split_whitespace() );
+        return Ok( key );
+    }
+
+    if let Some( key ) = key {
+        return Ok( Zeroizing::new( key.to_string() ) );
+    }
+
+    env::var( KEY_ENV_NAME ).map( Zeroizing::new ).map_err( | _ | invalid_input( "No key given. Use KEYS, --key-file or AES_RUSTLANG_KEY" ) )
+}
+
+fn open_input( path : &str ) -> io::Result<Box<dyn Read>>
+{
+    if path == "-" {
+        Ok( Box::new( io::stdin() ) )
+    }
+    else {
+        Ok( Box::new( File::open( path )? ) )
+    }
+}
+
+fn open_output( path : Option<&str> ) -> io::Result<Box<dyn Write>>
+{
+    match path {
+        None | Some( "-" ) => Ok( Box::new( BufWriter::new( io::stdout() ) ) ),
+        Some( path ) => Ok( Box::new( BufWriter::new( File::create( path )? ) ) )
+    }
+}
+
+// The block mode takes exactly one block, which the cipher functions assume
+fn read_block( input : &mut TextReader ) -> io::Result<String>
+{
+    let text = input.read_all()?;
+
+    if text.len() != HEX_BLOCK_SIZE || !text.chars().all( | c | c.is_ascii_hexdigit() ) {
+        return Err( invalid_input( "Block mode needs exactly one 16-byte block" ) );
+    }
+
+    Ok( text )
+}
+
+// An empty IV is generated, or taken from the cipher text
+fn check_iv( iv : &str ) -> io::Result<()>
+{
+    if !iv.is_empty() && hex::decode( iv ).map( | iv | iv.len() ) != Ok( HEX_BLOCK_SIZE / 2 ) {
+        return Err( invalid_input( "Initialization vector must be 16 bytes in hex" ) );
+    }
+
+    Ok( () )
+}
+
+fn invalid_input( message : &str ) -> io::Error
+{
+    io::Error::new( io::ErrorKind::InvalidInput, message )
+}
+
+// Hand whole blocks of the input to block_func as they arrive, and return the rest.
+// With hold_last_block, the last block is kept back for the padding removal.
+fn stream_blocks( input : &mut TextReader, output : &mut TextWriter, hold_last_block : bool,
+                  mut block_func : impl FnMut( String ) -> String ) -> io::Result<String>
+{
+    let mut rest = String::new();
+
+    while let Some( chunk ) = input.read_chunk()? {
+        rest += &chunk;
+
+        let mut process_len = rest.len() / HEX_BLOCK_SIZE * HEX_BLOCK_SIZE;
+
+        if hold_last_block && process_len == rest.len() {
+            process_len = process_len.saturating_sub( HEX_BLOCK_SIZE );
+        }
+
+        if process_len != 0 {
+            let blocks : String = rest.drain( ..process_len ).collect();
+            output.write_text( &block_func( blocks ) )?;
+        }
+    }
+
+    Ok( rest )
+}
+
+fn check_last_block( rest : &str ) -> io::Result<()>
+{
+    if rest.len() != HEX_BLOCK_SIZE {
+        return Err( io::Error::new( io::ErrorKind::InvalidData, "Cipher text is not a multiple of the block size" ) );
+    }
+
+    Ok( () )
+}
+
+// PKCS#7 padding of the last plain block in hex, which is checked since a wrong key or a corrupt cipher text gives random bytes
+fn remove_padding( mut text : String ) -> io::Result<String>
+{
+    let block = hex::decode( &text ).map_err( invalid_data )?;
+    let padding_num = *block.last().unwrap_or( &0 ) as usize;
+
+    if padding_num == 0 || padding_num > block.len() || block[( block.len() - padding_num )..].iter().any( | &byte | byte as usize != padding_num ) {
+        return Err( io::Error::new( io::ErrorKind::InvalidData, "Invalid padding" ) );
+    }
+
+    text.truncate( text.len() - padding_num * 2 );
+
+    Ok( text )
+}
+
+fn generate_random_bytes( byte_num : &str, personalization : &str, key_size : KeySize, output : &mut TextWriter ) -> io::Result<()>
+{
+    const MAX_REQUEST_BYTES : usize = 1 << 16;
+
+    let byte_num : usize = byte_num.parse().map_err( | _ | invalid_input( "Failed to parse the number of random bytes" ) )?;
+    let personalization = hex::decode( personalization ).map_err( | _ | invalid_input( "Failed to convert personalization string" ) )?;
+    let mut drbg = ctr_drbg::CtrDrbg::from_os_entropy( key_size, false, &personalization ).map_err( io::Error::other )?;
+    let mut rest_num = byte_num;
+
+    while rest_num != 0 {
+        let request_len = rest_num.min( MAX_REQUEST_BYTES );
+
+        output.write_text( &hex::encode( drbg.generate( request_len, &[], false ).map_err( io::Error::other )? ) )?;
+        rest_num -= request_len;
+    }
+
+    Ok( () )
+}
+
+fn invalid_data<E : error::Error + Send + Sync + 'static>( e : E ) -> io::Error
+{
+    io::Error::new( io::ErrorKind::InvalidData, e )
+}
+
+// Reader of the input in pieces, converted to hex text for the cipher functions
+struct TextReader {
+    reader : Box<dyn Read>,
+    decoder : Option<Decoder>,
+    buffer : String
+}
+
+impl TextReader {
+    fn new( reader : Box<dyn Read>, encoding : Encoding ) -> Self {
+        TextReader { reader, decoder : Some( Decoder::new( encoding ) ), buffer : String::new() }
+    }
+
+    // Hex text of whole bytes, or None at the end of the input
+    fn read_chunk( &mut self ) -> io::Result<Option<String>> {
+        if self.buffer.is_empty() {
+            self.fill_buffer()?;
+        }
+
+        if self.buffer.is_empty() {
+            return Ok( None );
+        }
+
+        Ok( Some( self.buffer.drain( .. ).collect() ) )
+    }
+
+    fn read_prefix( &mut self, len : usize ) -> io::Result<String> {
+        while self.buffer.len() < len && self.decoder.is_some() {
+            self.fill_buffer()?;
+        }
+
+        if self.buffer.len() < len {
+            return Err( io::Error::new( io::ErrorKind::UnexpectedEof, "Text is too short" ) );
+        }
+
+        Ok( self.buffer.drain( ..len ).collect() )
+    }
+
+    fn read_all( &mut self ) -> io::Result<String> {
+        let mut text = String::new();
+
+        while let Some( chunk ) = self.read_chunk()? {
+            text += &chunk;
+        }
+
+        Ok( text )
+    }
+
+    // Append more hex text to the buffer, or reach the end of the input. The reader may return
+    // fewer bytes than asked for, and the decoder may hold some back, so it reads until either happens
+    fn fill_buffer( &mut self ) -> io::Result<()> {
+        let mut read_buffer = vec![0; READ_CHUNK_SIZE];
+        let buffer_len = self.buffer.len();
+
+        while self.buffer.len() == buffer_len {
+            let decoder = match self.decoder.as_mut() {
+                Some( decoder ) => decoder,
+                None => break
+            };
+
+            let read_len = self.reader.read( &mut read_buffer )?;
+
+            if read_len == 0 {
+                let decoder = self.decoder.take().expect( "Failed to get decoder" );
+                self.buffer += &hex::encode( decoder.finalize().map_err( invalid_data )? );
+            }
+            else {
+                self.buffer += &hex::encode( decoder.update( &read_buffer[..read_len] ).map_err( invalid_data )? );
+            }
+        }
+
+        Ok( () )
+    }
+}
+
+// Bytes of the input, for the stream adapters
+impl Read for TextReader {
+    fn read( &mut self, buf : &mut [u8] ) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            self.fill_buffer()?;
+        }
+
+        let read_len = buf.len().min( self.buffer.len() / 2 );
+        let text : String = self.buffer.drain( ..( read_len * 2 ) ).collect();
+
+        buf[..read_len].copy_from_slice( &hex::decode( text ).expect( "Failed to convert text" ) );
+
+        Ok( read_len )
+    }
+}
+
+// Writer of hex text from the cipher functions in the output encoding
+struct TextWriter {
+    writer : Box<dyn Write>,
+    encoding : Encoding,
+    encoder : Encoder
+}
+
+impl TextWriter {
+    fn new( writer : Box<dyn Write>, encoding : Encoding ) -> Self {
+        TextWriter { writer, encoding, encoder : Encoder::new( encoding ) }
+    }
+
+    fn write_text( &mut self, hex_text : &str ) -> io::Result<()> {
+        let data = hex::decode( hex_text ).map_err( | _ | io::Error::new( io::ErrorKind::InvalidData, "Text is not hex" ) )?;
+
+        self.writer.write_all( &self.encoder.update( &data ).map_err( invalid_data )? )
+    }
+
+    fn finish( mut self ) -> io::Result<()> {
+        self.writer.write_all( &self.encoder.finalize().map_err( invalid_data )? )?;
+
+        // binary and UTF-8 output is written as is
+        if self.encoding.is_text() {
+            writeln!( self.writer )?;
+        }
+
+        self.writer.flush()
+    }
+}
+
+impl Write for TextWriter {
+    fn write( &mut self, buf : &[u8] ) -> io::Result<usize> {
+        self.writer.write_all( &self.encoder.update( buf ).map_err( invalid_data )? )?;
+
+        Ok( buf.len() )
+    }
+
+    fn flush( &mut self ) -> io::Result<()> {
+        self.writer.flush()
+    }
 }
\ No newline at end of file
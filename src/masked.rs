@@ -0,0 +1,449 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::aes::KeyError;
+use crate::aes_common::{self, Operation};
+use crate::bitsliced::BitslicedAes;
+use crate::block_cipher::{self, BlockCipher, RoundKeys, State, BLOCK_LENGTH, MAX_ROUND_KEY_NUM};
+use crate::zeroize::Zeroize;
+
+#[cfg(test)]
+use hex;
+#[cfg(all(test, feature = "std"))]
+use crate::aes128;
+#[cfg(all(test, feature = "std"))]
+use crate::block_cipher::ReferenceAes;
+
+// the key of the mask generator and its nonce
+pub const SEED_LENGTH : usize = BLOCK_LENGTH + NONCE_LENGTH;
+const NONCE_LENGTH : usize = 12;
+
+type Block = [u8; BLOCK_LENGTH];
+
+// Masks from AES in counter mode under the seed. It is bitsliced so that the masks do not leak through the cache,
+// and the counter is atomic so that every call through a shared reference gets fresh masks.
+// The masks would repeat after 2^32 blocks, so the generator panics instead, and the cipher has to be made again with a new seed
+struct MaskGenerator {
+    cipher : BitslicedAes,
+    nonce : [u8; NONCE_LENGTH],
+    counter : AtomicU32
+}
+
+impl MaskGenerator {
+    fn new( seed : &[u8; SEED_LENGTH] ) -> Self {
+        let mut nonce = [0; NONCE_LENGTH];
+        nonce.copy_from_slice( &seed[BLOCK_LENGTH..] );
+
+        MaskGenerator {
+            cipher : BitslicedAes::new( &seed[..BLOCK_LENGTH] ).expect( "Failed to expand the mask generator key" ),
+            nonce,
+            counter : AtomicU32::new( 0 )
+        }
+    }
+
+    fn next_block( &self ) -> Block {
+        let mut block = [0; BLOCK_LENGTH];
+
+        block[..NONCE_LENGTH].copy_from_slice( &self.nonce );
+        let counter = self.counter.fetch_update( Ordering::Relaxed, Ordering::Relaxed, | counter | counter.checked_add( 1 ) )
+            .expect( "The masks of the seed are used up" );
+
+        block[NONCE_LENGTH..].copy_from_slice( &counter.to_be_bytes() );
+        self.cipher.encrypt_block( &mut block );
+
+        block
+    }
+}
+
+// A clone takes a new nonce from the generator, so the two never share masks
+impl Clone for MaskGenerator {
+    fn clone( &self ) -> Self {
+        let mut nonce = [0; NONCE_LENGTH];
+        nonce.copy_from_slice( &self.next_block()[..NONCE_LENGTH] );
+
+        MaskGenerator { cipher : self.cipher.clone(), nonce, counter : AtomicU32::new( 0 ) }
+    }
+}
+
+impl Drop for MaskGenerator {
+    fn drop( &mut self ) {
+        self.nonce.zeroize();
+    }
+}
+
+// The masks of one call, an independent byte for every position of the state. The state is masked with input_masks
+// before SubBytes and output_masks after it. ShiftRows and MixColumns move and mix the masks with the bytes,
+// so no linear layer ever combines two bytes under the same mask, and a byte never replaces another under the same mask
+struct Masks {
+    input_masks : State,
+    output_masks : State,
+    // ShiftRows( output_masks ), the masks before MixColumns of the cipher and after InvMixColumns of the inverse cipher
+    shifted_masks : State,
+    // MixColumns( shifted_masks ), the masks after MixColumns and before InvMixColumns
+    mixed_masks : State
+}
+
+impl Masks {
+    fn new( random : &[Block; 2] ) -> Self {
+        let output_masks = block_cipher::block_to_state( &random[1] );
+        let shifted_masks = aes_common::shift_rows( output_masks );
+
+        Masks {
+            input_masks : block_cipher::block_to_state( &random[0] ),
+            output_masks,
+            shifted_masks,
+            mixed_masks : aes_common::mix_columns( shifted_masks )
+        }
+    }
+
+    fn zeroize( &mut self ) {
+        for masks in [&mut self.input_masks, &mut self.output_masks, &mut self.shifted_masks, &mut self.mixed_masks] {
+            for row in masks.iter_mut() {
+                row.zeroize();
+            }
+        }
+    }
+}
+
+// First-order boolean masked AES(Herbst, Oswald and Popp, ACNS 2006) for side-channel resistance.
+// The round keys are kept in two random shares, and every call takes fresh masks for every byte of the state
+// and recomputes an S-box table for each byte, so no intermediate value of a call, nor the distance between two of them,
+// depends on the key and the text alone.
+// The key schedule is expanded unmasked once, when the key is set
+#[derive(Clone)]
+pub struct MaskedAes {
+    // masked_round_keys xor key_masks is the key schedule
+    masked_round_keys : [u32; MAX_ROUND_KEY_NUM],
+    key_masks : [u32; MAX_ROUND_KEY_NUM],
+    round_num : usize,
+    mask_generator : MaskGenerator
+}
+
+impl MaskedAes {
+    // The seed from the operating system random source
+    #[cfg(feature = "std")]
+    pub fn new( key : &[u8] ) -> Result<Self, KeyError> {
+        let mut seed = [0; SEED_LENGTH];
+        getrandom::getrandom( &mut seed ).expect( "Failed to read the OS random source" );
+
+        let aes = MaskedAes::with_seed( key, &seed );
+        seed.zeroize();
+
+        aes
+    }
+
+    // The masks are generated from the seed, which must be secret and random. It is for targets without std
+    pub fn with_seed( key : &[u8], seed : &[u8; SEED_LENGTH] ) -> Result<Self, KeyError> {
+        let round_keys = RoundKeys::new( key )?;
+        let round_num = round_keys.round_num();
+        let mask_generator = MaskGenerator::new( seed );
+        let mut aes = MaskedAes { masked_round_keys : [0; MAX_ROUND_KEY_NUM], key_masks : [0; MAX_ROUND_KEY_NUM], round_num, mask_generator };
+
+        for round in 0..=round_num {
+            let mut random = aes.mask_generator.next_block();
+
+            for ( col, word ) in round_keys.round_key( round ).iter().enumerate() {
+                let i = round * aes_common::BLOCK_SIZE + col;
+
+                aes.key_masks[i] = u32::from_be_bytes( [random[col * 4], random[col * 4 + 1], random[col * 4 + 2], random[col * 4 + 3]] );
+                aes.masked_round_keys[i] = word ^ aes.key_masks[i];
+            }
+
+            random.zeroize();
+        }
+
+        Ok( aes )
+    }
+
+    pub fn round_num( &self ) -> usize {
+        self.round_num
+    }
+
+    // The round key masked with masks. The key share is removed last, so the round key never appears unmasked
+    fn masked_round_key( &self, round : usize, masks : &State ) -> [u32; 4] {
+        let mut round_key = [0; 4];
+
+        for col in 0..aes_common::BLOCK_SIZE {
+            let i = round * aes_common::BLOCK_SIZE + col;
+            let mask_word = u32::from_be_bytes( [masks[0][col], masks[1][col], masks[2][col], masks[3][col]] );
+
+            round_key[col] = ( self.masked_round_keys[i] ^ mask_word ) ^ self.key_masks[i];
+        }
+
+        round_key
+    }
+
+    fn next_masks( &self ) -> Masks {
+        let mut random = [self.mask_generator.next_block(), self.mask_generator.next_block()];
+        let masks = Masks::new( &random );
+
+        random.iter_mut().for_each( | block | block.zeroize() );
+
+        masks
+    }
+
+    // The cipher(FIPS 197 5.1) on the masked state. observe is called after every operation with share 0,
+    // the masked state, and share 1, its mask. Their xor is the state of the unmasked cipher
+    pub(crate) fn encrypt_shares( &self, block : &mut Block, observe : &mut dyn FnMut( usize, Operation, usize, &State ) ) {
+        let mut masks = self.next_masks();
+        let mut tables = masked_tables( &aes_common::S_BOX, &masks.input_masks, &masks.output_masks );
+        // the round keys take the state from the masks after MixColumns to the masks before SubBytes
+        let round_key_masks = xor_states( masks.mixed_masks, masks.input_masks );
+        let mut state = block_cipher::block_to_state( block );
+
+        for round in 0..=self.round_num {
+            for &operation in aes_common::operations( round, self.round_num ) {
+                let mask = match operation {
+                    Operation::SubBytes => {
+                        state = substitute( state, &tables );
+                        masks.output_masks
+                    },
+                    Operation::ShiftRows => {
+                        state = aes_common::shift_rows( state );
+                        masks.shifted_masks
+                    },
+                    Operation::MixColumns => {
+                        state = aes_common::mix_columns( state );
+                        masks.mixed_masks
+                    },
+                    Operation::AddRoundKey => {
+                        let ( key_masks, state_masks ) = if round == 0 {
+                            ( masks.input_masks, masks.input_masks )
+                        }
+                        else if round == self.round_num {
+                            ( masks.shifted_masks, [[0; 4]; 4] )
+                        }
+                        else {
+                            ( round_key_masks, masks.input_masks )
+                        };
+
+                        let mut round_key = self.masked_round_key( round, &key_masks );
+                        state = block_cipher::add_round_key( state, &round_key );
+                        round_key.zeroize();
+                        state_masks
+                    }
+                };
+
+                observe( round, operation, 0, &state );
+                observe( round, operation, 1, &mask );
+            }
+        }
+
+        block_cipher::state_to_block( state, block );
+
+        masks.zeroize();
+        tables.iter_mut().for_each( | table | table.zeroize() );
+    }
+}
+
+// Decryption is the inverse cipher(FIPS 197 5.3) with the masks of the cipher swapped around the S-box
+impl BlockCipher for MaskedAes {
+    fn encrypt_block( &self, block : &mut Block ) {
+        self.encrypt_shares( block, &mut | _, _, _, _ | {} );
+    }
+
+    fn decrypt_block( &self, block : &mut Block ) {
+        let mut masks = self.next_masks();
+        let mut tables = masked_tables( &aes_common::INV_S_BOX, &masks.output_masks, &masks.input_masks );
+        let round_key_masks = xor_states( masks.input_masks, masks.mixed_masks );
+        // InvShiftRows takes the masks of the last round key to output_masks
+        let mut round_key = self.masked_round_key( self.round_num, &masks.shifted_masks );
+        let mut state = block_cipher::add_round_key( block_cipher::block_to_state( block ), &round_key );
+
+        for round in ( 1..self.round_num ).rev() {
+            state = aes_common::inv_shift_rows( state );
+            state = substitute( state, &tables );
+
+            round_key = self.masked_round_key( round, &round_key_masks );
+            state = block_cipher::add_round_key( state, &round_key );
+
+            // the masks are back to shifted_masks for the next InvShiftRows
+            state = aes_common::inv_mix_columns( state );
+        }
+
+        state = aes_common::inv_shift_rows( state );
+        state = substitute( state, &tables );
+
+        round_key = self.masked_round_key( 0, &masks.input_masks );
+        state = block_cipher::add_round_key( state, &round_key );
+
+        block_cipher::state_to_block( state, block );
+
+        masks.zeroize();
+        tables.iter_mut().for_each( | table | table.zeroize() );
+        round_key.zeroize();
+    }
+}
+
+impl Drop for MaskedAes {
+    fn drop( &mut self ) {
+        self.masked_round_keys.zeroize();
+        self.key_masks.zeroize();
+    }
+}
+
+// table[x xor input_mask] = s_box[x] xor output_mask, so the masked S-box takes and returns masked bytes
+fn masked_table( s_box : &[[u8; 16]; 16], input_mask : u8, output_mask : u8 ) -> [u8; 256] {
+    let mut table = [0; 256];
+
+    for ( x, entry ) in table.iter_mut().enumerate() {
+        let unmasked = x as u8 ^ input_mask;
+        *entry = s_box[( unmasked >> 4 ) as usize][( unmasked & 0x0f ) as usize] ^ output_mask;
+    }
+
+    table
+}
+
+// A masked S-box for every byte of the state, in the order of the block(row + 4 * column)
+fn masked_tables( s_box : &[[u8; 16]; 16], input_masks : &State, output_masks : &State ) -> [[u8; 256]; BLOCK_LENGTH] {
+    let mut tables = [[0; 256]; BLOCK_LENGTH];
+
+    for ( position, table ) in tables.iter_mut().enumerate() {
+        let ( row, col ) = ( position % 4, position / 4 );
+        *table = masked_table( s_box, input_masks[row][col], output_masks[row][col] );
+    }
+
+    tables
+}
+
+fn substitute( mut state : State, tables : &[[u8; 256]; BLOCK_LENGTH] ) -> State {
+    for ( row, bytes ) in state.iter_mut().enumerate() {
+        for ( col, byte ) in bytes.iter_mut().enumerate() {
+            *byte = tables[row + 4 * col][*byte as usize];
+        }
+    }
+
+    state
+}
+
+fn xor_states( mut x : State, y : State ) -> State {
+    for ( x_row, y_row ) in x.iter_mut().zip( y.iter() ) {
+        for ( x_byte, y_byte ) in x_row.iter_mut().zip( y_row.iter() ) {
+            *x_byte ^= y_byte;
+        }
+    }
+
+    x
+}
+
+#[test]
+fn test_masked_aes() {
+    //FIPS 197 Appendix C
+    let text = "00112233445566778899aabbccddeeff";
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let expect = ["69c4e0d86a7b0430d8cdb78070b4c55a",
+                  "dda97ca4864cdfe06eaf70a0ec0d7191",
+                  "8ea2b7ca516745bfeafc49904b496089"];
+
+    for i in 0..key.len() {
+        let aes = MaskedAes::with_seed( &hex::decode( key[i] ).unwrap(), &[0x5a; SEED_LENGTH] ).unwrap();
+        let mut block = [0; BLOCK_LENGTH];
+        block.copy_from_slice( &hex::decode( text ).unwrap() );
+
+        aes.encrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), expect[i] );
+
+        aes.decrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), text );
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_masked_aes_matches_aes128() {
+    // xorshift64 for reproducible keys and texts
+    let mut seed : u64 = 0x0123_4567_89ab_cdef;
+    let mut next_block = || {
+        let mut block = [0; BLOCK_LENGTH];
+
+        for byte in block.iter_mut() {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            *byte = seed as u8;
+        }
+
+        block
+    };
+
+    for _ in 0..20 {
+        let key = next_block();
+        let aes = MaskedAes::new( &key ).unwrap();
+        let reference = ReferenceAes::new( &key ).unwrap();
+
+        for _ in 0..5 {
+            let text = next_block();
+            let mut block = text;
+
+            aes.encrypt_block( &mut block );
+            assert_eq!( hex::encode( block ), aes128::encrypt( hex::encode( text ), &hex::encode( key ) ) );
+
+            aes.decrypt_block( &mut block );
+            assert_eq!( block, text );
+
+            let mut blocks = [text; 9];
+            aes.encrypt_parallel( &mut blocks );
+            reference.decrypt_parallel( &mut blocks );
+            assert_eq!( blocks, [text; 9] );
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_masked_aes_shares() {
+    let aes = MaskedAes::with_seed( &hex::decode( "000102030405060708090a0b0c0d0e0f" ).unwrap(), &[0x5a; SEED_LENGTH] ).unwrap();
+    let clone = aes.clone();
+
+    //FIPS 197 Appendix C.1, round[1].start and round[1].s_box
+    let expect = ["00102030405060708090a0b0c0d0e0f0",
+                  "63cab7040953d051cd60e0e7ba70e18c"];
+
+    let mut masked_states = Vec::new();
+
+    for cipher in [&aes, &aes, &clone] {
+        let mut block = [0; BLOCK_LENGTH];
+        block.copy_from_slice( &hex::decode( "00112233445566778899aabbccddeeff" ).unwrap() );
+
+        let mut shares = Vec::new();
+        cipher.encrypt_shares( &mut block, &mut | round, operation, share, state | {
+            if round <= 1 && ( operation == Operation::AddRoundKey || operation == Operation::SubBytes ) {
+                shares.push( ( share, *state ) );
+            }
+        } );
+
+        assert_eq!( hex::encode( block ), "69c4e0d86a7b0430d8cdb78070b4c55a" );
+
+        // share 0 xor share 1 is the unmasked state, but share 0 alone is not
+        for i in 0..expect.len() {
+            let ( masked, mask ) = ( shares[i * 2], shares[i * 2 + 1] );
+            assert_eq!( ( masked.0, mask.0 ), ( 0, 1 ) );
+
+            let mut unmasked = [0; BLOCK_LENGTH];
+            block_cipher::state_to_block( xor_states( masked.1, mask.1 ), &mut unmasked );
+            assert_eq!( hex::encode( unmasked ), expect[i] );
+        }
+
+        masked_states.push( shares[0].1 );
+    }
+
+    // fresh masks for every call and for the clone
+    assert_ne!( masked_states[0], masked_states[1] );
+    assert_ne!( masked_states[0], masked_states[2] );
+    assert_ne!( masked_states[1], masked_states[2] );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_mask_generator_exhausted() {
+    let generator = MaskGenerator::new( &[0x5a; SEED_LENGTH] );
+    generator.counter.store( u32::MAX - 1, Ordering::Relaxed );
+
+    // the last counter is used once, and every call after it fails instead of repeating the masks of counter 0
+    generator.next_block();
+
+    for _ in 0..2 {
+        assert!( std::panic::catch_unwind( || generator.next_block() ).is_err() );
+    }
+}
\ No newline at end of file
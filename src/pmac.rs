@@ -0,0 +1,262 @@
+use std::thread;
+use hex;
+use crate::{block_cmac, cmac};
+use crate::zeroize::Zeroizing;
+use crate::block_cipher::CipherScope;
+
+#[cfg(test)]
+use crate::aes128;
+
+const BLOCK_SIZE : usize = 16;
+
+// x^-1 in GF(2^128): 0x80 for the MSB and 0x43 for the reduction of x^128 + x^7 + x^2 + x + 1
+const HALVING_MSB : u8 = 0x80;
+const HALVING_LSB : u8 = 0x43;
+
+pub fn generate_aes_pmac( plain_text : String, key : String, cipher_func : fn( String, &str ) -> String ) -> String {
+    generate_aes_pmac_parallel( plain_text, key, cipher_func, 1 )
+}
+
+pub fn generate_aes_pmac_parallel( plain_text : String, key : String, cipher_func : fn( String, &str ) -> String,
+                                   thread_num : usize ) -> String {
+    let mut pmac = Pmac::new( key, cipher_func, thread_num );
+
+    pmac.update( plain_text );
+    pmac.finalize()
+}
+
+// Incremental PMAC for text given in pieces
+pub struct Pmac {
+    key : Zeroizing<String>,
+    cipher_func : fn( String, &str ) -> String,
+    thread_num : usize,
+    l : Zeroizing<[u8; BLOCK_SIZE]>,
+    l_table : Zeroizing<Vec<[u8; BLOCK_SIZE]>>,
+    sum : [u8; BLOCK_SIZE],
+    // number of blocks already added to sum
+    block_num : usize,
+    // the last block is kept until finalize because it is not encrypted
+    buffer : Vec<u8>
+}
+
+impl Pmac {
+    pub fn new( key : String, cipher_func : fn( String, &str ) -> String, thread_num : usize ) -> Self {
+        let l = Zeroizing::new( cmac::string_to_block( cipher_func( "00".repeat( BLOCK_SIZE ), &key ) ) );
+
+        Pmac {
+            key : Zeroizing::new( key ),
+            cipher_func,
+            thread_num,
+            l_table : generate_l_table( *l, 1 ),
+            l,
+            sum : [0; BLOCK_SIZE],
+            block_num : 0,
+            buffer : Vec::new()
+        }
+    }
+
+    pub fn update( &mut self, text : String ) {
+        self.buffer.extend( hex::decode( text ).expect( "Failed to convert text in Pmac::update" ) );
+
+        if self.buffer.len() <= BLOCK_SIZE {
+            return;
+        }
+
+        // every block except the last one is processed independently
+        let full_block_num = ( self.buffer.len() - 1 ) / BLOCK_SIZE;
+        let l_table = generate_l_table( *self.l, self.block_num + full_block_num + 1 );
+
+        if l_table.len() > self.l_table.len() {
+            self.l_table = l_table;
+        }
+
+        let partial_sum = sum_blocks_parallel( &self.buffer[..( full_block_num * BLOCK_SIZE )], self.block_num + 1,
+                                               &self.l_table, &self.key, self.cipher_func, self.thread_num );
+
+        self.sum = cmac::xor_block( self.sum, partial_sum );
+        self.block_num += full_block_num;
+        self.buffer.drain( ..( full_block_num * BLOCK_SIZE ) );
+    }
+
+    pub fn finalize( self ) -> String {
+        let mut block : [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+        let sum : [u8; BLOCK_SIZE];
+
+        block[..self.buffer.len()].copy_from_slice( &self.buffer );
+
+        if self.buffer.len() == BLOCK_SIZE {
+            sum = cmac::xor_block( self.sum, cmac::xor_block( block, halve_block( *self.l ) ) );
+        }
+        else {
+            block[self.buffer.len()] = 0x80;
+            sum = cmac::xor_block( self.sum, block );
+        }
+
+        ( self.cipher_func )( hex::encode( sum ), &self.key )
+    }
+}
+
+fn sum_blocks_parallel( data : &[u8], first_index : usize, l_table : &[[u8; BLOCK_SIZE]], key : &str,
+                        cipher_func : fn( String, &str ) -> String, thread_num : usize ) -> [u8; BLOCK_SIZE] {
+    let block_num = data.len() / BLOCK_SIZE;
+    let worker_num = thread_num.clamp( 1, block_num.max( 1 ) );
+    let chunk_block_num = block_num.div_ceil( worker_num ).max( 1 );
+    let mut sum : [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+
+    if worker_num == 1 {
+        return sum_blocks( data, first_index, l_table, key, cipher_func );
+    }
+
+    let partial_sums : Vec<[u8; BLOCK_SIZE]> = thread::scope( | scope | {
+        let handles : Vec<_> = data
+            .chunks( chunk_block_num * BLOCK_SIZE )
+            .enumerate()
+            .map( | ( chunk_index, chunk ) | {
+                scope.spawn( move || sum_blocks( chunk, first_index + chunk_index * chunk_block_num, l_table, key, cipher_func ) )
+            } )
+            .collect();
+
+        handles.into_iter().map( | handle | handle.join().expect( "PMAC worker thread panicked" ) ).collect()
+    } );
+
+    for partial_sum in partial_sums {
+        sum = cmac::xor_block( sum, partial_sum );
+    }
+
+    sum
+}
+
+// Sum of E(M[i] xor Offset(i)) for the blocks starting at block index first_index
+fn sum_blocks( data : &[u8], first_index : usize, l_table : &[[u8; BLOCK_SIZE]], key : &str,
+               cipher_func : fn( String, &str ) -> String ) -> [u8; BLOCK_SIZE] {
+    let _cipher_scope = CipherScope::new( key );
+    let mut offset = get_offset( first_index - 1, l_table );
+    let mut sum : [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+
+    for ( i, chunk ) in data.chunks( BLOCK_SIZE ).enumerate() {
+        let mut block : [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+        block.copy_from_slice( chunk );
+
+        offset = cmac::xor_block( offset, l_table[( first_index + i ).trailing_zeros() as usize] );
+
+        let encrypted_block = cmac::string_to_block( cipher_func( hex::encode( cmac::xor_block( block, offset ) ), key ) );
+        sum = cmac::xor_block( sum, encrypted_block );
+    }
+
+    sum
+}
+
+// Offset(i) is the xor of L(j) for every bit j set in the Gray code of i
+fn get_offset( index : usize, l_table : &[[u8; BLOCK_SIZE]] ) -> [u8; BLOCK_SIZE] {
+    let gray_code = index ^ ( index >> 1 );
+    let mut offset : [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+
+    for ( bit_pos, l ) in l_table.iter().enumerate() {
+        if gray_code & ( 1 << bit_pos ) != 0 {
+            offset = cmac::xor_block( offset, *l );
+        }
+    }
+
+    offset
+}
+
+fn generate_l_table( l : [u8; BLOCK_SIZE], block_num : usize ) -> Zeroizing<Vec<[u8; BLOCK_SIZE]>> {
+    let table_size = ( usize::BITS - block_num.leading_zeros() ) as usize + 1;
+    let mut l_table : Zeroizing<Vec<[u8; BLOCK_SIZE]>> = Zeroizing::new( Vec::with_capacity( table_size ) );
+
+    l_table.push( l );
+
+    for i in 1..table_size {
+        let next = block_cmac::double_block( l_table[i - 1] );
+        l_table.push( next );
+    }
+
+    l_table
+}
+
+// division by x in GF(2^128)
+fn halve_block( input : [u8; BLOCK_SIZE] ) -> [u8; BLOCK_SIZE] {
+    let mut output : [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+
+    for i in 0..BLOCK_SIZE {
+        output[i] = input[i] >> 1;
+
+        if i != 0 {
+            output[i] |= input[i - 1] << 7;
+        }
+    }
+
+    if input[BLOCK_SIZE - 1] & 0x01 != 0x00 {
+        output[0] ^= HALVING_MSB;
+        output[BLOCK_SIZE - 1] ^= HALVING_LSB;
+    }
+
+    output
+}
+
+#[test]
+fn test_generate_aes_pmac() {
+
+    //PMAC1 test vectors, PMAC-AES-128
+    let key = "000102030405060708090a0b0c0d0e0f";
+
+    let text = ["",
+                "000102",
+                "000102030405060708090a0b0c0d0e0f",
+                "000102030405060708090a0b0c0d0e0f10111213",
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f2021"];
+
+    let expect = ["4399572cd6ea5341b8d35876a7098af7",
+                  "256ba5193c1b991b4df0c51f388a9e27",
+                  "ebbd822fa458daf6dfdad7c27da76338",
+                  "0412ca150bbf79058d8c75a58c993f55",
+                  "e97ac04e9e5e3399ce5355cd7407bc75",
+                  "5cba7d5eb24f7c86ccc54604e53d5512"];
+
+    for i in 0..text.len() {
+        let actual_result = generate_aes_pmac( text[i].to_string(), key.to_string(), aes128::encrypt );
+        assert_eq!( actual_result, expect[i] );
+    }
+}
+
+#[test]
+fn test_generate_aes_pmac_parallel() {
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let text : String = ( 0..1000 ).map( | i | format!( "{:02x}", ( i * 7 ) % 256 ) ).collect();
+    let expect = generate_aes_pmac( text.clone(), key.to_string(), aes128::encrypt );
+
+    for thread_num in [2, 3, 8, 100] {
+        let actual_result = generate_aes_pmac_parallel( text.clone(), key.to_string(), aes128::encrypt, thread_num );
+        assert_eq!( actual_result, expect );
+    }
+}
+
+#[test]
+fn test_halve_block() {
+    let input = ["fbeed618357133667c85e08f7236a8de",
+                 "f7ddac306ae266ccf90bc11ee46d513b"];
+
+    for input in input {
+        let block = cmac::string_to_block( input.to_string() );
+        assert_eq!( block_cmac::double_block( halve_block( block ) ), block );
+        assert_eq!( halve_block( block_cmac::double_block( block ) ), block );
+    }
+}
+
+#[test]
+fn test_pmac_update() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let text : String = ( 0..300 ).map( | i | format!( "{:02x}", i % 256 ) ).collect();
+    let expect = generate_aes_pmac( text.clone(), key.to_string(), aes128::encrypt );
+
+    for piece_len in [2, 32, 34, 70, 600] {
+        let mut pmac = Pmac::new( key.to_string(), aes128::encrypt, 4 );
+
+        for piece in text.as_bytes().chunks( piece_len ) {
+            pmac.update( String::from_utf8( piece.to_vec() ).unwrap() );
+        }
+
+        assert_eq!( pmac.finalize(), expect );
+    }
+}
\ No newline at end of file
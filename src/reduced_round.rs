@@ -0,0 +1,189 @@
+use core::error;
+use core::fmt;
+use crate::aes::KeyError;
+use crate::aes_common;
+use crate::block_cipher::{self, BlockCipher, RoundKeys, BLOCK_LENGTH};
+
+#[cfg(test)]
+use hex;
+#[cfg(test)]
+use crate::block_cipher::ReferenceAes;
+
+// Whether the last round omits MixColumns as in FIPS 197, or is a full round like the others
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LastRound {
+    WithoutMixColumns,
+    WithMixColumns
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReducedRoundError {
+    InvalidKey( KeyError ),
+    // the round count and the rounds of the key schedule of the key size
+    InvalidRoundNum { round_num : usize, max_round_num : usize }
+}
+
+impl fmt::Display for ReducedRoundError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            ReducedRoundError::InvalidKey( e ) => write!( f, "{}", e ),
+            ReducedRoundError::InvalidRoundNum { round_num, max_round_num } => {
+                write!( f, "round count must be 1 to {}, but it is {}", max_round_num, round_num )
+            }
+        }
+    }
+}
+
+impl error::Error for ReducedRoundError {}
+
+impl From<KeyError> for ReducedRoundError {
+    fn from( e : KeyError ) -> Self {
+        ReducedRoundError::InvalidKey( e )
+    }
+}
+
+// AES with a chosen number of rounds, for the analysis of reduced-round variants.
+// The round keys are the first ones of the usual key schedule, so the round count is at most Nr of the key size.
+// With the full round count and WithoutMixColumns, it is the same as AES
+#[derive(Clone)]
+pub struct ReducedRoundAes {
+    round_keys : RoundKeys,
+    round_num : usize,
+    last_round : LastRound
+}
+
+impl ReducedRoundAes {
+    pub fn new( key : &[u8], round_num : usize, last_round : LastRound ) -> Result<Self, ReducedRoundError> {
+        let round_keys = RoundKeys::new( key )?;
+        let max_round_num = round_keys.round_num();
+
+        if round_num == 0 || round_num > max_round_num {
+            return Err( ReducedRoundError::InvalidRoundNum { round_num, max_round_num } );
+        }
+
+        Ok( ReducedRoundAes { round_keys, round_num, last_round } )
+    }
+
+    pub fn round_num( &self ) -> usize {
+        self.round_num
+    }
+
+    pub fn last_round( &self ) -> LastRound {
+        self.last_round
+    }
+
+    fn has_mix_columns( &self, round : usize ) -> bool {
+        round < self.round_num || self.last_round == LastRound::WithMixColumns
+    }
+}
+
+// The cipher and the inverse cipher(FIPS 197 5.1 and 5.3) with the round functions of aes_common
+impl BlockCipher for ReducedRoundAes {
+    fn encrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        let mut state = block_cipher::block_to_state( block );
+
+        state = block_cipher::add_round_key( state, self.round_keys.round_key( 0 ) );
+
+        for round in 1..=self.round_num {
+            state = aes_common::sub_bytes( state );
+            state = aes_common::shift_rows( state );
+
+            if self.has_mix_columns( round ) {
+                state = aes_common::mix_columns( state );
+            }
+
+            state = block_cipher::add_round_key( state, self.round_keys.round_key( round ) );
+        }
+
+        block_cipher::state_to_block( state, block );
+    }
+
+    fn decrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        let mut state = block_cipher::block_to_state( block );
+
+        for round in ( 1..=self.round_num ).rev() {
+            state = block_cipher::add_round_key( state, self.round_keys.round_key( round ) );
+
+            if self.has_mix_columns( round ) {
+                state = aes_common::inv_mix_columns( state );
+            }
+
+            state = aes_common::inv_shift_rows( state );
+            state = aes_common::inv_sub_bytes( state );
+        }
+
+        state = block_cipher::add_round_key( state, self.round_keys.round_key( 0 ) );
+
+        block_cipher::state_to_block( state, block );
+    }
+}
+
+#[cfg(test)]
+fn to_block( text : &str ) -> [u8; BLOCK_LENGTH] {
+    let mut block = [0; BLOCK_LENGTH];
+
+    block.copy_from_slice( &hex::decode( text ).unwrap() );
+    block
+}
+
+#[test]
+fn test_reduced_round_aes() {
+    //FIPS 197 Appendix C.1 AES-128. The state after round r is round[r + 1].start, and the last round is round[10]
+    let text = to_block( "00112233445566778899aabbccddeeff" );
+    let key = hex::decode( "000102030405060708090a0b0c0d0e0f" ).unwrap();
+
+    let round_num = [1, 2, 9];
+
+    let expect = ["89d810e8855ace682d1843d8cb128fe4",
+                  "4915598f55e5d7a0daca94fa1f0a63f7",
+                  "bd6e7c3df2b5779e0b61216e8b10b689"];
+
+    for i in 0..round_num.len() {
+        let aes = ReducedRoundAes::new( &key, round_num[i], LastRound::WithMixColumns ).unwrap();
+        let mut block = text;
+
+        aes.encrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), expect[i] );
+
+        aes.decrypt_block( &mut block );
+        assert_eq!( block, text );
+    }
+
+    // round[2].s_row of Appendix C.1 xor round[2].k_sch
+    let aes = ReducedRoundAes::new( &key, 2, LastRound::WithoutMixColumns ).unwrap();
+    let mut block = text;
+
+    aes.encrypt_block( &mut block );
+    assert_eq!( hex::encode( block ), "112cd562f390ce6a66520f457751389f" );
+
+    aes.decrypt_block( &mut block );
+    assert_eq!( block, text );
+}
+
+#[test]
+fn test_reduced_round_aes_full_rounds() {
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let round_num = [10, 12, 14];
+
+    for i in 0..key.len() {
+        let key = hex::decode( key[i] ).unwrap();
+        let reference = ReferenceAes::new( &key ).unwrap();
+        let aes = ReducedRoundAes::new( &key, round_num[i], LastRound::WithoutMixColumns ).unwrap();
+        let mut expect = to_block( "00112233445566778899aabbccddeeff" );
+        let mut block = expect;
+
+        reference.encrypt_block( &mut expect );
+        aes.encrypt_block( &mut block );
+        assert_eq!( block, expect );
+
+        assert_eq!( ReducedRoundAes::new( &key, round_num[i] + 1, LastRound::WithoutMixColumns ).err(),
+                    Some( ReducedRoundError::InvalidRoundNum { round_num : round_num[i] + 1, max_round_num : round_num[i] } ) );
+    }
+
+    assert!( ReducedRoundAes::new( &[0; 16], 0, LastRound::WithMixColumns ).is_err() );
+    assert_eq!( ReducedRoundAes::new( &[0; 20], 1, LastRound::WithMixColumns ).err(),
+                Some( ReducedRoundError::InvalidKey( KeyError::InvalidLength( 20 ) ) ) );
+}
\ No newline at end of file
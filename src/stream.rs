@@ -0,0 +1,288 @@
+use std::io::{self, Read, Write};
+use hex;
+use crate::aes::Aes;
+use crate::aes_common;
+use crate::eax::{self, AeadError};
+use crate::zeroize::Zeroizing;
+
+// STREAM(Hoang, Reyhanitabar, Rogaway and Vizar) segmented AEAD with EAX.
+// The stream is a random nonce prefix followed by the segments, each the cipher text and a 16-byte tag.
+// The nonce of segment i is nonce prefix(11) || i(4) || last segment flag(1), so segments cannot be
+// reordered, and a stream cut at a segment boundary is rejected because its last segment is not flagged.
+
+pub const NONCE_PREFIX_LENGTH : usize = 11;
+pub const DEFAULT_SEGMENT_SIZE : usize = 64 * 1024;
+
+const TAG_LENGTH : usize = 16;
+
+// Encrypt everything written to it into the inner writer. finish() must be called to write the last segment
+pub struct AeadWriter<W : Write> {
+    inner : W,
+    key : Zeroizing<String>,
+    cipher_func : fn( String, &str ) -> String,
+    nonce_prefix : Vec<u8>,
+    segment_size : usize,
+    counter : u32,
+    // plain text of the segments not written yet
+    buffer : Vec<u8>
+}
+
+impl<W : Write> AeadWriter<W> {
+    pub fn new( inner : W, key : String, segment_size : usize ) -> io::Result<Self> {
+        let nonce_prefix = hex::decode( aes_common::generate_random_text( NONCE_PREFIX_LENGTH ) ).expect( "Failed to convert nonce prefix" );
+
+        AeadWriter::with_nonce_prefix( inner, key, segment_size, nonce_prefix )
+    }
+
+    fn with_nonce_prefix( mut inner : W, key : String, segment_size : usize, nonce_prefix : Vec<u8> ) -> io::Result<Self> {
+        assert!( segment_size != 0, "Segment size must not be 0" );
+
+        let key_size = Aes::new( key.clone() ).map_err( | e | io::Error::new( io::ErrorKind::InvalidInput, e ) )?.key_size();
+
+        inner.write_all( &nonce_prefix )?;
+
+        Ok( AeadWriter { inner, key : Zeroizing::new( key ), cipher_func : key_size.cipher_func(), nonce_prefix, segment_size, counter : 0, buffer : Vec::new() } )
+    }
+
+    // Write the last segment and return the inner writer
+    pub fn finish( mut self ) -> io::Result<W> {
+        let segment = std::mem::take( &mut self.buffer );
+
+        self.write_segment( &segment, true )?;
+        self.inner.flush()?;
+
+        Ok( self.inner )
+    }
+
+    fn write_segment( &mut self, segment : &[u8], is_last : bool ) -> io::Result<()> {
+        let nonce = build_nonce( &self.nonce_prefix, self.counter, is_last );
+        let cipher_text = eax::encrypt_eax_mode( hex::encode( segment ), self.key.to_string(), nonce, String::new(), self.cipher_func );
+
+        self.counter = self.counter.checked_add( 1 ).ok_or_else( || io::Error::other( "Too many segments in the stream" ) )?;
+        self.inner.write_all( &hex::decode( cipher_text ).expect( "Failed to convert cipher text" ) )
+    }
+}
+
+impl<W : Write> Write for AeadWriter<W> {
+    fn write( &mut self, buf : &[u8] ) -> io::Result<usize> {
+        self.buffer.extend_from_slice( buf );
+
+        // a full segment is kept back until more data arrives, since the last segment is flagged
+        while self.buffer.len() > self.segment_size {
+            let segment : Vec<u8> = self.buffer.drain( ..self.segment_size ).collect();
+            self.write_segment( &segment, false )?;
+        }
+
+        Ok( buf.len() )
+    }
+
+    fn flush( &mut self ) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Decrypt a stream from the inner reader. Only segments whose tag has been verified are read out
+pub struct AeadReader<R : Read> {
+    inner : R,
+    key : Zeroizing<String>,
+    cipher_func : fn( String, &str ) -> String,
+    nonce_prefix : Option<Vec<u8>>,
+    segment_size : usize,
+    counter : u32,
+    is_finished : bool,
+    // cipher text read ahead, and plain text not read out yet
+    buffer : Vec<u8>,
+    plain_text : Vec<u8>,
+    plain_text_pos : usize
+}
+
+impl<R : Read> AeadReader<R> {
+    pub fn new( inner : R, key : String, segment_size : usize ) -> io::Result<Self> {
+        assert!( segment_size != 0, "Segment size must not be 0" );
+
+        let key_size = Aes::new( key.clone() ).map_err( | e | io::Error::new( io::ErrorKind::InvalidInput, e ) )?.key_size();
+
+        Ok( AeadReader {
+            inner,
+            key : Zeroizing::new( key ),
+            cipher_func : key_size.cipher_func(),
+            nonce_prefix : None,
+            segment_size,
+            counter : 0,
+            is_finished : false,
+            buffer : Vec::new(),
+            plain_text : Vec::new(),
+            plain_text_pos : 0
+        } )
+    }
+
+    // Read until the buffer has len bytes, and return false at the end of the input
+    fn fill_buffer( &mut self, len : usize ) -> io::Result<bool> {
+        let mut read_buffer = vec![0; len.saturating_sub( self.buffer.len() )];
+
+        while self.buffer.len() < len {
+            let read_len = self.inner.read( &mut read_buffer[..( len - self.buffer.len() )] )?;
+
+            if read_len == 0 {
+                return Ok( false );
+            }
+
+            self.buffer.extend_from_slice( &read_buffer[..read_len] );
+        }
+
+        Ok( true )
+    }
+
+    fn read_segment( &mut self ) -> io::Result<()> {
+        let nonce_prefix = match self.nonce_prefix.take() {
+            Some( nonce_prefix ) => nonce_prefix,
+            None => {
+                if !self.fill_buffer( NONCE_PREFIX_LENGTH )? {
+                    return Err( invalid_data( AeadError::InvalidLength ) );
+                }
+
+                self.buffer.drain( ..NONCE_PREFIX_LENGTH ).collect()
+            }
+        };
+
+        // one byte more than a segment tells whether the segment is the last one
+        let segment_len = self.segment_size + TAG_LENGTH;
+        let is_last = !self.fill_buffer( segment_len + 1 )?;
+        let segment : Vec<u8> = self.buffer.drain( ..segment_len.min( self.buffer.len() ) ).collect();
+
+        let nonce = build_nonce( &nonce_prefix, self.counter, is_last );
+        let plain_text = eax::decrypt_eax_mode( hex::encode( segment ), self.key.to_string(), nonce, String::new(), self.cipher_func ).map_err( invalid_data )?;
+
+        self.plain_text = hex::decode( plain_text ).expect( "Failed to convert plain text" );
+        self.plain_text_pos = 0;
+        self.nonce_prefix = Some( nonce_prefix );
+        self.counter = self.counter.checked_add( 1 ).ok_or_else( || io::Error::other( "Too many segments in the stream" ) )?;
+        self.is_finished = is_last;
+
+        Ok( () )
+    }
+}
+
+impl<R : Read> Read for AeadReader<R> {
+    fn read( &mut self, buf : &mut [u8] ) -> io::Result<usize> {
+        while self.plain_text_pos == self.plain_text.len() {
+            if self.is_finished {
+                return Ok( 0 );
+            }
+
+            self.read_segment()?;
+        }
+
+        let read_len = buf.len().min( self.plain_text.len() - self.plain_text_pos );
+
+        buf[..read_len].copy_from_slice( &self.plain_text[self.plain_text_pos..( self.plain_text_pos + read_len )] );
+        self.plain_text_pos += read_len;
+
+        Ok( read_len )
+    }
+}
+
+fn build_nonce( nonce_prefix : &[u8], counter : u32, is_last : bool ) -> String {
+    hex::encode( nonce_prefix ) + &hex::encode( counter.to_be_bytes() ) + if is_last { "01" } else { "00" }
+}
+
+fn invalid_data( e : AeadError ) -> io::Error {
+    io::Error::new( io::ErrorKind::InvalidData, e )
+}
+
+#[cfg(test)]
+fn encrypt_stream( plain_text : &[u8], key : &str, segment_size : usize ) -> Vec<u8> {
+    let mut writer = AeadWriter::with_nonce_prefix( Vec::new(), key.to_string(), segment_size, vec![0xa5; NONCE_PREFIX_LENGTH] ).unwrap();
+
+    // written in uneven pieces
+    for piece in plain_text.chunks( 7 ) {
+        writer.write_all( piece ).unwrap();
+    }
+
+    writer.finish().unwrap()
+}
+
+#[cfg(test)]
+fn decrypt_stream( cipher_text : &[u8], key : &str, segment_size : usize ) -> io::Result<Vec<u8>> {
+    let mut reader = AeadReader::new( cipher_text, key.to_string(), segment_size )?;
+    let mut plain_text = Vec::new();
+
+    reader.read_to_end( &mut plain_text )?;
+
+    Ok( plain_text )
+}
+
+#[test]
+fn test_stream() {
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let plain_text : Vec<u8> = ( 0..100 ).collect();
+    let text_len = [0, 1, 31, 32, 33, 64, 100];
+
+    for key in key {
+        for text_len in text_len {
+            let cipher_text = encrypt_stream( &plain_text[..text_len], key, 32 );
+            let segment_num = text_len.div_ceil( 32 ).max( 1 );
+
+            assert_eq!( cipher_text.len(), NONCE_PREFIX_LENGTH + text_len + segment_num * TAG_LENGTH );
+            assert_eq!( decrypt_stream( &cipher_text, key, 32 ).unwrap(), &plain_text[..text_len] );
+        }
+    }
+
+    // a stream of one segment is the nonce prefix and EAX with the last segment flag
+    let nonce = hex::encode( [0xa5; NONCE_PREFIX_LENGTH] ) + "00000000" + "01";
+    let expect = eax::encrypt_eax_mode( hex::encode( &plain_text[..20] ), key[0].to_string(), nonce, String::new(), crate::aes128::encrypt );
+    assert_eq!( hex::encode( &encrypt_stream( &plain_text[..20], key[0], 32 )[NONCE_PREFIX_LENGTH..] ), expect );
+
+    // a new nonce prefix is taken every time
+    let mut writer = AeadWriter::new( Vec::new(), key[0].to_string(), 32 ).unwrap();
+    writer.write_all( &plain_text ).unwrap();
+    let cipher_text = writer.finish().unwrap();
+    assert_ne!( cipher_text, encrypt_stream( &plain_text, key[0], 32 ) );
+    assert_eq!( decrypt_stream( &cipher_text, key[0], 32 ).unwrap(), plain_text );
+}
+
+#[test]
+fn test_stream_error() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let plain_text : Vec<u8> = ( 0..80 ).collect();
+    let cipher_text = encrypt_stream( &plain_text, key, 32 );
+    let segment_len = 32 + TAG_LENGTH;
+
+    for i in 0..cipher_text.len() {
+        let mut tampered = cipher_text.clone();
+        tampered[i] ^= 0x01;
+
+        assert!( decrypt_stream( &tampered, key, 32 ).is_err() );
+    }
+
+    for len in 0..cipher_text.len() {
+        assert!( decrypt_stream( &cipher_text[..len], key, 32 ).is_err() );
+    }
+
+    // cut at a segment boundary
+    let truncated = &cipher_text[..( NONCE_PREFIX_LENGTH + segment_len * 2 )];
+    assert_eq!( decrypt_stream( truncated, key, 32 ).unwrap_err().kind(), io::ErrorKind::InvalidData );
+
+    // swapping the first two segments
+    let mut swapped = cipher_text[..NONCE_PREFIX_LENGTH].to_vec();
+    swapped.extend( &cipher_text[( NONCE_PREFIX_LENGTH + segment_len )..( NONCE_PREFIX_LENGTH + segment_len * 2 )] );
+    swapped.extend( &cipher_text[NONCE_PREFIX_LENGTH..( NONCE_PREFIX_LENGTH + segment_len )] );
+    swapped.extend( &cipher_text[( NONCE_PREFIX_LENGTH + segment_len * 2 )..] );
+    assert!( decrypt_stream( &swapped, key, 32 ).is_err() );
+
+    let mut extended = cipher_text.clone();
+    extended.push( 0 );
+    assert!( decrypt_stream( &extended, key, 32 ).is_err() );
+
+    assert!( decrypt_stream( &cipher_text, "000102030405060708090a0b0c0d0e0e", 32 ).is_err() );
+    assert!( decrypt_stream( &cipher_text, key, 16 ).is_err() );
+
+    // nothing is read out of a segment that fails the verification
+    let mut tampered = cipher_text.clone();
+    tampered[NONCE_PREFIX_LENGTH] ^= 0x01;
+    let mut reader = AeadReader::new( tampered.as_slice(), key.to_string(), 32 ).unwrap();
+    let mut buffer = [0; 16];
+    assert!( reader.read( &mut buffer ).is_err() );
+}
\ No newline at end of file
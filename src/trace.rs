@@ -0,0 +1,268 @@
+use std::fmt;
+use hex;
+use crate::aes::KeyError;
+use crate::aes_common;
+use crate::block_cipher::{self, RoundKeys, State, BLOCK_LENGTH};
+
+type Block = [u8; BLOCK_LENGTH];
+
+// The state after each step of a round of the cipher, named as in FIPS 197 Appendix C.
+// The last round has no MixColumns
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundTrace {
+    pub start : Block,
+    pub s_box : Block,
+    pub s_row : Block,
+    pub m_col : Option<Block>,
+    pub k_sch : Block
+}
+
+// The state after each step of a round of the inverse cipher(FIPS 197 5.3).
+// The last round has no InvMixColumns, so the state after AddRoundKey is the output
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvRoundTrace {
+    pub start : Block,
+    pub s_row : Block,
+    pub s_box : Block,
+    pub k_sch : Block,
+    pub k_add : Option<Block>
+}
+
+// The intermediate values of one block. Round 0 only adds k_sch to the input,
+// so rounds holds round 1 to Nr
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace<R> {
+    pub input : Block,
+    pub k_sch : Block,
+    pub rounds : Vec<R>,
+    pub output : Block
+}
+
+pub type CipherTrace = Trace<RoundTrace>;
+pub type InvCipherTrace = Trace<InvRoundTrace>;
+
+pub fn trace_encrypt( input : &Block, key : &[u8] ) -> Result<CipherTrace, KeyError> {
+    let round_keys = RoundKeys::new( key )?;
+    let round_num = round_keys.round_num();
+    let mut rounds = Vec::with_capacity( round_num );
+    let mut state = block_cipher::add_round_key( block_cipher::block_to_state( input ), round_keys.round_key( 0 ) );
+
+    for round in 1..=round_num {
+        let start = to_block( state );
+
+        state = aes_common::sub_bytes( state );
+        let s_box = to_block( state );
+
+        state = aes_common::shift_rows( state );
+        let s_row = to_block( state );
+
+        let mut m_col = None;
+
+        if round < round_num {
+            state = aes_common::mix_columns( state );
+            m_col = Some( to_block( state ) );
+        }
+
+        state = block_cipher::add_round_key( state, round_keys.round_key( round ) );
+        rounds.push( RoundTrace { start, s_box, s_row, m_col, k_sch : words_to_block( round_keys.round_key( round ) ) } );
+    }
+
+    Ok( Trace { input : *input, k_sch : words_to_block( round_keys.round_key( 0 ) ), rounds, output : to_block( state ) } )
+}
+
+// The inverse cipher of FIPS 197 5.3, not the equivalent inverse cipher the backends use,
+// so the values match the INVERSE CIPHER listing of Appendix C
+pub fn trace_decrypt( input : &Block, key : &[u8] ) -> Result<InvCipherTrace, KeyError> {
+    let round_keys = RoundKeys::new( key )?;
+    let round_num = round_keys.round_num();
+    let mut rounds = Vec::with_capacity( round_num );
+    let mut state = block_cipher::add_round_key( block_cipher::block_to_state( input ), round_keys.round_key( round_num ) );
+
+    for round in 1..=round_num {
+        let start = to_block( state );
+
+        state = aes_common::inv_shift_rows( state );
+        let s_row = to_block( state );
+
+        state = aes_common::inv_sub_bytes( state );
+        let s_box = to_block( state );
+
+        // round r of the inverse cipher uses the round key of round Nr - r
+        state = block_cipher::add_round_key( state, round_keys.round_key( round_num - round ) );
+
+        let mut k_add = None;
+
+        if round < round_num {
+            k_add = Some( to_block( state ) );
+            state = aes_common::inv_mix_columns( state );
+        }
+
+        rounds.push( InvRoundTrace { start, s_row, s_box, k_sch : words_to_block( round_keys.round_key( round_num - round ) ), k_add } );
+    }
+
+    Ok( Trace { input : *input, k_sch : words_to_block( round_keys.round_key( round_num ) ), rounds, output : to_block( state ) } )
+}
+
+// The layout of FIPS 197 Appendix C, one value per line
+impl fmt::Display for CipherTrace {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        write_line( f, 0, "input", &self.input )?;
+        write_line( f, 0, "k_sch", &self.k_sch )?;
+
+        for ( i, round ) in self.rounds.iter().enumerate() {
+            write_line( f, i + 1, "start", &round.start )?;
+            write_line( f, i + 1, "s_box", &round.s_box )?;
+            write_line( f, i + 1, "s_row", &round.s_row )?;
+
+            if let Some( m_col ) = &round.m_col {
+                write_line( f, i + 1, "m_col", m_col )?;
+            }
+
+            write_line( f, i + 1, "k_sch", &round.k_sch )?;
+        }
+
+        write!( f, "round[{:2}].{:<7} {}", self.rounds.len(), "output", hex::encode( self.output ) )
+    }
+}
+
+impl fmt::Display for InvCipherTrace {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        write_line( f, 0, "iinput", &self.input )?;
+        write_line( f, 0, "ik_sch", &self.k_sch )?;
+
+        for ( i, round ) in self.rounds.iter().enumerate() {
+            write_line( f, i + 1, "istart", &round.start )?;
+            write_line( f, i + 1, "is_row", &round.s_row )?;
+            write_line( f, i + 1, "is_box", &round.s_box )?;
+            write_line( f, i + 1, "ik_sch", &round.k_sch )?;
+
+            if let Some( k_add ) = &round.k_add {
+                write_line( f, i + 1, "ik_add", k_add )?;
+            }
+        }
+
+        write!( f, "round[{:2}].{:<7} {}", self.rounds.len(), "ioutput", hex::encode( self.output ) )
+    }
+}
+
+fn write_line( f : &mut fmt::Formatter, round : usize, name : &str, value : &Block ) -> fmt::Result {
+    writeln!( f, "round[{:2}].{:<7} {}", round, name, hex::encode( value ) )
+}
+
+fn to_block( state : State ) -> Block {
+    let mut block = [0; BLOCK_LENGTH];
+
+    block_cipher::state_to_block( state, &mut block );
+    block
+}
+
+fn words_to_block( words : &[u32] ) -> Block {
+    let mut block = [0; BLOCK_LENGTH];
+
+    for ( col, word ) in words.iter().enumerate() {
+        block[( col * 4 )..( col * 4 + 4 )].copy_from_slice( &word.to_be_bytes() );
+    }
+
+    block
+}
+
+#[cfg(test)]
+fn to_hex_block( text : &str ) -> Block {
+    let mut block = [0; BLOCK_LENGTH];
+
+    block.copy_from_slice( &hex::decode( text ).unwrap() );
+    block
+}
+
+#[test]
+fn test_trace_encrypt() {
+    //FIPS 197 Appendix C.1 AES-128
+    let text = to_hex_block( "00112233445566778899aabbccddeeff" );
+    let key = hex::decode( "000102030405060708090a0b0c0d0e0f" ).unwrap();
+    let trace = trace_encrypt( &text, &key ).unwrap();
+
+    assert_eq!( trace.rounds.len(), 10 );
+    assert_eq!( hex::encode( trace.k_sch ), "000102030405060708090a0b0c0d0e0f" );
+
+    let first_round = &trace.rounds[0];
+    assert_eq!( hex::encode( first_round.start ), "00102030405060708090a0b0c0d0e0f0" );
+    assert_eq!( hex::encode( first_round.s_box ), "63cab7040953d051cd60e0e7ba70e18c" );
+    assert_eq!( hex::encode( first_round.s_row ), "6353e08c0960e104cd70b751bacad0e7" );
+    assert_eq!( first_round.m_col, Some( to_hex_block( "5f72641557f5bc92f7be3b291db9f91a" ) ) );
+    assert_eq!( hex::encode( first_round.k_sch ), "d6aa74fdd2af72fadaa678f1d6ab76fe" );
+
+    let last_round = &trace.rounds[9];
+    assert_eq!( hex::encode( last_round.start ), "bd6e7c3df2b5779e0b61216e8b10b689" );
+    assert_eq!( hex::encode( last_round.s_box ), "7a9f102789d5f50b2beffd9f3dca4ea7" );
+    assert_eq!( hex::encode( last_round.s_row ), "7ad5fda789ef4e272bca100b3d9ff59f" );
+    assert_eq!( last_round.m_col, None );
+    assert_eq!( hex::encode( last_round.k_sch ), "13111d7fe3944a17f307a78b4d2b30c5" );
+    assert_eq!( hex::encode( trace.output ), "69c4e0d86a7b0430d8cdb78070b4c55a" );
+
+    let lines : Vec<String> = trace.to_string().lines().map( | line | line.to_string() ).collect();
+    assert_eq!( lines.len(), 2 + 9 * 5 + 4 + 1 );
+    assert_eq!( lines[0], "round[ 0].input   00112233445566778899aabbccddeeff" );
+    assert_eq!( lines[5], "round[ 1].m_col   5f72641557f5bc92f7be3b291db9f91a" );
+    assert_eq!( lines[lines.len() - 1], "round[10].output  69c4e0d86a7b0430d8cdb78070b4c55a" );
+}
+
+#[test]
+fn test_trace_decrypt() {
+    //FIPS 197 Appendix C.1 AES-128, INVERSE CIPHER
+    let text = to_hex_block( "69c4e0d86a7b0430d8cdb78070b4c55a" );
+    let key = hex::decode( "000102030405060708090a0b0c0d0e0f" ).unwrap();
+    let trace = trace_decrypt( &text, &key ).unwrap();
+
+    assert_eq!( hex::encode( trace.k_sch ), "13111d7fe3944a17f307a78b4d2b30c5" );
+
+    let first_round = &trace.rounds[0];
+    assert_eq!( hex::encode( first_round.start ), "7ad5fda789ef4e272bca100b3d9ff59f" );
+    assert_eq!( hex::encode( first_round.s_row ), "7a9f102789d5f50b2beffd9f3dca4ea7" );
+    assert_eq!( hex::encode( first_round.s_box ), "bd6e7c3df2b5779e0b61216e8b10b689" );
+    assert_eq!( hex::encode( first_round.k_sch ), "549932d1f08557681093ed9cbe2c974e" );
+    assert_eq!( first_round.k_add, Some( to_hex_block( "e9f74eec023020f61bf2ccf2353c21c7" ) ) );
+    assert_eq!( hex::encode( trace.rounds[1].start ), "54d990a16ba09ab596bbf40ea111702f" );
+
+    let last_round = &trace.rounds[9];
+    assert_eq!( hex::encode( last_round.start ), "6353e08c0960e104cd70b751bacad0e7" );
+    assert_eq!( hex::encode( last_round.s_row ), "63cab7040953d051cd60e0e7ba70e18c" );
+    assert_eq!( hex::encode( last_round.s_box ), "00102030405060708090a0b0c0d0e0f0" );
+    assert_eq!( last_round.k_add, None );
+    assert_eq!( hex::encode( trace.output ), "00112233445566778899aabbccddeeff" );
+
+    let lines : Vec<String> = trace.to_string().lines().map( | line | line.to_string() ).collect();
+    assert_eq!( lines[0], "round[ 0].iinput  69c4e0d86a7b0430d8cdb78070b4c55a" );
+    assert_eq!( lines[lines.len() - 1], "round[10].ioutput 00112233445566778899aabbccddeeff" );
+}
+
+#[test]
+fn test_trace_key_size() {
+    //FIPS 197 Appendix C.2 AES-192 and C.3 AES-256
+    let text = to_hex_block( "00112233445566778899aabbccddeeff" );
+
+    let key = ["000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let expect = ["dda97ca4864cdfe06eaf70a0ec0d7191",
+                  "8ea2b7ca516745bfeafc49904b496089"];
+
+    let round_num = [12, 14];
+
+    for i in 0..key.len() {
+        let key = hex::decode( key[i] ).unwrap();
+        let trace = trace_encrypt( &text, &key ).unwrap();
+        let inv_trace = trace_decrypt( &trace.output, &key ).unwrap();
+
+        assert_eq!( trace.rounds.len(), round_num[i] );
+        assert_eq!( hex::encode( trace.output ), expect[i] );
+        assert_eq!( inv_trace.output, text );
+
+        // the inverse cipher passes through the states of the cipher in reverse
+        for round in 1..=round_num[i] {
+            assert_eq!( inv_trace.rounds[round - 1].start, trace.rounds[round_num[i] - round].s_row );
+            assert_eq!( inv_trace.rounds[round - 1].s_box, trace.rounds[round_num[i] - round].start );
+        }
+    }
+
+    assert_eq!( trace_encrypt( &text, &[0; 20] ).err(), Some( KeyError::InvalidLength( 20 ) ) );
+}
\ No newline at end of file
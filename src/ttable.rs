@@ -0,0 +1,241 @@
+use crate::aes::KeyError;
+use crate::aes_common;
+use crate::block_cipher::{BlockCipher, RoundKeys, BLOCK_LENGTH};
+
+#[cfg(test)]
+use hex;
+#[cfg(all(test, feature = "std"))]
+use crate::block_cipher::ReferenceAes;
+
+// S-boxes as flat tables of 256 bytes
+const S_BOX : [u8; 256] = flatten_s_box( aes_common::S_BOX );
+const INV_S_BOX : [u8; 256] = flatten_s_box( aes_common::INV_S_BOX );
+
+// TE[i][x] is SubBytes and MixColumns of a byte x in row i of a column, as a column word.
+// The row index also selects the byte shifted by ShiftRows, so a round is four lookups per column
+static TE : [[u32; 256]; 4] = generate_te();
+
+// TD[i][x] is InvSubBytes and InvMixColumns in the same way, for the equivalent inverse cipher(FIPS 197 5.3.5)
+static TD : [[u32; 256]; 4] = generate_td();
+
+// AES with T-tables for both encryption and decryption
+#[derive(Clone)]
+pub struct TTableAes {
+    round_keys : RoundKeys
+}
+
+impl TTableAes {
+    pub fn new( key : &[u8] ) -> Result<Self, KeyError> {
+        Ok( TTableAes { round_keys : RoundKeys::new( key )? } )
+    }
+}
+
+impl BlockCipher for TTableAes {
+    fn encrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        let round_num = self.round_keys.round_num();
+        let mut state = [0u32; 4];
+
+        for col in 0..4 {
+            state[col] = u32::from_be_bytes( [block[col * 4], block[col * 4 + 1], block[col * 4 + 2], block[col * 4 + 3]] ) ^ self.round_keys.round_key( 0 )[col];
+        }
+
+        for round in 1..round_num {
+            let round_key = self.round_keys.round_key( round );
+            let mut next_state = [0u32; 4];
+
+            for col in 0..4 {
+                next_state[col] = TE[0][( state[col] >> 24 ) as usize] ^
+                                  TE[1][( ( state[( col + 1 ) % 4] >> 16 ) & 0xff ) as usize] ^
+                                  TE[2][( ( state[( col + 2 ) % 4] >> 8 ) & 0xff ) as usize] ^
+                                  TE[3][( state[( col + 3 ) % 4] & 0xff ) as usize] ^
+                                  round_key[col];
+            }
+
+            state = next_state;
+        }
+
+        // the last round has no MixColumns
+        let round_key = self.round_keys.round_key( round_num );
+
+        for col in 0..4 {
+            let word = u32::from_be_bytes( [S_BOX[( state[col] >> 24 ) as usize],
+                                            S_BOX[( ( state[( col + 1 ) % 4] >> 16 ) & 0xff ) as usize],
+                                            S_BOX[( ( state[( col + 2 ) % 4] >> 8 ) & 0xff ) as usize],
+                                            S_BOX[( state[( col + 3 ) % 4] & 0xff ) as usize]] ) ^ round_key[col];
+
+            block[( col * 4 )..( col * 4 + 4 )].copy_from_slice( &word.to_be_bytes() );
+        }
+    }
+
+    // InvShiftRows takes row r of a column from the column r to the left
+    fn decrypt_block( &self, block : &mut [u8; BLOCK_LENGTH] ) {
+        let round_num = self.round_keys.round_num();
+        let mut state = [0u32; 4];
+
+        for col in 0..4 {
+            state[col] = u32::from_be_bytes( [block[col * 4], block[col * 4 + 1], block[col * 4 + 2], block[col * 4 + 3]] ) ^ self.round_keys.inv_round_key( round_num )[col];
+        }
+
+        for round in ( 1..round_num ).rev() {
+            let round_key = self.round_keys.inv_round_key( round );
+            let mut next_state = [0u32; 4];
+
+            for col in 0..4 {
+                next_state[col] = TD[0][( state[col] >> 24 ) as usize] ^
+                                  TD[1][( ( state[( col + 3 ) % 4] >> 16 ) & 0xff ) as usize] ^
+                                  TD[2][( ( state[( col + 2 ) % 4] >> 8 ) & 0xff ) as usize] ^
+                                  TD[3][( state[( col + 1 ) % 4] & 0xff ) as usize] ^
+                                  round_key[col];
+            }
+
+            state = next_state;
+        }
+
+        // the last round has no InvMixColumns
+        let round_key = self.round_keys.inv_round_key( 0 );
+
+        for col in 0..4 {
+            let word = u32::from_be_bytes( [INV_S_BOX[( state[col] >> 24 ) as usize],
+                                            INV_S_BOX[( ( state[( col + 3 ) % 4] >> 16 ) & 0xff ) as usize],
+                                            INV_S_BOX[( ( state[( col + 2 ) % 4] >> 8 ) & 0xff ) as usize],
+                                            INV_S_BOX[( state[( col + 1 ) % 4] & 0xff ) as usize]] ) ^ round_key[col];
+
+            block[( col * 4 )..( col * 4 + 4 )].copy_from_slice( &word.to_be_bytes() );
+        }
+    }
+}
+
+const fn flatten_s_box( s_box : [[u8; 16]; 16] ) -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        table[i] = s_box[i >> 4][i & 0x0f];
+        i += 1;
+    }
+
+    table
+}
+
+// multiplication by x(02) in GF(2^8)
+const fn xtime( input : u8 ) -> u8 {
+    ( input << 1 ) ^ ( if input & 0x80 != 0 { 0x1b } else { 0x00 } )
+}
+
+// multiplication in GF(2^8) by xtime and add
+const fn multiply( mut multiplicand : u8, mut multiplier : u8 ) -> u8 {
+    let mut product = 0;
+
+    while multiplier != 0 {
+        if multiplier & 1 != 0 {
+            product ^= multiplicand;
+        }
+
+        multiplicand = xtime( multiplicand );
+        multiplier >>= 1;
+    }
+
+    product
+}
+
+const fn generate_te() -> [[u32; 256]; 4] {
+    let mut table = [[0; 256]; 4];
+    let mut i = 0;
+
+    while i < 256 {
+        let s = S_BOX[i];
+        let s2 = xtime( s );
+        let s3 = s2 ^ s;
+
+        // MixColumns column {02, 01, 01, 03} of the byte in row 0, rotated for the other rows
+        let word = u32::from_be_bytes( [s2, s, s, s3] );
+
+        table[0][i] = word;
+        table[1][i] = word.rotate_right( 8 );
+        table[2][i] = word.rotate_right( 16 );
+        table[3][i] = word.rotate_right( 24 );
+        i += 1;
+    }
+
+    table
+}
+
+const fn generate_td() -> [[u32; 256]; 4] {
+    let mut table = [[0; 256]; 4];
+    let mut i = 0;
+
+    while i < 256 {
+        let s = INV_S_BOX[i];
+
+        // InvMixColumns column {0e, 09, 0d, 0b} of the byte in row 0, rotated for the other rows
+        let word = u32::from_be_bytes( [multiply( s, 0x0e ), multiply( s, 0x09 ), multiply( s, 0x0d ), multiply( s, 0x0b )] );
+
+        table[0][i] = word;
+        table[1][i] = word.rotate_right( 8 );
+        table[2][i] = word.rotate_right( 16 );
+        table[3][i] = word.rotate_right( 24 );
+        i += 1;
+    }
+
+    table
+}
+
+#[test]
+fn test_ttable_aes() {
+
+    //FIPS 197 Appendix C Example Vectors
+    let text = "00112233445566778899aabbccddeeff";
+
+    let key = ["000102030405060708090a0b0c0d0e0f",
+               "000102030405060708090a0b0c0d0e0f1011121314151617",
+               "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"];
+
+    let expect = ["69c4e0d86a7b0430d8cdb78070b4c55a",
+                  "dda97ca4864cdfe06eaf70a0ec0d7191",
+                  "8ea2b7ca516745bfeafc49904b496089"];
+
+    for i in 0..key.len() {
+        let aes = TTableAes::new( &hex::decode( key[i] ).unwrap() ).unwrap();
+        let mut block = [0; BLOCK_LENGTH];
+
+        block.copy_from_slice( &hex::decode( text ).unwrap() );
+        aes.encrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), expect[i] );
+
+        aes.decrypt_block( &mut block );
+        assert_eq!( hex::encode( block ), text );
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_ttable_aes_against_reference() {
+    // xorshift64 for reproducible keys and blocks
+    let mut seed : u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next_byte = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as u8
+    };
+
+    for key_length in [16, 24, 32] {
+        for _ in 0..100 {
+            let key : Vec<u8> = ( 0..key_length ).map( | _ | next_byte() ).collect();
+            let mut block = [0; BLOCK_LENGTH];
+            block.iter_mut().for_each( | byte | *byte = next_byte() );
+
+            let reference = ReferenceAes::new( &key ).unwrap();
+            let ttable = TTableAes::new( &key ).unwrap();
+            let mut expect = block;
+
+            reference.encrypt_block( &mut expect );
+            ttable.encrypt_block( &mut block );
+            assert_eq!( block, expect );
+
+            reference.decrypt_block( &mut expect );
+            ttable.decrypt_block( &mut block );
+            assert_eq!( block, expect );
+        }
+    }
+}
\ No newline at end of file
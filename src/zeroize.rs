@@ -0,0 +1,172 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+// Overwrite memory with zeros by volatile writes, which the compiler does not remove even if the memory is freed right after
+pub trait Zeroize {
+    fn zeroize( &mut self );
+}
+
+impl Zeroize for [u8] {
+    fn zeroize( &mut self ) {
+        for byte in self.iter_mut() {
+            unsafe { ptr::write_volatile( byte, 0 ) };
+        }
+
+        atomic::compiler_fence( Ordering::SeqCst );
+    }
+}
+
+impl Zeroize for [u32] {
+    fn zeroize( &mut self ) {
+        for word in self.iter_mut() {
+            unsafe { ptr::write_volatile( word, 0 ) };
+        }
+
+        atomic::compiler_fence( Ordering::SeqCst );
+    }
+}
+
+impl Zeroize for [u128] {
+    fn zeroize( &mut self ) {
+        for lane in self.iter_mut() {
+            unsafe { ptr::write_volatile( lane, 0 ) };
+        }
+
+        atomic::compiler_fence( Ordering::SeqCst );
+    }
+}
+
+impl<const N : usize> Zeroize for [u8; N] {
+    fn zeroize( &mut self ) {
+        self.as_mut_slice().zeroize();
+    }
+}
+
+impl<const N : usize> Zeroize for [u32; N] {
+    fn zeroize( &mut self ) {
+        self.as_mut_slice().zeroize();
+    }
+}
+
+impl<const N : usize> Zeroize for [u128; N] {
+    fn zeroize( &mut self ) {
+        self.as_mut_slice().zeroize();
+    }
+}
+
+// The spare capacity is also wiped, since it may hold bytes of a longer content before
+#[cfg(feature = "std")]
+impl Zeroize for Vec<u8> {
+    fn zeroize( &mut self ) {
+        self.as_mut_slice().zeroize();
+
+        for byte in self.spare_capacity_mut() {
+            unsafe { ptr::write_volatile( byte, MaybeUninit::new( 0 ) ) };
+        }
+
+        atomic::compiler_fence( Ordering::SeqCst );
+        self.clear();
+    }
+}
+
+// Tables of blocks, such as the PMAC offsets
+#[cfg(feature = "std")]
+impl<const N : usize> Zeroize for Vec<[u8; N]> {
+    fn zeroize( &mut self ) {
+        for block in self.iter_mut() {
+            block.zeroize();
+        }
+
+        for block in self.spare_capacity_mut() {
+            unsafe { ptr::write_volatile( block, MaybeUninit::new( [0; N] ) ) };
+        }
+
+        atomic::compiler_fence( Ordering::SeqCst );
+        self.clear();
+    }
+}
+
+#[cfg(feature = "std")]
+impl Zeroize for String {
+    fn zeroize( &mut self ) {
+        // safe, since an empty vector is valid UTF-8
+        unsafe { self.as_mut_vec().zeroize() };
+    }
+}
+
+// A value that is wiped when dropped, for keys and round keys
+#[derive(Clone, Default)]
+pub struct Zeroizing<T : Zeroize>( T );
+
+impl<T : Zeroize> Zeroizing<T> {
+    pub fn new( value : T ) -> Self {
+        Zeroizing( value )
+    }
+}
+
+impl<T : Zeroize> Deref for Zeroizing<T> {
+    type Target = T;
+
+    fn deref( &self ) -> &T {
+        &self.0
+    }
+}
+
+impl<T : Zeroize> DerefMut for Zeroizing<T> {
+    fn deref_mut( &mut self ) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T : Zeroize> Drop for Zeroizing<T> {
+    fn drop( &mut self ) {
+        self.0.zeroize();
+    }
+}
+
+// The content is never printed
+impl<T : Zeroize> fmt::Debug for Zeroizing<T> {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        write!( f, "Zeroizing(..)" )
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<String> for Zeroizing<String> {
+    fn from( value : String ) -> Self {
+        Zeroizing( value )
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_zeroize() {
+    let mut key = String::from( "000102030405060708090a0b0c0d0e0f" );
+    key.zeroize();
+    assert!( key.is_empty() );
+
+    // the bytes beyond the length are wiped as well
+    let mut key_u8 = vec![0xff_u8; 32];
+    key_u8.truncate( 16 );
+    key_u8.zeroize();
+    assert!( key_u8.is_empty() );
+    assert!( key_u8.spare_capacity_mut().iter().all( | byte | unsafe { byte.assume_init() } == 0 ) );
+
+    let mut round_key = [0x2b7e_1516_u32; 44];
+    round_key.zeroize();
+    assert_eq!( round_key, [0; 44] );
+
+    let mut l_table = vec![[0xff_u8; 16]; 4];
+    l_table.truncate( 2 );
+    l_table.zeroize();
+    assert!( l_table.is_empty() );
+    assert!( l_table.spare_capacity_mut().iter().all( | block | unsafe { block.assume_init() } == [0; 16] ) );
+
+    let round_key = Zeroizing::new( [0x2b_u8; 16] );
+    assert_eq!( *round_key, [0x2b; 16] );
+    assert_eq!( format!( "{:?}", round_key ), "Zeroizing(..)" );
+}
\ No newline at end of file
@@ -1,4 +1,9 @@
-use std::process::Command;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::Duration;
 use assert_cmd::prelude::*;
 
 #[test]
@@ -814,4 +819,864 @@ fn test_cli_aes256_cmac_ex4() {
         .assert()
         .success()
         .stdout( expect );
+}
+
+#[test]
+fn test_cli_aes128_pmac_empty() {
+    
+    //PMAC1 test vectors, PMAC-AES-128
+    let text = "";
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let expect = "4399572cd6ea5341b8d35876a7098af7\n";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( text )
+        .arg( key )
+        .arg( "-k" )
+        .arg( "aes128" )
+        .arg( "-o" )
+        .arg( "pmac" )
+        .assert()
+        .success()
+        .stdout( expect );
+}
+
+#[test]
+fn test_cli_aes128_pmac_34bytes() {
+    
+    //PMAC1 test vectors, PMAC-AES-128
+    let text = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f2021";
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let expect = "5cba7d5eb24f7c86ccc54604e53d5512\n";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( text )
+        .arg( key )
+        .arg( "-k" )
+        .arg( "aes128" )
+        .arg( "-o" )
+        .arg( "pmac" )
+        .assert()
+        .success()
+        .stdout( expect );
+}
+
+#[test]
+fn test_cli_aes256_drbg() {
+    
+    // more bytes than a single CTR_DRBG request
+    let byte_num = 70000;
+    let personalization = "000102030405060708090a0b0c0d0e0f";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    let output = cmd.arg( byte_num.to_string() )
+        .arg( personalization )
+        .arg( "-k" )
+        .arg( "aes256" )
+        .arg( "-o" )
+        .arg( "drbg" )
+        .output()
+        .expect( "Failed to execute binary" );
+
+    let stdout = String::from_utf8( output.stdout ).expect( "Failed to convert output" );
+    let random_bytes = hex::decode( stdout.trim_end() ).expect( "Failed to convert random bytes" );
+
+    assert!( output.status.success() );
+    assert_eq!( random_bytes.len(), byte_num );
+    assert!( random_bytes.iter().any( | &byte | byte != 0 ) );
+}
+
+#[test]
+fn test_cli_keygen_aes192() {
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    let output = cmd.arg( "keygen" )
+        .arg( "-k" )
+        .arg( "aes192" )
+        .output()
+        .expect( "Failed to execute binary" );
+
+    let stdout = String::from_utf8( output.stdout ).expect( "Failed to convert output" );
+    let key = hex::decode( stdout.trim_end() ).expect( "Failed to convert key" );
+
+    assert!( output.status.success() );
+    assert_eq!( key.len(), 24 );
+}
+
+#[test]
+fn test_cli_aes128_cbc_random_iv() {
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    let output = cmd.arg( text )
+        .arg( key )
+        .arg( "-k" )
+        .arg( "aes128" )
+        .arg( "-o" )
+        .arg( "cbc-encrypt" )
+        .output()
+        .expect( "Failed to execute binary" );
+
+    // IV || cipher text with a full padding block
+    let cipher_text = String::from_utf8( output.stdout ).expect( "Failed to convert output" );
+    assert_eq!( cipher_text.trim_end().len(), 32 + text.len() + 32 );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( cipher_text.trim_end() )
+        .arg( key )
+        .arg( "-k" )
+        .arg( "aes128" )
+        .arg( "-o" )
+        .arg( "cbc-decrypt" )
+        .assert()
+        .success()
+        .stdout( format!( "{}\n", text ) );
+}
+
+#[test]
+fn test_cli_aes128_cbc_file() {
+
+    //NIST SP 800-38A F.2.1 CBC-AES128.Encrypt
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\n30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710\n";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c\n";
+    let iv = "000102030405060708090a0b0c0d0e0f";
+    let expect = "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b273bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a78cb82807230e1321d3fae00d18cc2012\n";
+
+    let dir = env::temp_dir().join( "aes_rustlang_test_cli_aes128_cbc_file" );
+    fs::create_dir_all( &dir ).expect( "Failed to create directory" );
+    fs::write( dir.join( "plain.hex" ), text ).expect( "Failed to write plain text" );
+    fs::write( dir.join( "key.hex" ), key ).expect( "Failed to write key" );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "--in" )
+        .arg( dir.join( "plain.hex" ) )
+        .arg( "--out" )
+        .arg( dir.join( "cipher.hex" ) )
+        .arg( "--key-file" )
+        .arg( dir.join( "key.hex" ) )
+        .arg( "-o" )
+        .arg( "cbc-encrypt" )
+        .arg( "-i" )
+        .arg( iv )
+        .assert()
+        .success()
+        .stdout( "" );
+
+    assert_eq!( fs::read_to_string( dir.join( "cipher.hex" ) ).expect( "Failed to read cipher text" ), expect );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "--in" )
+        .arg( dir.join( "cipher.hex" ) )
+        .arg( key.trim_end() )
+        .arg( "-o" )
+        .arg( "cbc-decrypt" )
+        .arg( "-i" )
+        .arg( iv )
+        .assert()
+        .success()
+        .stdout( text.replace( "\n", "" ) + "\n" );
+
+    fs::remove_dir_all( &dir ).expect( "Failed to remove directory" );
+}
+
+#[test]
+fn test_cli_aes128_cmac_stdin() {
+
+    //NIST Special Publication 800-38B Appendix D: Examples
+    //CMAC-AES128 Example #2 (16 bytes)
+    let text = "6bc1bee22e409f96e93d7e117393172a\n";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let expect = "070a16b46b4d4144f79bdd9dd04a287c\n";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "-" )
+        .arg( "-o" )
+        .arg( "cmac" )
+        .env( "AES_RUSTLANG_KEY", key )
+        .with_stdin()
+        .buffer( text )
+        .assert()
+        .success()
+        .stdout( expect );
+}
+
+#[test]
+fn test_cli_no_key() {
+    let text = "00112233445566778899aabbccddeeff";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( text )
+        .env_remove( "AES_RUSTLANG_KEY" )
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_aes128_ecb_utf8_base64() {
+    let text = "Hello, AES";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    let output = cmd.arg( text )
+        .arg( key )
+        .arg( "-o" )
+        .arg( "ecb-encrypt" )
+        .arg( "--input-format" )
+        .arg( "utf8" )
+        .arg( "--output-format" )
+        .arg( "base64" )
+        .output()
+        .expect( "Failed to execute binary" );
+
+    let cipher_text = String::from_utf8( output.stdout ).expect( "Failed to convert output" );
+    assert_eq!( cipher_text.trim_end().len(), 24 );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    // no newline is added to UTF-8 output
+    cmd.arg( cipher_text.trim_end() )
+        .arg( key )
+        .arg( "-o" )
+        .arg( "ecb-decrypt" )
+        .arg( "--input-format" )
+        .arg( "base64" )
+        .arg( "--output-format" )
+        .arg( "utf8" )
+        .assert()
+        .success()
+        .stdout( text );
+}
+
+#[test]
+fn test_cli_aes128_encrypt_raw() {
+
+    //FIPS 197 p35 C.1 AES-128 (Nk=4, Nr=10)
+    let text : Vec<u8> = ( 0..16 ).map( | i | i * 0x11 ).collect();
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let expect : &[u8] = b"\x69\xc4\xe0\xd8\x6a\x7b\x04\x30\xd8\xcd\xb7\x80\x70\xb4\xc5\x5a";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "-" )
+        .arg( key )
+        .arg( "--input-format" )
+        .arg( "raw" )
+        .arg( "--output-format" )
+        .arg( "raw" )
+        .with_stdin()
+        .buffer( text )
+        .assert()
+        .success()
+        .stdout( expect );
+}
+
+#[test]
+fn test_cli_encrypt_aes256_cbc() {
+
+    //NIST SP 800-38A F.2.5 CBC-AES256.Encrypt
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+    let key = "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+    let iv = "000102030405060708090a0b0c0d0e0f";
+    let expect = "f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    let output = cmd.arg( "encrypt" )
+        .arg( text )
+        .arg( key )
+        .arg( "-k" )
+        .arg( "aes256" )
+        .arg( "--iv" )
+        .arg( iv )
+        .output()
+        .expect( "Failed to execute binary" );
+
+    let cipher_text = String::from_utf8( output.stdout ).expect( "Failed to convert output" );
+    assert!( cipher_text.starts_with( expect ) );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "decrypt" )
+        .arg( cipher_text.trim_end() )
+        .arg( key )
+        .arg( "-k" )
+        .arg( "aes256" )
+        .arg( "--iv" )
+        .arg( iv )
+        .assert()
+        .success()
+        .stdout( format!( "{}\n", text ) );
+}
+
+#[test]
+fn test_cli_encrypt_ecb_with_iv() {
+    let text = "00112233445566778899aabbccddeeff";
+    let key = "000102030405060708090a0b0c0d0e0f";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "encrypt" )
+        .arg( text )
+        .arg( key )
+        .arg( "--mode" )
+        .arg( "ecb" )
+        .arg( "--iv" )
+        .arg( key )
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_mac_verify_pmac() {
+
+    //PMAC1 test vectors, PMAC-AES-128
+    let text = "000102030405060708090a0b0c0d0e0f10111213";
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let expect = "0412ca150bbf79058d8c75a58c993f55";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "mac" )
+        .arg( text )
+        .arg( key )
+        .arg( "--algorithm" )
+        .arg( "pmac" )
+        .assert()
+        .success()
+        .stdout( format!( "{}\n", expect ) );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "verify" )
+        .arg( text )
+        .arg( key )
+        .arg( "--algorithm" )
+        .arg( "pmac" )
+        .arg( "--tag" )
+        .arg( expect )
+        .assert()
+        .success()
+        .stdout( "OK\n" );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "verify" )
+        .arg( text )
+        .arg( key )
+        .arg( "--tag" )
+        .arg( expect )
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_wrap_aes256() {
+
+    //RFC 3394 4.6 Wrap 256 bits of Key Data with a 256-bit KEK
+    let text = "00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f";
+    let key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    let expect = "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "wrap" )
+        .arg( text )
+        .arg( key )
+        .arg( "-k" )
+        .arg( "aes256" )
+        .assert()
+        .success()
+        .stdout( format!( "{}\n", expect ) );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "unwrap" )
+        .arg( expect )
+        .arg( key )
+        .arg( "-k" )
+        .arg( "aes256" )
+        .assert()
+        .success()
+        .stdout( format!( "{}\n", text ) );
+}
+
+#[test]
+fn test_cli_random() {
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    let output = cmd.arg( "random" )
+        .arg( "48" )
+        .arg( "--personalization" )
+        .arg( "0011" )
+        .output()
+        .expect( "Failed to execute binary" );
+
+    let stdout = String::from_utf8( output.stdout ).expect( "Failed to convert output" );
+
+    assert!( output.status.success() );
+    assert_eq!( hex::decode( stdout.trim_end() ).expect( "Failed to convert random bytes" ).len(), 48 );
+}
+
+#[test]
+fn test_cli_infer_key_size() {
+
+    //FIPS 197 Appendix C.3 AES-256 (Nk=8, Nr=14)
+    let text = "00112233445566778899aabbccddeeff";
+    let key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    let expect = "8ea2b7ca516745bfeafc49904b496089";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "encrypt" )
+        .arg( text )
+        .arg( key )
+        .arg( "--mode" )
+        .arg( "block" )
+        .assert()
+        .success()
+        .stdout( format!( "{}\n", expect ) );
+}
+
+#[test]
+fn test_cli_key_size_mismatch() {
+    let text = "00112233445566778899aabbccddeeff";
+    let key = ["000102030405060708090a0b0c0d0e0f", "0001020304050607"];
+    let key_length = [Some( "aes256" ), None];
+
+    for i in 0..key.len() {
+        let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+        cmd.arg( "encrypt" )
+            .arg( text )
+            .arg( key[i] );
+
+        if let Some( key_length ) = key_length[i] {
+            cmd.arg( "-k" ).arg( key_length );
+        }
+
+        cmd.assert()
+            .failure();
+    }
+}
+
+#[test]
+fn test_cli_wrong_key_padding() {
+
+    //SP 800-38A F.2.2 CBC-AES128.Decrypt(+PKCS#7 padding) with another key
+    let text = "7649abac8119b246cee98e9b12e9197d8cb82807230e1321d3fae00d18cc2012";
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let iv = "000102030405060708090a0b0c0d0e0f";
+
+    for mode in ["cbc", "ecb"] {
+        let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+        cmd.arg( "decrypt" )
+            .arg( text )
+            .arg( key )
+            .arg( "--mode" )
+            .arg( mode );
+
+        if mode == "cbc" {
+            cmd.arg( "--iv" ).arg( iv );
+        }
+
+        let output = cmd.assert()
+            .failure()
+            .code( 1 )
+            .get_output()
+            .clone();
+
+        assert!( String::from_utf8_lossy( &output.stderr ).contains( "Invalid padding" ) );
+    }
+}
+
+#[test]
+fn test_cli_block_mode_length() {
+    let key = "000102030405060708090a0b0c0d0e0f";
+
+    for ( command, text ) in [("encrypt", "0011"), ("decrypt", "0011"), ("encrypt", "00112233445566778899aabbccddeeff00")] {
+        let output = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" )
+            .arg( command )
+            .arg( text )
+            .arg( key )
+            .arg( "--mode" )
+            .arg( "block" )
+            .assert()
+            .failure()
+            .code( 1 )
+            .get_output()
+            .clone();
+
+        assert!( String::from_utf8_lossy( &output.stderr ).contains( "exactly one 16-byte block" ) );
+    }
+}
+
+#[test]
+fn test_cli_invalid_iv() {
+    let text = "00112233445566778899aabbccddeeff";
+    let key = "000102030405060708090a0b0c0d0e0f";
+
+    let iv = [("encrypt", "cbc", "00"),
+              ("decrypt", "cbc", "zz"),
+              ("encrypt", "cbc", "000102030405060708090a0b0c0d0e0f00"),
+              ("decrypt", "ofb", "0011")];
+
+    for ( command, mode, iv ) in iv {
+        let output = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" )
+            .arg( command )
+            .arg( text )
+            .arg( key )
+            .arg( "--mode" )
+            .arg( mode )
+            .arg( "--iv" )
+            .arg( iv )
+            .assert()
+            .failure()
+            .code( 1 )
+            .get_output()
+            .clone();
+
+        assert!( String::from_utf8_lossy( &output.stderr ).contains( "Initialization vector must be 16 bytes in hex" ) );
+    }
+}
+
+#[test]
+fn test_cli_seal_open() {
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+    let key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    let output = cmd.arg( "seal" )
+        .arg( text )
+        .arg( key )
+        .arg( "--aad" )
+        .arg( "0011" )
+        .arg( "--chunk-size" )
+        .arg( "16" )
+        .output()
+        .expect( "Failed to execute binary" );
+
+    assert!( output.status.success() );
+
+    let container = String::from_utf8( output.stdout ).expect( "Failed to convert output" );
+    let container = container.trim_end();
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "open" )
+        .arg( container )
+        .arg( key )
+        .arg( "--aad" )
+        .arg( "0011" )
+        .assert()
+        .success()
+        .stdout( format!( "{}\n", text ) );
+
+    // a flipped bit in the last tag, a missing last chunk and another AAD are all rejected
+    let mut tampered = container.to_string();
+    let last = if tampered.ends_with( '0' ) { "1" } else { "0" };
+    tampered.replace_range( ( tampered.len() - 1 ).., last );
+
+    let last_chunk_len = ( 5 + 16 + 16 ) * 2;
+    let truncated = &container[..( container.len() - last_chunk_len )];
+
+    let invalid_input = [( tampered.as_str(), "0011" ), ( truncated, "0011" ), ( container, "0012" )];
+
+    for ( container, aad ) in invalid_input {
+        let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+        cmd.arg( "open" )
+            .arg( container )
+            .arg( key )
+            .arg( "--aad" )
+            .arg( aad )
+            .assert()
+            .failure();
+    }
+}
+
+#[test]
+fn test_cli_stream_mode() {
+    let text = "Streaming AEAD keeps large files authenticated";
+    let key = "000102030405060708090a0b0c0d0e0f";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    let output = cmd.arg( "encrypt" )
+        .arg( text )
+        .arg( key )
+        .arg( "--mode" )
+        .arg( "stream" )
+        .arg( "--input-format" )
+        .arg( "utf8" )
+        .output()
+        .expect( "Failed to execute binary" );
+
+    assert!( output.status.success() );
+
+    let cipher_text = String::from_utf8( output.stdout ).expect( "Failed to convert output" );
+    let cipher_text = cipher_text.trim_end();
+
+    // 11-byte nonce prefix and a 16-byte tag
+    assert_eq!( cipher_text.len(), ( 11 + text.len() + 16 ) * 2 );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "decrypt" )
+        .arg( cipher_text )
+        .arg( key )
+        .arg( "--mode" )
+        .arg( "stream" )
+        .arg( "--output-format" )
+        .arg( "utf8" )
+        .assert()
+        .success()
+        .stdout( text );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "decrypt" )
+        .arg( &cipher_text[..( cipher_text.len() - 2 )] )
+        .arg( key )
+        .arg( "--mode" )
+        .arg( "stream" )
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_cfb_ofb_ctr_mode() {
+
+    //NIST SP 800-38A F.3.13, F.4.1 and F.5.1 with AES-128
+    let text = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+
+    let mode = ["cfb", "ofb", "ctr"];
+
+    let iv = ["000102030405060708090a0b0c0d0e0f",
+              "000102030405060708090a0b0c0d0e0f",
+              "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff"];
+
+    let expect = ["3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b",
+                  "3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed825",
+                  "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff"];
+
+    for i in 0..mode.len() {
+        let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+        cmd.arg( "encrypt" )
+            .arg( text )
+            .arg( key )
+            .arg( "--mode" )
+            .arg( mode[i] )
+            .arg( "--iv" )
+            .arg( iv[i] )
+            .assert()
+            .success()
+            .stdout( format!( "{}\n", expect[i] ) );
+
+        // without --iv, the IV is taken from the first block
+        let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+        cmd.arg( "decrypt" )
+            .arg( format!( "{}{}", iv[i], &expect[i][..40] ) )
+            .arg( key )
+            .arg( "--mode" )
+            .arg( mode[i] )
+            .assert()
+            .success()
+            .stdout( format!( "{}\n", &text[..40] ) );
+    }
+}
+
+#[test]
+fn test_cli_threads() {
+    let text : String = ( 0..4000 ).map( | i | format!( "{:02x}", ( i * 7 ) % 256 ) ).collect();
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let iv = "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff";
+
+    for mode in ["ecb", "cbc", "ctr"] {
+        let mut cipher_text = Vec::new();
+
+        // the same cipher text with any number of threads
+        for threads in ["1", "4"] {
+            let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+            cmd.arg( "encrypt" )
+                .arg( &text )
+                .arg( key )
+                .arg( "--mode" )
+                .arg( mode )
+                .arg( "--threads" )
+                .arg( threads );
+
+            if mode != "ecb" {
+                cmd.arg( "--iv" ).arg( iv );
+            }
+
+            let output = cmd.output().expect( "Failed to run binary" );
+            assert!( output.status.success() );
+            cipher_text.push( String::from_utf8( output.stdout ).unwrap() );
+        }
+
+        assert_eq!( cipher_text[0], cipher_text[1] );
+
+        let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+        cmd.arg( "decrypt" )
+            .arg( cipher_text[0].trim_end() )
+            .arg( key )
+            .arg( "--mode" )
+            .arg( mode )
+            .arg( "--threads" )
+            .arg( "4" );
+
+        if mode != "ecb" {
+            cmd.arg( "--iv" ).arg( iv );
+        }
+
+        cmd.assert()
+            .success()
+            .stdout( format!( "{}\n", text ) );
+    }
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+    cmd.arg( "mac" )
+        .arg( &text )
+        .arg( key )
+        .arg( "--algorithm" )
+        .arg( "pmac" )
+        .arg( "--threads" )
+        .arg( "0" )
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_trace() {
+    //FIPS 197 Appendix C.1 AES-128
+    let text = "00112233445566778899aabbccddeeff";
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let cipher_text = "69c4e0d86a7b0430d8cdb78070b4c55a";
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+    let output = cmd.arg( "encrypt" ).arg( text ).arg( key ).arg( "--trace" ).output().expect( "Failed to run binary" );
+    let stdout = String::from_utf8( output.stdout ).unwrap();
+    let lines : Vec<&str> = stdout.lines().collect();
+
+    assert!( output.status.success() );
+    assert_eq!( lines[..4], ["PLAINTEXT: 00112233445566778899aabbccddeeff",
+                             "KEY: 000102030405060708090a0b0c0d0e0f",
+                             "CIPHER (ENCRYPT):",
+                             "round[ 0].input   00112233445566778899aabbccddeeff"] );
+    assert!( lines.contains( &"round[ 1].s_box   63cab7040953d051cd60e0e7ba70e18c" ) );
+    assert_eq!( lines[lines.len() - 1], "round[10].output  69c4e0d86a7b0430d8cdb78070b4c55a" );
+
+    let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+    let output = cmd.arg( "decrypt" ).arg( cipher_text ).arg( key ).arg( "--trace" ).arg( "--mode" ).arg( "block" ).output().expect( "Failed to run binary" );
+    let stdout = String::from_utf8( output.stdout ).unwrap();
+    let lines : Vec<&str> = stdout.lines().collect();
+
+    assert!( output.status.success() );
+    assert_eq!( lines[2], "INVERSE CIPHER (DECRYPT):" );
+    assert!( lines.contains( &"round[ 1].ik_add  e9f74eec023020f61bf2ccf2353c21c7" ) );
+    assert_eq!( lines[lines.len() - 1], "round[10].ioutput 00112233445566778899aabbccddeeff" );
+
+    // a single block only
+    for args in [vec![text, key, "--mode", "cbc"], vec![&text[..30], key]] {
+        let mut cmd = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" );
+
+        cmd.arg( "encrypt" )
+            .args( &args )
+            .arg( "--trace" )
+            .assert()
+            .failure();
+    }
+}
+#[test]
+fn test_cli_chunked_and_short_stdin() {
+
+    //NIST SP 800-38A F.3.13, F.4.1 and F.5.1 with AES-128, the IV prepended to the first cipher block
+    let key = "2b7e151628aed2a6abf7158809cf4f3c";
+    let expect = "6bc1bee22e409f96e93d7e117393172a\n";
+
+    let mode = ["cfb", "ofb", "ctr"];
+
+    let cipher_text = ["000102030405060708090a0b0c0d0e0f3b3fd92eb72dad20333449f8e83cfb4a\n",
+                       "000102030405060708090a0b0c0d0e0f3b3fd92eb72dad20333449f8e83cfb4a\n",
+                       "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff874d6191b620e3261bef6864990db6ce\n"];
+
+    for i in 0..mode.len() {
+        let mut child = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" )
+            .arg( "decrypt" )
+            .arg( "-" )
+            .arg( key )
+            .arg( "--mode" )
+            .arg( mode[i] )
+            .stdin( Stdio::piped() )
+            .stdout( Stdio::piped() )
+            .stderr( Stdio::piped() )
+            .spawn()
+            .expect( "Failed to spawn binary" );
+
+        // the first read returns only a part of the IV
+        let mut stdin = child.stdin.take().expect( "Failed to get stdin" );
+        stdin.write_all( &cipher_text[i].as_bytes()[..8] ).expect( "Failed to write stdin" );
+        stdin.flush().expect( "Failed to flush stdin" );
+        thread::sleep( Duration::from_millis( 200 ) );
+        stdin.write_all( &cipher_text[i].as_bytes()[8..] ).expect( "Failed to write stdin" );
+        drop( stdin );
+
+        let output = wait_with_timeout( child );
+        assert!( output.status.success() );
+        assert_eq!( String::from_utf8_lossy( &output.stdout ), expect );
+    }
+
+    // shorter than the IV
+    for mode in ["cbc", "ctr", "cfb", "ofb"] {
+        let mut child = Command::cargo_bin( env!( "CARGO_PKG_NAME" ) ).expect( "Failed to get binary" )
+            .arg( "decrypt" )
+            .arg( "-" )
+            .arg( key )
+            .arg( "--mode" )
+            .arg( mode )
+            .stdin( Stdio::piped() )
+            .stdout( Stdio::piped() )
+            .stderr( Stdio::piped() )
+            .spawn()
+            .expect( "Failed to spawn binary" );
+
+        child.stdin.take().expect( "Failed to get stdin" ).write_all( b"0011223344" ).expect( "Failed to write stdin" );
+
+        let output = wait_with_timeout( child );
+        assert!( !output.status.success() );
+        assert!( String::from_utf8_lossy( &output.stderr ).contains( "too short" ) );
+    }
+}
+
+// the output of the child, failing instead of hanging when it does not exit
+fn wait_with_timeout( mut child : Child ) -> Output {
+    for _ in 0..100 {
+        if child.try_wait().expect( "Failed to wait for binary" ).is_some() {
+            return child.wait_with_output().expect( "Failed to get output" );
+        }
+
+        thread::sleep( Duration::from_millis( 100 ) );
+    }
+
+    child.kill().expect( "Failed to kill binary" );
+    panic!( "The binary did not exit" );
 }
\ No newline at end of file